@@ -2,32 +2,49 @@
 use super::*;
 use colored::*;
 use core::fmt::Debug;
+use std::sync::LazyLock;
 
 const GRASS_DELTA: u8 = 4; // amount by which grass grows each tick
 const INITIAL_HEIGHT: u8 = 48;
 const SPREAD_HEIGHT: u8 = 48;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 struct Grass {
     height: u8,
 }
-register_type!(Grass);
+register_object!(
+    Grass,
+    clone,
+    traits = [Action, Render, Fodder],
+    repeated_traits = [Debug]
+);
 
-pub fn add_grass(world: &mut World, store: &Store, loc: Point) {
-    let mut component = Component::new("grass");
-    add_object!(
-        component,
+pub fn grass_archetype() -> Archetype {
+    let mut archetype = Archetype::new("grass");
+    add_archetype_object!(
+        archetype,
         Grass,
         Grass::new(INITIAL_HEIGHT),
         [Action, Render, Fodder],
         [Debug]
     );
-    world.add_front(store, loc, component);
+    archetype
+}
+
+/// New grass components are clones of this.
+fn new_grass() -> Component {
+    static GRASS: LazyLock<Component> = LazyLock::new(|| spawn_archetype("grass").unwrap());
+    GRASS.try_clone().unwrap()
+}
+
+pub fn add_grass(world: &mut World, store: &Store, loc: Point) {
+    world.add_front(store, loc, new_grass());
 }
 
 pub fn spread_grass(world: &mut World, store: &Store, loc: Point) {
-    let mut component = Component::new("grass");
-    add_object!(component, Grass, Grass::new(1), [Action, Render, Fodder]);
+    let component = new_grass();
+    find_object_mut!(component, Grass).unwrap().height = 1;
     world.add_front(store, loc, component);
 }
 
@@ -2,16 +2,28 @@ use super::*;
 use core::fmt::Debug;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Hungers {
     hunger: i32, // [0, max_hunger]
+    initial: i32,
     max_hunger: i32,
 }
-register_type!(Hungers);
+register_object!(Hungers, traits = [Hunger], repeated_traits = [Debug]);
+
+pub fn register_hungers_factory() {
+    register_factory!(
+        Hungers,
+        |params| Ok(Hungers::new(params.get("initial")?, params.get("max")?)),
+        [Hunger],
+        [Debug]
+    );
+}
 
 impl Hungers {
     pub fn new(initial: i32, max: i32) -> Hungers {
         Hungers {
             hunger: initial,
+            initial,
             max_hunger: max,
         }
     }
@@ -22,10 +34,12 @@ impl Hunger for Hungers {
         self.hunger
     }
 
-    fn set(&mut self, value: i32) {
-        assert!(value >= 0);
-        assert!(value <= self.max_hunger);
-        self.hunger = value;
+    fn reset(&mut self) {
+        self.hunger = self.initial;
+    }
+
+    fn starving(&self) -> bool {
+        self.hunger == self.max_hunger
     }
 
     fn adjust(&mut self, delta: i32) {
@@ -1,7 +1,3 @@
-#![feature(lazy_cell)]
-#![feature(ptr_metadata)]
-#![feature(unsize)]
-
 use chrono::Utc;
 use clap::Parser;
 use gear_objects::*;
@@ -10,12 +6,23 @@ use rand::rngs::StdRng;
 use rand::Rng;
 use rand::{RngCore, SeedableRng};
 
+// Objects are saved in snapshots when the serde feature is enabled.
+macro_rules! register_object {
+    ($type:ident, $($options:tt)*) => {
+        #[cfg(feature = "serde")]
+        register_type!($type, serde, $($options)*);
+        #[cfg(not(feature = "serde"))]
+        register_type!($type, $($options)*);
+    };
+}
+
 mod grass;
 mod hungers;
 mod mover;
 mod point;
 mod rabbit;
 mod skeleton;
+mod species;
 mod store;
 mod traits;
 mod wolf;
@@ -27,6 +34,7 @@ use mover::*;
 use point::*;
 use rabbit::*;
 use skeleton::*;
+use species::*;
 use store::*;
 use traits::*;
 use wolf::*;
@@ -47,10 +55,19 @@ struct Args {
     #[clap(long, value_name = "COUNT", default_value_t = 12)]
     rabbits: i32,
 
+    /// Extra component to add by archetype name, e.g. wolf (may be repeated)
+    #[clap(long, value_name = "NAME")]
+    spawn: Vec<String>,
+
     /// Random number seed [default: random]
     #[clap(long, value_name = "NUM")]
     seed: Option<u64>,
 
+    /// Save the components to a snapshot file when the sim finishes
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "PATH")]
+    snapshot: Option<std::path::PathBuf>,
+
     /// Number of times to run the sim
     #[clap(long, value_name = "NUM", default_value_t = 10)]
     ticks: i32,
@@ -70,10 +87,8 @@ fn add_grass_patch(world: &mut World, store: &Store, center: Point, radius: i32)
         for dx in -radius..=radius {
             let x = center.x + dx;
             let loc = Point::new(x, y);
-            if world.distance2(center, loc) < radius {
-                if world.cell(loc).is_empty() {
-                    add_grass(world, store, loc);
-                }
+            if world.distance2(center, loc) < radius && world.cell(loc).is_empty() {
+                add_grass(world, store, loc);
             }
         }
     }
@@ -90,17 +105,19 @@ fn print_legend() {
     println!("New skeletons are red.");
 }
 
-// Sim is loosely based on http://www.shodor.org/interactivate/activities/RabbitsAndWolves
-// (there's not quite enough there to fully specify how the sim should behave).
-fn run_sim(options: Args) {
-    const WIDTH: i32 = 30;
-    const HEIGHT: i32 = 20;
+const WIDTH: i32 = 30;
+const HEIGHT: i32 = 20;
 
+fn create_sim(options: &Args) -> (World, Store) {
     let seed = options.seed.unwrap_or(Utc::now().timestamp_millis() as u64);
     let mut rng = StdRng::seed_from_u64(seed);
     let mut world = World::new(WIDTH, HEIGHT, Box::new(rng.clone()), options.verbose);
     let mut store = Store::new();
 
+    register_archetype(grass_archetype());
+    register_archetype(rabbit_archetype());
+    register_archetype(wolf_archetype());
+
     for _ in 0..options.grass {
         let radius: i32 = rng.gen_range(1..20);
         let center = Point::new(rng.gen_range(0..WIDTH), rng.gen_range(0..HEIGHT));
@@ -117,7 +134,23 @@ fn run_sim(options: Args) {
         add_wolf(&mut world, &store, loc);
     }
 
+    for name in options.spawn.iter() {
+        let Some(component) = spawn_archetype(name) else {
+            eprintln!("Unknown archetype '{name}'");
+            std::process::exit(1);
+        };
+        let loc = Point::new(rng.gen_range(0..WIDTH), rng.gen_range(0..HEIGHT));
+        world.add_back(&store, loc, component);
+    }
+
     store.sync();
+    (world, store)
+}
+
+// Sim is loosely based on http://www.shodor.org/interactivate/activities/RabbitsAndWolves
+// (there's not quite enough there to fully specify how the sim should behave).
+fn run_sim(options: Args) {
+    let (mut world, mut store) = create_sim(&options);
     world.render(&store);
     for _ in 0..options.ticks {
         world.step(&mut store);
@@ -126,6 +159,17 @@ fn run_sim(options: Args) {
             break;
         }
     }
+
+    #[cfg(feature = "serde")]
+    if let Some(path) = options.snapshot {
+        let result = std::fs::File::create(&path)
+            .map_err(SnapshotError::Io)
+            .and_then(|file| store.save(std::io::BufWriter::new(file)));
+        if let Err(err) = result {
+            eprintln!("Couldn't write {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() {
@@ -136,3 +180,27 @@ fn main() {
         run_sim(options);
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() {
+        let options = Args::parse_from(["sim", "--seed", "7"]);
+        let (mut world, mut store) = create_sim(&options);
+        for _ in 0..5 {
+            world.step(&mut store);
+        }
+
+        let mut bytes = Vec::new();
+        store.save(&mut bytes).unwrap();
+        let copy = Store::load(bytes.as_slice()).unwrap();
+
+        let mut expected = String::new();
+        world.render_to(&store, &mut expected);
+        let mut actual = String::new();
+        world.render_to(&copy, &mut actual);
+        assert_eq!(actual, expected);
+    }
+}
@@ -1,6 +1,7 @@
 //! Helper object for components that move around.
 use super::*;
 use rand::seq::IteratorRandom;
+use std::sync::{Arc, LazyLock};
 
 pub struct Mover {}
 register_type!(Mover);
@@ -11,6 +12,26 @@ impl Mover {
     }
 }
 
+/// Mover is stateless so all the animals share one via their prototype.
+pub fn mover_prototype() -> Arc<Component> {
+    static PROTOTYPE: LazyLock<Arc<Component>> = LazyLock::new(|| {
+        let mut component = Component::new("mover");
+        add_object!(component, Mover, Mover::new(), [Moveable]);
+        let prototype = Arc::new(component);
+        #[cfg(feature = "serde")]
+        register_prototype("mover", prototype.clone());
+        prototype
+    });
+    PROTOTYPE.clone()
+}
+
+/// Base archetype for animals.
+pub fn animal_archetype() -> Archetype {
+    let mut archetype = Archetype::new("animal");
+    archetype.add_step(|component| component.set_prototype(mover_prototype()));
+    archetype
+}
+
 impl Moveable for Mover {
     fn random_move<'a, 'b>(&self, context: &Context<'a, 'b>) -> Option<Point> {
         let neighbors = context.world.all(context.loc, 1, |pt| {
@@ -4,41 +4,40 @@ use colored::*;
 use core::fmt::Debug;
 use rand::seq::IteratorRandom;
 
-const VISION_RADIUS: i32 = 4; // rabbits don't have great vision
-
-const MAX_HUNGER: i32 = 45; // starves when hit this
-const INITAL_HUNGER: i32 = 25;
-const REPRO_HUNGER: i32 = 5;
-const EAT_DELTA: i32 = -9;
-const BASAL_DELTA: i32 = 3;
-
-const REPRO_AGE: i32 = 10;
-const MAX_AGE: i32 = 25;
-
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 struct Rabbit {
     age: i32,
+    species: Species,
 }
-register_type!(Rabbit);
+register_object!(
+    Rabbit,
+    traits = [Action, Animal, Prey, Render],
+    repeated_traits = [Debug]
+);
 
-pub fn add_rabbit(world: &mut World, store: &Store, loc: Point) -> ComponentId {
-    let mut component = Component::new("rabbit");
-    let id = component.id;
-    add_object!(
-        component,
+pub fn register_rabbit_factory() {
+    register_factory!(
         Rabbit,
-        Rabbit::new(),
+        |params| Ok(Rabbit::new(Species::from_params(params)?)),
         [Action, Animal, Prey, Render],
         [Debug]
     );
-    add_object!(component, Mover, Mover::new(), [Moveable]);
-    add_object!(
-        component,
-        Hungers,
-        Hungers::new(INITAL_HUNGER, MAX_HUNGER),
-        [Hunger],
-        [Debug]
-    );
+}
+
+pub fn rabbit_archetype() -> Archetype {
+    let mut archetype = animal_archetype().extend("rabbit");
+    archetype.add_step(|component| {
+        species_prefab("rabbit").add_to(component).unwrap(); // Rabbit is in the alive state
+        add_skeleton_state(component, "dead");
+    });
+    archetype.add_step(|component| component.set_state("alive").unwrap());
+    archetype
+}
+
+pub fn add_rabbit(world: &mut World, store: &Store, loc: Point) -> ComponentId {
+    let component = spawn_archetype("rabbit").unwrap();
+    let id = component.id;
     world.add_back(store, loc, component);
     id
 }
@@ -55,10 +54,8 @@ pub fn find_empty_cell(world: &World, store: &Store, loc: Point) -> Option<Point
     for dy in -1..=1 {
         for dx in -1..=1 {
             let candidate = Point::new(loc.x + dx, loc.y + dy);
-            if candidate != loc {
-                if !has_animal(world, store, candidate) {
-                    candidates.push(candidate);
-                }
+            if candidate != loc && !has_animal(world, store, candidate) {
+                candidates.push(candidate);
             }
         }
     }
@@ -77,10 +74,10 @@ fn predator_nearby<'a, 'b>(context: &Context<'a, 'b>) -> bool {
     for dy in -1..=1 {
         for dx in -1..=1 {
             let candidate = Point::new(context.loc.x + dx, context.loc.y + dy);
-            if candidate != context.loc {
-                if find_predator(context.world, context.store, candidate).is_some() {
-                    return true;
-                }
+            if candidate != context.loc
+                && find_predator(context.world, context.store, candidate).is_some()
+            {
+                return true;
             }
         }
     }
@@ -88,8 +85,8 @@ fn predator_nearby<'a, 'b>(context: &Context<'a, 'b>) -> bool {
 }
 
 impl Rabbit {
-    pub fn new() -> Rabbit {
-        Rabbit { age: 0 }
+    pub fn new(species: Species) -> Rabbit {
+        Rabbit { age: 0, species }
     }
 
     fn find_grass<'a, 'b>(&self, context: &Context<'a, 'b>) -> Option<ComponentId> {
@@ -105,7 +102,7 @@ impl Rabbit {
         let mut dst = None;
         let mut dist = 0; // want to maximize distance from all visible wolves
 
-        let wolves = context.world.all(context.loc, VISION_RADIUS, |pt| {
+        let wolves = context.world.all(context.loc, self.species.vision, |pt| {
             context
                 .world
                 .cell(pt)
@@ -116,16 +113,15 @@ impl Rabbit {
         for dy in -1..=1 {
             for dx in -1..=1 {
                 let candidate = Point::new(context.loc.x + dx, context.loc.y + dy);
-                if candidate != context.loc {
-                    if !has_animal(context.world, context.store, candidate) {
-                        let d = wolves
-                            .iter()
-                            .map(|pt| context.world.distance2(*pt, candidate))
-                            .sum();
-                        if d > dist {
-                            dst = Some(candidate);
-                            dist = d;
-                        }
+                if candidate != context.loc && !has_animal(context.world, context.store, candidate)
+                {
+                    let d = wolves
+                        .iter()
+                        .map(|pt| context.world.distance2(*pt, candidate))
+                        .sum();
+                    if d > dist {
+                        dst = Some(candidate);
+                        dist = d;
                     }
                 }
             }
@@ -139,7 +135,7 @@ impl Rabbit {
         let mut dist = i32::MAX;
         let mut height = 0;
 
-        for neighbor in context.world.all(context.loc, VISION_RADIUS, |pt| {
+        for neighbor in context.world.all(context.loc, self.species.vision, |pt| {
             context
                 .world
                 .cell(pt)
@@ -194,22 +190,22 @@ impl Action for Rabbit {
         self.age += 1;
 
         // Rabbits can die of old age.
-        if self.age >= MAX_AGE {
+        if self.age >= self.species.max_age {
             self.log(&context, "died of old age");
-            add_skeleton(context.world, context.store, context.loc);
-            return LifeCycle::Dead;
+            context.store.set_state(context.id, "dead");
+            return LifeCycle::Alive;
         }
 
         // If we're not hungry then reproduce.
         let component = context.store.get(context.id);
         let mut hunger = find_trait_mut!(component, Hunger).unwrap();
-        if hunger.get() <= REPRO_HUNGER
-            && self.age >= REPRO_AGE
+        if hunger.get() <= self.species.repro_hunger
+            && self.age >= self.species.repro_age
             && !predator_nearby(&context)
             && context.world.rng().gen_bool(0.5)
         {
             if let Some(neighbor) = find_empty_cell(context.world, context.store, context.loc) {
-                hunger.set(INITAL_HUNGER);
+                hunger.reset();
                 let new_id = add_rabbit(context.world, context.store, neighbor);
                 self.log(
                     &context,
@@ -232,7 +228,7 @@ impl Action for Rabbit {
 
         // If we're hungry and there is grass in the cell then eat it.
         if let Some(grass_id) = self.find_grass(&context) {
-            hunger.adjust(EAT_DELTA);
+            hunger.adjust(self.species.eat_delta);
             self.log(&context, "ate grass");
             let new_context = Context {
                 id: grass_id,
@@ -243,11 +239,11 @@ impl Action for Rabbit {
             fodder.eat(new_context, 25); // grass may die here
             return LifeCycle::Alive;
         } else {
-            hunger.adjust(BASAL_DELTA);
-            if hunger.get() == MAX_HUNGER {
+            hunger.adjust(self.species.basal_delta);
+            if hunger.starving() {
                 self.log(&context, "starved to death");
-                add_skeleton(context.world, context.store, context.loc);
-                return LifeCycle::Dead;
+                context.store.set_state(context.id, "dead");
+                return LifeCycle::Alive;
             }
         }
 
@@ -6,21 +6,26 @@ use core::fmt::Debug;
 const MAX_LIFETIME: i32 = 4;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 struct Skeleton {
     lifetime: i32,
 }
-register_type!(Skeleton);
+register_object!(
+    Skeleton,
+    traits = [Action, Render],
+    repeated_traits = [Debug]
+);
 
-pub fn add_skeleton(world: &mut World, store: &Store, loc: Point) {
-    let mut component = Component::new("skeleton");
-    add_object!(
+/// Allows an animal to turn into a skeleton by switching its component to the state.
+pub fn add_skeleton_state(component: &mut Component, state: &str) {
+    add_state_object!(
         component,
+        state,
         Skeleton,
         Skeleton::new(),
         [Action, Render],
         [Debug]
     );
-    world.add_back(store, loc, component);
 }
 
 impl Skeleton {
@@ -0,0 +1,50 @@
+//! Tuning for the animals. This is defined in species.prefab so that it can be tweaked
+//! without touching the code.
+use super::*;
+use std::sync::LazyLock;
+
+/// Parameters shared by all the animals of a species.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Species {
+    pub vision: i32, // radius
+    pub repro_hunger: i32,
+    pub eat_delta: i32,
+    pub basal_delta: i32,
+    pub repro_age: i32,
+    pub max_age: i32,
+}
+
+impl Species {
+    pub fn from_params(params: &PrefabParams) -> Result<Species, PrefabError> {
+        Ok(Species {
+            vision: params.get("vision")?,
+            repro_hunger: params.get("repro_hunger")?,
+            eat_delta: params.get("eat_delta")?,
+            basal_delta: params.get("basal_delta")?,
+            repro_age: params.get("repro_age")?,
+            max_age: params.get("max_age")?,
+        })
+    }
+}
+
+static PREFABS: LazyLock<Vec<Prefab>> = LazyLock::new(|| {
+    register_hungers_factory();
+    register_rabbit_factory();
+    register_wolf_factory();
+    match parse_prefabs("species.prefab", include_str!("species.prefab")) {
+        Ok(prefabs) => prefabs,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+});
+
+/// Returns the prefab for a species, e.g. "rabbit".
+pub fn species_prefab(name: &str) -> &'static Prefab {
+    PREFABS
+        .iter()
+        .find(|prefab| prefab.name == name)
+        .unwrap_or_else(|| panic!("species.prefab is missing {name}"))
+}
@@ -1,26 +1,27 @@
 use super::*;
-use fnv::FnvHashMap;
 use std::cell::RefCell;
 
 /// Manages [`Component`]` lifetimes. This is broken out from [`World`] to avoid borrow
 /// checker issues.
 pub struct Store {
-    components: FnvHashMap<ComponentId, Component>,
+    components: ComponentStore,
     liverow: RefCell<Vec<Component>>,
     deathrow: RefCell<Vec<ComponentId>>,
+    staterow: RefCell<Vec<(ComponentId, &'static str)>>,
 }
 
 impl Store {
     pub fn new() -> Store {
         Store {
-            components: FnvHashMap::default(),
+            components: ComponentStore::new(),
             liverow: RefCell::new(Vec::new()),
             deathrow: RefCell::new(Vec::new()),
+            staterow: RefCell::new(Vec::new()),
         }
     }
 
     pub fn get(&self, id: ComponentId) -> &Component {
-        self.components.get(&id).unwrap()
+        self.components.get(id).unwrap()
     }
 
     pub fn add(&self, actor: Component) {
@@ -31,15 +32,40 @@ impl Store {
         self.deathrow.borrow_mut().push(id);
     }
 
+    /// Switches the component to a new state, see [`Component::set_state`].
+    pub fn set_state(&self, id: ComponentId, state: &'static str) {
+        self.staterow.borrow_mut().push((id, state));
+    }
+
+    /// Saves all the components, see [`write_snapshot`].
+    #[cfg(feature = "serde")]
+    pub fn save(&self, writer: impl std::io::Write) -> Result<(), SnapshotError> {
+        assert!(self.liverow.borrow().is_empty()); // sync should have been called
+        write_snapshot(&self.components, writer)
+    }
+
+    /// Creates a store using components saved with [`Store::save`].
+    #[cfg(all(test, feature = "serde"))]
+    pub fn load(reader: impl std::io::Read) -> Result<Store, SnapshotError> {
+        let mut store = Store::new();
+        store.components = read_snapshot(reader)?;
+        Ok(store)
+    }
+
     /// Called after each component has had a chance to execute.
     pub fn sync(&mut self) {
         for component in self.liverow.take() {
-            let old = self.components.insert(component.id, component);
+            let old = self.components.insert(component);
             assert!(old.is_none());
         }
 
+        for (id, state) in self.staterow.take() {
+            let component = self.components.get_mut(id).unwrap();
+            component.set_state(state).unwrap();
+        }
+
         for id in self.deathrow.take() {
-            let old = self.components.remove(&id);
+            let old = self.components.remove(id);
             assert!(old.is_some());
         }
     }
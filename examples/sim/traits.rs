@@ -34,7 +34,8 @@ register_type!(Render);
 /// Helper interface for something that gets hungry.
 pub trait Hunger {
     fn get(&self) -> i32;
-    fn set(&mut self, value: i32);
+    fn reset(&mut self); // back to the initial hunger
+    fn starving(&self) -> bool;
     fn adjust(&mut self, delta: i32);
 }
 register_type!(Hunger);
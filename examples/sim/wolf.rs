@@ -4,41 +4,40 @@ use colored::*;
 use core::fmt::Debug;
 use rand::seq::IteratorRandom;
 
-const VISION_RADIUS: i32 = 8; // wolves see quite a bit better than rabbits
-
-const MAX_HUNGER: i32 = 200; // starves when hit this
-const INITAL_HUNGER: i32 = 120;
-const REPRO_HUNGER: i32 = 80;
-const EAT_DELTA: i32 = -20;
-const BASAL_DELTA: i32 = 2;
-
-const REPRO_AGE: i32 = 10;
-const MAX_AGE: i32 = 50;
-
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 struct Wolf {
     age: i32,
+    species: Species,
 }
-register_type!(Wolf);
-
-pub fn add_wolf(world: &mut World, store: &Store, loc: Point) -> ComponentId {
-    let mut component = Component::new("wolf");
-    let id = component.id;
-    add_object!(
-        component,
+register_object!(
+    Wolf,
+    traits = [Action, Animal, Predator, Render],
+    repeated_traits = [Debug]
+);
+
+pub fn register_wolf_factory() {
+    register_factory!(
         Wolf,
-        Wolf::new(),
+        |params| Ok(Wolf::new(Species::from_params(params)?)),
         [Action, Animal, Predator, Render],
         [Debug]
     );
-    add_object!(component, Mover, Mover::new(), [Moveable]);
-    add_object!(
-        component,
-        Hungers,
-        Hungers::new(INITAL_HUNGER, MAX_HUNGER),
-        [Hunger],
-        [Debug]
-    );
+}
+
+pub fn wolf_archetype() -> Archetype {
+    let mut archetype = animal_archetype().extend("wolf");
+    archetype.add_step(|component| {
+        species_prefab("wolf").add_to(component).unwrap(); // Wolf is in the alive state
+        add_skeleton_state(component, "dead");
+    });
+    archetype.add_step(|component| component.set_state("alive").unwrap());
+    archetype
+}
+
+pub fn add_wolf(world: &mut World, store: &Store, loc: Point) -> ComponentId {
+    let component = spawn_archetype("wolf").unwrap();
+    let id = component.id;
     world.add_back(store, loc, component);
     id
 }
@@ -70,15 +69,15 @@ fn find_prey_cell<'a, 'b>(context: &Context<'a, 'b>) -> Option<(Point, Component
 }
 
 impl Wolf {
-    pub fn new() -> Wolf {
-        Wolf { age: 0 }
+    pub fn new(species: Species) -> Wolf {
+        Wolf { age: 0, species }
     }
 
     fn move_towards_prey<'a, 'b>(&self, context: &Context<'a, 'b>) -> Option<Point> {
         let mut dst = None;
         let mut dist = i32::MAX;
 
-        for neighbor in context.world.all(context.loc, VISION_RADIUS, |pt| {
+        for neighbor in context.world.all(context.loc, self.species.vision, |pt| {
             context
                 .world
                 .cell(pt)
@@ -115,21 +114,21 @@ impl Action for Wolf {
         self.age += 1;
 
         // Wolves can die of old age.
-        if self.age >= MAX_AGE {
+        if self.age >= self.species.max_age {
             self.log(&context, "died of old age");
-            add_skeleton(context.world, context.store, context.loc);
-            return LifeCycle::Dead;
+            context.store.set_state(context.id, "dead");
+            return LifeCycle::Alive;
         }
 
         // If we're not hungry then reproduce.
         let component = context.store.get(context.id);
         let mut hunger = find_trait_mut!(component, Hunger).unwrap();
-        if hunger.get() <= REPRO_HUNGER
-            && self.age >= REPRO_AGE
+        if hunger.get() <= self.species.repro_hunger
+            && self.age >= self.species.repro_age
             && context.world.rng().gen_bool(0.5)
         {
             if let Some(neighbor) = find_empty_cell(context.world, context.store, context.loc) {
-                hunger.set(INITAL_HUNGER);
+                hunger.reset();
                 let new_id = add_wolf(context.world, context.store, neighbor);
                 self.log(
                     &context,
@@ -141,16 +140,16 @@ impl Action for Wolf {
 
         // if we're hungry and there is prey nearby then eat it
         if let Some((neighbor, prey_id)) = find_prey_cell(&context) {
-            hunger.adjust(EAT_DELTA);
+            hunger.adjust(self.species.eat_delta);
             context.world.remove(context.store, prey_id, neighbor);
             self.log(&context, &format!("ate rabbit{prey_id} at {neighbor}"));
             return LifeCycle::Alive;
         } else {
-            hunger.adjust(BASAL_DELTA);
-            if hunger.get() == MAX_HUNGER {
+            hunger.adjust(self.species.basal_delta);
+            if hunger.starving() {
                 self.log(&context, "starved to death");
-                add_skeleton(context.world, context.store, context.loc);
-                return LifeCycle::Dead;
+                context.store.set_state(context.id, "dead");
+                return LifeCycle::Alive;
             }
         }
 
@@ -3,6 +3,7 @@ use colored::*;
 use fnv::FnvHashMap;
 use rand::seq::SliceRandom;
 use std::cell::{RefCell, RefMut};
+use std::fmt::Write;
 
 /// Handles all the global object state except for Component lifetimes.
 pub struct World {
@@ -30,14 +31,14 @@ impl World {
         }
     }
 
-    pub fn rng(&self) -> RefMut<Box<dyn RngCore>> {
+    pub fn rng(&self) -> RefMut<'_, Box<dyn RngCore>> {
         self.rng.borrow_mut()
     }
 
     /// Note that the world is a toroid so locations wrap around.
     pub fn cell(&self, loc: Point) -> &Vec<ComponentId> {
         let loc = self.wrap(loc);
-        &self.actors.get(&loc).unwrap_or(&self.dummy)
+        self.actors.get(&loc).unwrap_or(&self.dummy)
     }
 
     /// Use this for components that should always be rendered.
@@ -130,12 +131,11 @@ impl World {
         }
         self.pending[..].shuffle(self.rng.borrow_mut().as_mut());
 
-        while !self.pending.is_empty() {
-            let (loc, id) = self.pending.pop().unwrap();
+        while let Some((loc, id)) = self.pending.pop() {
             {
                 let context = Context {
                     world: self,
-                    store: &store,
+                    store,
                     loc,
                     id,
                 };
@@ -156,38 +156,46 @@ impl World {
 
     /// Render all cells to the terminal.
     pub fn render(&self, store: &Store) -> LifeCycle {
+        let mut text = String::new();
+        let cycle = self.render_to(store, &mut text);
+        print!("{text}");
+        cycle
+    }
+
+    /// Render all cells to a string.
+    pub fn render_to(&self, store: &Store, out: &mut String) -> LifeCycle {
         let mut cycle = LifeCycle::Dead;
 
-        println!("ticks: {}", self.ticks);
+        writeln!(out, "ticks: {}", self.ticks).unwrap();
         if self.verbose >= 1 {
-            print!("  ");
+            write!(out, "  ").unwrap();
             for x in 0..self.width {
-                print!("{}", x % 10);
+                write!(out, "{}", x % 10).unwrap();
             }
-            println!();
+            writeln!(out).unwrap();
         }
         for y in 0..self.height {
             if self.verbose >= 1 {
-                print!("{} ", y % 10);
+                write!(out, "{} ", y % 10).unwrap();
             }
             for x in 0..self.width {
                 let loc = Point::new(x, y);
-                if let Some(id) = self.actors.get(&loc).map(|v| v.last()).flatten() {
+                if let Some(id) = self.actors.get(&loc).and_then(|v| v.last()) {
                     let component = store.get(*id);
                     let render = find_trait!(component, Render).unwrap();
                     let ch = render.render();
                     if ch != "|".normal() && ch != " ".normal() {
                         cycle = LifeCycle::Alive;
                     }
-                    print!("{}", ch);
+                    write!(out, "{}", ch).unwrap();
                 } else {
-                    print!(" ");
+                    write!(out, " ").unwrap();
                 }
             }
-            println!();
+            writeln!(out).unwrap();
         }
-        println!();
-        println!("{}", "-".repeat(self.width as usize));
+        writeln!(out).unwrap();
+        writeln!(out, "{}", "-".repeat(self.width as usize)).unwrap();
         cycle
     }
 
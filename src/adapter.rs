@@ -0,0 +1,685 @@
+use super::*;
+use fnv::FnvHashMap;
+use std::any::Any;
+use std::marker::Unsize;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{DynMetadata, Pointee};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use type_erased_ptr::*;
+
+/// Used by adapters to access the trait they adapt. See [`register_adapter`]. Panics if
+/// used after the borrow that created the adapter has ended or if mutably dereferenced by
+/// an adapter that was borrowed immutably.
+pub struct Adapted<Trait>
+where
+    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+{
+    trait_ptr: *mut Trait,
+    borrow: Arc<AtomicU8>, // RELEASED, SHARED, or EXCLUSIVE
+}
+
+const RELEASED: u8 = 0;
+const SHARED: u8 = 1;
+const EXCLUSIVE: u8 = 2;
+
+impl<Trait> Deref for Adapted<Trait>
+where
+    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+{
+    type Target = Trait;
+
+    fn deref(&self) -> &Trait {
+        assert!(
+            self.borrow.load(Ordering::Acquire) != RELEASED,
+            "adapted trait was used after its borrow ended"
+        );
+        unsafe { &*self.trait_ptr }
+    }
+}
+
+// Adapters can move their Adapted out of themselves (e.g. with mem::swap) so the borrow
+// state is checked instead of assuming that the Adapted lives as long as the borrow.
+impl<Trait> DerefMut for Adapted<Trait>
+where
+    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Trait {
+        assert!(
+            self.borrow.load(Ordering::Acquire) == EXCLUSIVE,
+            "adapted trait was mutated without a mutable borrow"
+        );
+        unsafe { &mut *self.trait_ptr }
+    }
+}
+
+// An adapter created for a borrow of the adapted object. Owned by RefTrait and RefMutTrait
+// which drop it before releasing the borrow.
+pub(crate) struct Adapter {
+    object: Option<Box<dyn Any>>,
+    borrow: Arc<AtomicU8>,
+}
+
+impl Adapter {
+    // Returns the adapter along with a pointer to its trait.
+    pub(crate) fn new(
+        source: &TypeErasedPointer,
+        adapt: AdaptFn,
+        mutable: bool,
+    ) -> (Adapter, TypeErasedPointer) {
+        let borrow = Arc::new(AtomicU8::new(if mutable { EXCLUSIVE } else { SHARED }));
+        let (object, erased) = adapt(source, &borrow);
+        let adapter = Adapter {
+            object: Some(object),
+            borrow,
+        };
+        (adapter, erased)
+    }
+}
+
+impl Drop for Adapter {
+    fn drop(&mut self) {
+        self.object = None; // the adapter's drop may still use its Adapted
+        self.borrow.store(RELEASED, Ordering::Release);
+    }
+}
+
+// Returns the adapter along with a pointer to its trait.
+pub(crate) type AdaptFn =
+    fn(&TypeErasedPointer, &Arc<AtomicU8>) -> (Box<dyn Any>, TypeErasedPointer);
+
+// target trait id => [(source trait id, adapt function)]. The lists are shared so that
+// lookups can return them without allocating.
+type Adapters = FnvHashMap<TypeId, Arc<[(TypeId, AdaptFn)]>>;
+
+static ADAPTERS: LazyLock<RwLock<Adapters>> = LazyLock::new(|| RwLock::new(Adapters::default()));
+
+// Allows lookups to skip the lock in the common case where there are no adapters.
+static HAS_ADAPTERS: AtomicBool = AtomicBool::new(false);
+
+fn adapt<Source, Target, Adapter>(
+    source: &TypeErasedPointer,
+    borrow: &Arc<AtomicU8>,
+) -> (Box<dyn Any>, TypeErasedPointer)
+where
+    Source: ?Sized + Pointee<Metadata = DynMetadata<Source>> + 'static,
+    Target: ?Sized + Pointee<Metadata = DynMetadata<Target>> + 'static,
+    Adapter: From<Adapted<Source>> + Unsize<Target> + 'static,
+{
+    let adapted = Adapted {
+        trait_ptr: source.typed::<Source>(),
+        borrow: borrow.clone(),
+    };
+    let adapter_ptr = Box::into_raw(Box::new(Adapter::from(adapted)));
+    let erased = TypeErasedPointer::from_trait::<Adapter, Target>(source.object_id, adapter_ptr);
+    let adapter: Box<dyn Any> = unsafe { Box::from_raw(adapter_ptr) };
+    (adapter, erased)
+}
+
+// Converts a pointer to a subtrait into a pointer to one of its supertraits.
+#[doc(hidden)]
+pub fn upcast_trait<Sub, Super>(source: &TypeErasedPointer) -> TypeErasedPointer
+where
+    Sub: ?Sized + Pointee<Metadata = DynMetadata<Sub>> + Unsize<Super> + 'static,
+    Super: ?Sized + Pointee<Metadata = DynMetadata<Super>> + 'static,
+{
+    TypeErasedPointer::from_trait::<Sub, Super>(source.object_id, source.typed::<Sub>())
+}
+
+// Adapts by using trait upcasting.
+fn upcast<Source, Target>(
+    source: &TypeErasedPointer,
+    _borrow: &Arc<AtomicU8>,
+) -> (Box<dyn Any>, TypeErasedPointer)
+where
+    Source: ?Sized + Pointee<Metadata = DynMetadata<Source>> + Unsize<Target> + 'static,
+    Target: ?Sized + Pointee<Metadata = DynMetadata<Target>> + 'static,
+{
+    (Box::new(()), upcast_trait::<Source, Target>(source))
+}
+
+fn add_adapter(source_id: TypeId, target_id: TypeId, adapt: AdaptFn) {
+    let mut adapters = ADAPTERS.write().unwrap();
+    let mut sources: Vec<_> = adapters
+        .get(&target_id)
+        .into_iter()
+        .flat_map(|old| old.iter())
+        .filter(|(id, _)| *id != source_id)
+        .copied()
+        .collect();
+    sources.push((source_id, adapt));
+    adapters.insert(target_id, sources.into());
+    HAS_ADAPTERS.store(true, Ordering::Relaxed);
+}
+
+// Normally the [`register_adapter`]` macro would be used instead of calling this directly.
+#[doc(hidden)]
+pub fn register_adapter<Source, Target, Adapter>(source_id: TypeId, target_id: TypeId)
+where
+    Source: ?Sized + Pointee<Metadata = DynMetadata<Source>> + 'static,
+    Target: ?Sized + Pointee<Metadata = DynMetadata<Target>> + 'static,
+    Adapter: From<Adapted<Source>> + Unsize<Target> + 'static,
+{
+    add_adapter(source_id, target_id, adapt::<Source, Target, Adapter>);
+}
+
+// Normally the [`supersedes`]` macro would be used instead of calling this directly.
+#[doc(hidden)]
+pub fn register_supersedes<New, Old>(new_id: TypeId, old_id: TypeId)
+where
+    New: ?Sized + Pointee<Metadata = DynMetadata<New>> + Unsize<Old> + 'static,
+    Old: ?Sized + Pointee<Metadata = DynMetadata<Old>> + 'static,
+{
+    check_versions(new_id, old_id);
+    add_adapter(new_id, old_id, upcast::<New, Old>);
+}
+
+#[doc(hidden)]
+pub fn check_versions(new_id: TypeId, old_id: TypeId) {
+    let new = type_info(new_id).unwrap();
+    let old = type_info(old_id).unwrap();
+    assert!(
+        new.version > old.version,
+        "{} (version {}) cannot supersede {} (version {})",
+        new.name,
+        new.version,
+        old.name,
+        old.version
+    );
+}
+
+// Returns (target trait id, source trait id) for all the registered adapters.
+pub(crate) fn all_adapters() -> Vec<(TypeId, TypeId)> {
+    if HAS_ADAPTERS.load(Ordering::Relaxed) {
+        let adapters = ADAPTERS.read().unwrap();
+        adapters
+            .iter()
+            .flat_map(|(target, sources)| sources.iter().map(|(source, _)| (*target, *source)))
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+// Returns the traits that can be adapted to target_id.
+pub(crate) fn find_adapters(target_id: TypeId) -> AdapterIter {
+    let sources = if HAS_ADAPTERS.load(Ordering::Relaxed) {
+        ADAPTERS.read().unwrap().get(&target_id).cloned()
+    } else {
+        None
+    };
+    AdapterIter { sources, index: 0 }
+}
+
+// Iterates over (source trait id, adapt function) while keeping the list alive.
+pub(crate) struct AdapterIter {
+    sources: Option<Arc<[(TypeId, AdaptFn)]>>,
+    index: usize,
+}
+
+impl Iterator for AdapterIter {
+    type Item = (TypeId, AdaptFn);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.sources.as_ref()?.get(self.index).copied()?;
+        self.index += 1;
+        Some(item)
+    }
+}
+
+/// Allows components that lack a trait to provide it by adapting another trait. The
+/// adapter is an object that implements the target trait and is constructed from an
+/// [`Adapted`] source trait. Finds of the target trait (including repeated and mutable
+/// finds) will then use the adapter if the component has the source trait but not the
+/// target trait. This can be used to evolve traits without changing existing objects.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Plant {
+///     fn size(&self) -> u8;
+///     fn shrink(&mut self, amount: u8);
+/// }
+/// register_type!(Plant);
+///
+/// trait Fodder {
+///     fn height(&self) -> u8;
+///     fn eat(&mut self);
+/// }
+/// register_type!(Fodder);
+///
+/// struct PlantFodder {
+///     plant: Adapted<dyn Plant>,
+/// }
+///
+/// impl From<Adapted<dyn Plant>> for PlantFodder {
+///     fn from(plant: Adapted<dyn Plant>) -> Self {
+///         PlantFodder { plant }
+///     }
+/// }
+///
+/// impl Fodder for PlantFodder {
+///     fn height(&self) -> u8 {
+///         self.plant.size()
+///     }
+///
+///     fn eat(&mut self) {
+///         self.plant.shrink(10);
+///     }
+/// }
+/// register_adapter!(Plant, Fodder, PlantFodder);
+///
+/// struct Clover {
+///     size: u8,
+/// }
+/// register_type!(Clover);
+///
+/// impl Plant for Clover {
+///     fn size(&self) -> u8 {
+///         self.size
+///     }
+///
+///     fn shrink(&mut self, amount: u8) {
+///         self.size -= amount;
+///     }
+/// }
+///
+/// let mut component = Component::new("clover");
+/// add_object!(component, Clover, Clover { size: 30 }, [Plant]);
+///
+/// find_trait_mut!(component, Fodder).unwrap().eat();
+/// assert_eq!(find_trait!(component, Fodder).unwrap().height(), 20);
+/// ```
+#[macro_export]
+macro_rules! register_adapter {
+    ($source:ty, $target:ty, $adapter:ty) => {{
+        paste! {
+            register_adapter::<dyn $source, dyn $target, $adapter>(
+                [<get_ $source:lower _id>](),
+                [<get_ $target:lower _id>]())
+        }
+    }};
+}
+
+/// Declares that a new version of a trait supersedes an older version so that clients
+/// of the old trait can use components which only expose the new trait. If the new trait
+/// is a subtrait of the old trait then trait upcasting is used. Otherwise an adapter
+/// (see [`register_adapter`]) must be supplied to act as a shim. The new trait must have
+/// been registered with a larger version than the old trait.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Fruit {
+///     fn eat(&self) -> String;
+/// }
+/// register_type!(Fruit);
+///
+/// trait FruitV2: Fruit {
+///     fn calories(&self) -> i32;
+/// }
+/// register_type!(FruitV2, version = 2);
+/// supersedes!(FruitV2, Fruit);
+///
+/// struct Apple {}
+/// register_type!(Apple);
+///
+/// impl Fruit for Apple {
+///     fn eat(&self) -> String {
+///         "yum!".to_owned()
+///     }
+/// }
+///
+/// impl FruitV2 for Apple {
+///     fn calories(&self) -> i32 {
+///         95
+///     }
+/// }
+///
+/// let mut component = Component::new("apple");
+/// add_object!(component, Apple, Apple {}, [FruitV2]);
+///
+/// // Old clients can still use the component.
+/// assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+/// ```
+#[macro_export]
+macro_rules! supersedes {
+    ($new:ty, $old:ty) => {{
+        paste! {
+            register_supersedes::<dyn $new, dyn $old>(
+                [<get_ $new:lower _id>](),
+                [<get_ $old:lower _id>]())
+        }
+    }};
+
+    ($new:ty, $old:ty, $shim:ty) => {{
+        paste! {
+            check_versions([<get_ $new:lower _id>](), [<get_ $old:lower _id>]());
+        }
+        register_adapter!($new, $old, $shim)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::cell::RefCell;
+
+    trait Plant {
+        fn size(&self) -> u8;
+        fn shrink(&mut self, amount: u8);
+    }
+    register_type!(Plant);
+
+    trait Fodder {
+        fn height(&self) -> u8;
+        fn eat(&mut self);
+    }
+    register_type!(Fodder);
+
+    trait Named {
+        fn name(&self) -> String;
+    }
+    register_type!(Named);
+
+    trait Label {
+        fn label(&self) -> String;
+    }
+    register_type!(Label);
+
+    struct PlantFodder {
+        plant: Adapted<dyn Plant>,
+    }
+
+    impl From<Adapted<dyn Plant>> for PlantFodder {
+        fn from(plant: Adapted<dyn Plant>) -> Self {
+            PlantFodder { plant }
+        }
+    }
+
+    impl Fodder for PlantFodder {
+        fn height(&self) -> u8 {
+            self.plant.size()
+        }
+
+        fn eat(&mut self) {
+            self.plant.shrink(10);
+        }
+    }
+
+    struct NamedLabel {
+        named: Adapted<dyn Named>,
+    }
+
+    impl From<Adapted<dyn Named>> for NamedLabel {
+        fn from(named: Adapted<dyn Named>) -> Self {
+            NamedLabel { named }
+        }
+    }
+
+    impl Label for NamedLabel {
+        fn label(&self) -> String {
+            format!("<{}>", self.named.name())
+        }
+    }
+
+    struct Clover {
+        size: u8,
+    }
+    register_type!(Clover);
+
+    impl Plant for Clover {
+        fn size(&self) -> u8 {
+            self.size
+        }
+
+        fn shrink(&mut self, amount: u8) {
+            self.size -= amount;
+        }
+    }
+
+    impl Named for Clover {
+        fn name(&self) -> String {
+            "clover".to_owned()
+        }
+    }
+
+    struct Hay {}
+    register_type!(Hay);
+
+    impl Fodder for Hay {
+        fn height(&self) -> u8 {
+            1
+        }
+
+        fn eat(&mut self) {}
+    }
+
+    impl Named for Hay {
+        fn name(&self) -> String {
+            "hay".to_owned()
+        }
+    }
+
+    impl Label for Hay {
+        fn label(&self) -> String {
+            "HAY".to_owned()
+        }
+    }
+
+    #[test]
+    fn adapted() {
+        register_adapter!(Plant, Fodder, PlantFodder);
+        register_adapter!(Plant, Fodder, PlantFodder); // replaces the first registration
+        assert_eq!(find_adapters(get_fodder_id()).count(), 1);
+
+        let mut component = Component::new("clover");
+        add_keyed_object!(component, Clover, "patch", Clover { size: 30 }, [Plant]);
+        assert!(has_trait!(component, Fodder, "patch"));
+        assert!(!has_trait!(component, Fodder));
+        assert_eq!(find_slots!(component, Fodder).len(), 1);
+
+        {
+            let mut fodder = find_trait_mut!(component, Fodder, "patch").unwrap();
+            fodder.eat();
+            fodder.eat();
+        }
+        let fodder = find_trait!(component, Fodder, "patch").unwrap();
+        assert_eq!(fodder.height(), 10);
+        let plant = find_trait!(component, Plant, "patch").unwrap(); // immutable refs are fine
+        assert_eq!(plant.size(), 10);
+
+        // Components that have the trait don't use the adapter.
+        let mut component = Component::new("hay");
+        add_object!(component, Hay, Hay {}, [Fodder]);
+        assert_eq!(find_trait!(component, Fodder).unwrap().height(), 1);
+    }
+
+    trait Mow {
+        fn mow(&mut self);
+    }
+    register_type!(Mow);
+
+    thread_local! {
+        static MOWED: RefCell<Option<Adapted<dyn Plant>>> = const { RefCell::new(None) };
+    }
+
+    // Leaks its Adapted so that it outlives the borrow.
+    struct PlantMow {
+        plant: Option<Adapted<dyn Plant>>,
+    }
+
+    impl From<Adapted<dyn Plant>> for PlantMow {
+        fn from(plant: Adapted<dyn Plant>) -> Self {
+            PlantMow { plant: Some(plant) }
+        }
+    }
+
+    impl Mow for PlantMow {
+        fn mow(&mut self) {
+            let mut plant = self.plant.take().unwrap();
+            plant.shrink(1);
+            MOWED.with(|mowed| *mowed.borrow_mut() = Some(plant));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "adapted trait was used after its borrow ended")]
+    fn outlived() {
+        register_adapter!(Plant, Mow, PlantMow);
+
+        let mut component = Component::new("clover");
+        add_object!(component, Clover, Clover { size: 30 }, [Plant]);
+        find_trait_mut!(component, Mow).unwrap().mow();
+        assert_eq!(find_trait!(component, Plant).unwrap().size(), 29);
+
+        let plant = MOWED.with(|mowed| mowed.borrow_mut().take().unwrap());
+        plant.size();
+    }
+
+    #[test]
+    #[should_panic(expected = "mutable reference already exists")]
+    fn adapted_borrows() {
+        register_adapter!(Plant, Fodder, PlantFodder);
+
+        let mut component = Component::new("clover");
+        add_object!(component, Clover, Clover { size: 30 }, [Plant]);
+        let _fodder = find_trait_mut!(component, Fodder).unwrap();
+        let _plant = find_trait!(component, Plant).unwrap();
+    }
+
+    #[test]
+    fn adapted_repeated() {
+        register_adapter!(Named, Label, NamedLabel);
+
+        let mut component = Component::new("field");
+        add_object!(component, Clover, Clover { size: 30 }, [Plant], [Named]);
+        add_object!(component, Hay, Hay {}, [Fodder], [Named, Label]);
+
+        let mut labels: Vec<String> = find_repeated_trait!(component, Label)
+            .map(|l| l.label())
+            .collect();
+        labels.sort();
+        assert_eq!(labels, vec!["<clover>", "<hay>", "HAY"]);
+        assert_eq!(find_repeated_trait_mut!(component, Label).count(), 3);
+    }
+
+    trait Greeter {
+        fn greet(&self) -> String;
+    }
+    register_type!(Greeter);
+
+    trait GreeterV2: Greeter {
+        fn greet_by_name(&self, name: &str) -> String;
+    }
+    register_type!(GreeterV2, version = 2);
+
+    // Not a subtrait so this needs a shim to act as a Greeter.
+    trait GreeterV3 {
+        fn salutation(&self) -> String;
+    }
+    register_type!(GreeterV3, version = 3);
+
+    struct Polite {}
+    register_type!(Polite);
+
+    impl Greeter for Polite {
+        fn greet(&self) -> String {
+            "hello".to_owned()
+        }
+    }
+
+    impl GreeterV2 for Polite {
+        fn greet_by_name(&self, name: &str) -> String {
+            format!("hello {name}")
+        }
+    }
+
+    struct Terse {}
+    register_type!(Terse);
+
+    impl GreeterV3 for Terse {
+        fn salutation(&self) -> String {
+            "hi".to_owned()
+        }
+    }
+
+    struct GreeterShim {
+        greeter: Adapted<dyn GreeterV3>,
+    }
+
+    impl From<Adapted<dyn GreeterV3>> for GreeterShim {
+        fn from(greeter: Adapted<dyn GreeterV3>) -> Self {
+            GreeterShim { greeter }
+        }
+    }
+
+    impl Greeter for GreeterShim {
+        fn greet(&self) -> String {
+            self.greeter.salutation()
+        }
+    }
+
+    #[test]
+    fn versioned() {
+        supersedes!(GreeterV2, Greeter);
+        supersedes!(GreeterV3, Greeter, GreeterShim);
+
+        let mut component = Component::new("polite");
+        add_object!(component, Polite, Polite {}, [GreeterV2]);
+        assert_eq!(
+            find_trait!(component, GreeterV2)
+                .unwrap()
+                .greet_by_name("bob"),
+            "hello bob"
+        );
+        assert_eq!(find_trait!(component, Greeter).unwrap().greet(), "hello");
+
+        let mut component = Component::new("terse");
+        add_object!(component, Terse, Terse {}, [GreeterV3]);
+        assert_eq!(find_trait!(component, Greeter).unwrap().greet(), "hi");
+        assert!(!has_trait!(component, GreeterV2));
+
+        let exposed: Vec<(&str, u32, bool)> = component
+            .exposed_traits()
+            .iter()
+            .map(|e| (e.name, e.version, e.adapted_from.is_some()))
+            .collect();
+        assert_eq!(exposed, vec![("Greeter", 1, true), ("GreeterV3", 3, false)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Greeter (version 1) cannot supersede GreeterV2 (version 2)")]
+    fn bad_version() {
+        supersedes!(Greeter, GreeterV2, GreeterShim2);
+    }
+
+    struct GreeterShim2 {
+        greeter: Adapted<dyn Greeter>,
+    }
+
+    impl From<Adapted<dyn Greeter>> for GreeterShim2 {
+        fn from(greeter: Adapted<dyn Greeter>) -> Self {
+            GreeterShim2 { greeter }
+        }
+    }
+
+    impl Greeter for GreeterShim2 {
+        fn greet(&self) -> String {
+            self.greeter.greet()
+        }
+    }
+
+    impl GreeterV2 for GreeterShim2 {
+        fn greet_by_name(&self, _name: &str) -> String {
+            self.greeter.greet()
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use super::*;
+use fnv::FnvHashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+type Step<M> = Arc<dyn Fn(&mut Component<M>) + Send + Sync>;
+
+/// A named recipe for creating components. Archetypes consist of steps which are run
+/// when a component is instantiated, typically to add objects (see
+/// [`add_archetype_object`]) but also to do things like set a prototype or a state.
+/// Archetypes can be registered using [`register_archetype`] which allows components to
+/// be created by name.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Hunger {
+///     fn get(&self) -> i32;
+/// }
+/// register_type!(Hunger);
+///
+/// trait Name {
+///     fn get(&self) -> &'static str;
+/// }
+/// register_type!(Name);
+///
+/// struct Hungers {
+///     hunger: i32,
+/// }
+/// register_type!(Hungers);
+///
+/// impl Hunger for Hungers {
+///     fn get(&self) -> i32 {
+///         self.hunger
+///     }
+/// }
+///
+/// struct Wolf {}
+/// register_type!(Wolf);
+///
+/// impl Name for Wolf {
+///     fn get(&self) -> &'static str {
+///         "wolf"
+///     }
+/// }
+///
+/// let mut animal = Archetype::new("animal");
+/// add_archetype_object!(animal, Hungers, Hungers { hunger: 10 }, [Hunger]);
+///
+/// let mut wolf = animal.extend("wolf");
+/// add_archetype_object!(wolf, Wolf, Wolf {}, [Name]);
+/// register_archetype(wolf);
+///
+/// let component = spawn_archetype("wolf").unwrap();
+/// assert_eq!(find_trait!(component, Name).unwrap().get(), "wolf");
+/// assert_eq!(find_trait!(component, Hunger).unwrap().get(), 10);
+/// ```
+pub struct Archetype<M: Threading = Shared> {
+    name: String,
+    steps: Vec<Step<M>>,
+}
+
+impl Archetype {
+    /// Name is also used as the tag for instantiated components.
+    pub fn new(name: &str) -> Archetype {
+        Archetype::with_name(name)
+    }
+}
+
+impl Archetype<Local> {
+    /// Archetype for [`LocalComponent`]s.
+    pub fn new_local(name: &str) -> Archetype<Local> {
+        Archetype::with_name(name)
+    }
+}
+
+impl Archetype<Sendable> {
+    /// Archetype for [`SendComponent`]s.
+    pub fn new_sendable(name: &str) -> Archetype<Sendable> {
+        Archetype::with_name(name)
+    }
+}
+
+impl<M: Threading> Archetype<M> {
+    fn with_name(name: &str) -> Archetype<M> {
+        Archetype {
+            name: name.to_owned(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a step to run when a component is instantiated. Steps are run in the order
+    /// they were added.
+    pub fn add_step(&mut self, step: impl Fn(&mut Component<M>) + Send + Sync + 'static) {
+        self.steps.push(Arc::new(step));
+    }
+
+    /// Returns a new archetype with this archetype's steps. Additional steps can then be
+    /// added to the new archetype without affecting this archetype.
+    pub fn extend(&self, name: &str) -> Archetype<M> {
+        Archetype {
+            name: name.to_owned(),
+            steps: self.steps.clone(),
+        }
+    }
+
+    /// Creates a new component (tagged with the archetype's name) and runs the steps on
+    /// it.
+    pub fn instantiate(&self) -> Component<M> {
+        let mut component = Component::with_id(next_component_id(&self.name));
+        for step in self.steps.iter() {
+            step(&mut component);
+        }
+        component
+    }
+}
+
+static ARCHETYPES: LazyLock<RwLock<FnvHashMap<String, Arc<Archetype>>>> =
+    LazyLock::new(|| RwLock::new(FnvHashMap::default()));
+
+/// Adds an archetype to the global registry, replacing any archetype with the same
+/// name.
+pub fn register_archetype(archetype: Archetype) {
+    let mut archetypes = ARCHETYPES.write().unwrap();
+    archetypes.insert(archetype.name.clone(), Arc::new(archetype));
+}
+
+/// Returns a registered archetype.
+pub fn find_archetype(name: &str) -> Option<Arc<Archetype>> {
+    let archetypes = ARCHETYPES.read().unwrap();
+    archetypes.get(name).cloned()
+}
+
+/// Instantiates a registered archetype.
+pub fn spawn_archetype(name: &str) -> Option<Component> {
+    find_archetype(name).map(|archetype| archetype.instantiate())
+}
+
+/// Adds a step to an [`Archetype`] which adds an object along with its traits. The
+/// object expression is evaluated each time the archetype is instantiated. Arguments
+/// are the same as for [`add_object`].
+#[macro_export]
+macro_rules! add_archetype_object {
+    ($archetype:expr, $obj_type:ty, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        $archetype.add_step(move |component| {
+            add_object!(component, $obj_type, $object, [$($trait1),*] $(, [$($trait2),*])?)
+        })
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::rc::Rc;
+
+    trait Count {
+        fn count(&self) -> i32;
+    }
+    register_type!(Count);
+
+    struct Counter {
+        count: i32,
+    }
+    register_type!(Counter);
+
+    impl Count for Counter {
+        fn count(&self) -> i32 {
+            self.count
+        }
+    }
+
+    struct RcCounter {
+        count: Rc<i32>,
+    }
+    register_type!(RcCounter);
+
+    impl Count for RcCounter {
+        fn count(&self) -> i32 {
+            *self.count
+        }
+    }
+
+    #[test]
+    fn instantiate() {
+        let mut counter = Archetype::new("counter");
+        add_archetype_object!(counter, Counter, Counter { count: 1 }, [Count]);
+
+        let first = counter.instantiate();
+        let second = counter.instantiate();
+        assert_ne!(first.id, second.id);
+        find_object_mut!(first, Counter).unwrap().count = 2;
+        assert_eq!(find_trait!(first, Count).unwrap().count(), 2);
+        assert_eq!(find_trait!(second, Count).unwrap().count(), 1);
+
+        // Extending doesn't change the original.
+        let mut counted = counter.extend("counted");
+        counted.add_step(|component| mask_trait!(component, Count));
+        assert!(!has_trait!(counted.instantiate(), Count));
+        assert!(has_trait!(counter.instantiate(), Count));
+    }
+
+    #[test]
+    fn registry() {
+        let mut counter = Archetype::new("registered counter");
+        add_archetype_object!(counter, Counter, Counter { count: 3 }, [Count]);
+        register_archetype(counter);
+
+        let component = spawn_archetype("registered counter").unwrap();
+        assert_eq!(find_trait!(component, Count).unwrap().count(), 3);
+        assert_eq!(
+            find_archetype("registered counter").unwrap().name(),
+            "registered counter"
+        );
+        assert!(spawn_archetype("missing").is_none());
+    }
+
+    #[test]
+    fn local() {
+        let mut counter = Archetype::new_local("rc counter");
+        add_archetype_object!(counter, RcCounter, RcCounter { count: Rc::new(4) }, [Count]);
+        let component = counter.instantiate();
+        assert_eq!(find_trait!(component, Count).unwrap().count(), 4);
+    }
+}
@@ -0,0 +1,608 @@
+//! Compact binary serde format used by snapshots. Integers are LEB128 varints (signed
+//! integers are zigzag encoded), strings and sequences are prefixed with their length,
+//! and structs are written as their fields in order. The format is not self-describing so
+//! it can only be read using the same types that wrote it.
+use super::*;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::Deserializer;
+use std::io::{Read, Write};
+
+fn format_error(message: &str) -> SnapshotError {
+    SnapshotError::Format(message.to_owned())
+}
+
+pub(crate) struct BinaryWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub(crate) fn new(writer: W) -> BinaryWriter<W> {
+        BinaryWriter { writer }
+    }
+
+    fn write_varint(&mut self, mut value: u64) -> Result<(), SnapshotError> {
+        let mut bytes = [0u8; 10];
+        let mut count = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes[count] = byte;
+                count += 1;
+                break;
+            }
+            bytes[count] = byte | 0x80;
+            count += 1;
+        }
+        self.writer.write_all(&bytes[..count])?;
+        Ok(())
+    }
+
+    fn write_signed(&mut self, value: i64) -> Result<(), SnapshotError> {
+        self.write_varint(((value << 1) ^ (value >> 63)) as u64)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        self.write_varint(bytes.len() as u64)?;
+        self.writer.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::Serializer for &mut BinaryWriter<W> {
+    type Ok = ();
+    type Error = SnapshotError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, value: bool) -> Result<(), SnapshotError> {
+        self.writer.write_all(&[value as u8])?;
+        Ok(())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), SnapshotError> {
+        self.write_signed(value as i64)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), SnapshotError> {
+        self.write_signed(value as i64)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), SnapshotError> {
+        self.write_signed(value as i64)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), SnapshotError> {
+        self.write_signed(value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), SnapshotError> {
+        self.writer.write_all(&[value])?;
+        Ok(())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), SnapshotError> {
+        self.write_varint(value as u64)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), SnapshotError> {
+        self.write_varint(value as u64)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), SnapshotError> {
+        self.write_varint(value)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), SnapshotError> {
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), SnapshotError> {
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), SnapshotError> {
+        self.write_varint(value as u64)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), SnapshotError> {
+        self.write_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), SnapshotError> {
+        self.write_bytes(value)
+    }
+
+    fn serialize_none(self) -> Result<(), SnapshotError> {
+        self.serialize_bool(false)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), SnapshotError> {
+        self.writer.write_all(&[1])?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SnapshotError> {
+        self.write_varint(index as u64)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SnapshotError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), SnapshotError> {
+        self.write_varint(index as u64)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, SnapshotError> {
+        let len = len.ok_or_else(|| format_error("sequence lengths must be known"))?;
+        self.write_varint(len as u64)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, SnapshotError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self, SnapshotError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SnapshotError> {
+        self.write_varint(index as u64)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, SnapshotError> {
+        let len = len.ok_or_else(|| format_error("map lengths must be known"))?;
+        self.write_varint(len as u64)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, SnapshotError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SnapshotError> {
+        self.write_varint(index as u64)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Compound values are simply their elements written in order.
+macro_rules! serialize_compound {
+    ($trait:ident, $method:ident) => {
+        impl<W: Write> ser::$trait for &mut BinaryWriter<W> {
+            type Ok = ();
+            type Error = SnapshotError;
+
+            fn $method<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SnapshotError> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), SnapshotError> {
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! serialize_fields {
+    ($trait:ident) => {
+        impl<W: Write> ser::$trait for &mut BinaryWriter<W> {
+            type Ok = ();
+            type Error = SnapshotError;
+
+            fn serialize_field<T: ?Sized + Serialize>(
+                &mut self,
+                _key: &'static str,
+                value: &T,
+            ) -> Result<(), SnapshotError> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), SnapshotError> {
+                Ok(())
+            }
+        }
+    };
+}
+
+serialize_compound!(SerializeSeq, serialize_element);
+serialize_compound!(SerializeTuple, serialize_element);
+serialize_compound!(SerializeTupleStruct, serialize_field);
+serialize_compound!(SerializeTupleVariant, serialize_field);
+serialize_fields!(SerializeStruct);
+serialize_fields!(SerializeStructVariant);
+
+impl<W: Write> ser::SerializeMap for &mut BinaryWriter<W> {
+    type Ok = ();
+    type Error = SnapshotError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SnapshotError> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SnapshotError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+pub(crate) struct BinaryReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> BinaryReader<R> {
+    pub(crate) fn new(reader: R) -> BinaryReader<R> {
+        BinaryReader { reader }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        let mut byte = [0u8; 1];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn read_varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format_error("varint is too long"))
+    }
+
+    fn read_signed(&mut self) -> Result<i64, SnapshotError> {
+        let value = self.read_varint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    fn read_len(&mut self) -> Result<usize, SnapshotError> {
+        let len = self.read_varint()?;
+        usize::try_from(len).map_err(|_| format_error("length is too large"))
+    }
+
+    fn read_bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let len = self.read_len()?;
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(format_error("unexpected end of data"));
+        }
+        Ok(bytes)
+    }
+
+    fn read_string(&mut self) -> Result<String, SnapshotError> {
+        String::from_utf8(self.read_bytes()?).map_err(|_| format_error("invalid utf-8"))
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let mut bytes = [0u8; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+macro_rules! deserialize_int {
+    ($method:ident, $visit:ident, $type:ty, $read:ident) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+            let value = self.$read()?;
+            let value = <$type>::try_from(value)
+                .map_err(|_| format_error(concat!("value is too large for ", stringify!($type))))?;
+            visitor.$visit(value)
+        }
+    };
+}
+
+impl<'de, R: Read> Deserializer<'de> for &mut BinaryReader<R> {
+    type Error = SnapshotError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SnapshotError> {
+        Err(format_error("the binary format is not self-describing"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        match self.read_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(format_error("invalid bool")),
+        }
+    }
+
+    deserialize_int!(deserialize_i8, visit_i8, i8, read_signed);
+    deserialize_int!(deserialize_i16, visit_i16, i16, read_signed);
+    deserialize_int!(deserialize_i32, visit_i32, i32, read_signed);
+    deserialize_int!(deserialize_i64, visit_i64, i64, read_signed);
+    deserialize_int!(deserialize_u16, visit_u16, u16, read_varint);
+    deserialize_int!(deserialize_u32, visit_u32, u32, read_varint);
+    deserialize_int!(deserialize_u64, visit_u64, u64, read_varint);
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_u8(self.read_u8()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_f32(f32::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_f64(f64::from_le_bytes(self.read_array()?))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        let value = self.read_varint()?;
+        let ch = u32::try_from(value)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format_error("invalid char"))?;
+        visitor.visit_char(ch)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_string(self.read_string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_string(self.read_string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_byte_buf(self.read_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_byte_buf(self.read_bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        match self.read_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(format_error("invalid option")),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        let len = self.read_len()?;
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SnapshotError> {
+        let len = self.read_len()?;
+        visitor.visit_map(Elements { reader: self, len })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        let len = fields.len();
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        Err(format_error("the binary format cannot skip values"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Used for sequences, tuples, structs, and maps.
+struct Elements<'a, R: Read> {
+    reader: &'a mut BinaryReader<R>,
+    len: usize,
+}
+
+impl<'de, R: Read> de::SeqAccess<'de> for Elements<'_, R> {
+    type Error = SnapshotError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SnapshotError> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.reader).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de, R: Read> de::MapAccess<'de> for Elements<'_, R> {
+    type Error = SnapshotError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SnapshotError> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.reader).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SnapshotError> {
+        seed.deserialize(&mut *self.reader)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de, R: Read> de::EnumAccess<'de> for &mut BinaryReader<R> {
+    type Error = SnapshotError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), SnapshotError> {
+        let index = u32::try_from(self.read_varint()?)
+            .map_err(|_| format_error("invalid variant index"))?;
+        let index: de::value::U32Deserializer<SnapshotError> = index.into_deserializer();
+        let value = seed.deserialize(index)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, R: Read> de::VariantAccess<'de> for &mut BinaryReader<R> {
+    type Error = SnapshotError;
+
+    fn unit_variant(self) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SnapshotError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SnapshotError> {
+        let len = fields.len();
+        visitor.visit_seq(Elements { reader: self, len })
+    }
+}
@@ -0,0 +1,24 @@
+use super::*;
+use std::any::Any;
+use std::marker::PhantomData;
+use type_erased_ptr::*;
+
+/// An object, along with its trait registrations, that has been taken out of a
+/// [`Component`] using [`Component::take_object`]. It can be added to another component
+/// with the same threading marker using [`Component::insert_object`]. Dropping the bundle
+/// drops the object.
+pub struct ObjectBundle<M: Threading = Shared> {
+    pub id: ObjectId,
+    pub(crate) object: Box<dyn Any>,
+    pub(crate) pointer: *mut dyn Any,
+    pub(crate) traits: Vec<(TypeId, TypeErasedPointer, bool)>, // (trait id, trait pointer, repeated)
+    pub(crate) state: Option<InstanceKey>,
+    pub(crate) masked: bool,
+    pub(crate) marker: PhantomData<M>,
+}
+
+// Bundles can only be created from components which have the same guarantees. Bundles
+// own their object so, unlike components, they never share a prototype.
+unsafe impl Send for ObjectBundle<Shared> {}
+unsafe impl Sync for ObjectBundle<Shared> {}
+unsafe impl Send for ObjectBundle<Sendable> {}
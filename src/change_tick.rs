@@ -0,0 +1,15 @@
+//! Change ticks record when objects and components were last modified, see
+//! [`Component::changed_since`]. Interior mutability is not tracked.
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static CHANGE_TICK: AtomicU32 = AtomicU32::new(1);
+
+/// Returns the current change tick.
+pub fn change_tick() -> u32 {
+    CHANGE_TICK.load(Ordering::Relaxed)
+}
+
+/// Increments the change tick and returns the new tick.
+pub fn advance_change_tick() -> u32 {
+    CHANGE_TICK.fetch_add(1, Ordering::Relaxed) + 1
+}
@@ -1,47 +1,300 @@
 #[allow(unused_imports)]
 use super::*;
 use core::fmt::{self, Debug};
-use fnv::FnvHashMap;
+use double_buffer::Buffers;
+use fnv::{FnvHashMap, FnvHashSet};
 #[allow(unused_imports)]
 use paste::paste;
+use signal::Signals;
 use std::any::Any;
 use std::hash::{Hash, Hasher};
+use std::iter;
+use std::marker::PhantomData;
 use std::marker::Unsize;
+use std::mem;
+use std::ptr;
 use std::ptr::{DynMetadata, Pointee};
+use std::sync::Arc;
+use transaction::Journal;
 use type_erased_ptr::*;
 
 /// The unit of composition for the gear object model.
 /// A component consists  of one or more objects. Each object implements one or more
 /// traits. Component clients are only allowed to interact with objects via their traits.
 /// Note that publicly released traits should be treated as immutable to foster backward
-/// compatibility.
-pub struct Component {
+/// compatibility. New versions of a trait can be added using the version option of
+/// [`register_type`] and declared with [`supersedes`] so that old clients continue to
+/// work.
+///
+/// By default components, and the objects within them, are `Send + Sync`. Use
+/// [`LocalComponent`] for objects that are neither (e.g. objects using `Rc` or `RefCell`)
+/// and [`SendComponent`] for objects that are `Send` but not `Sync`.
+pub struct Component<M: Threading = Shared> {
     pub id: ComponentId,
-    objects: FnvHashMap<TypeId, Box<dyn Any + Send + Sync>>, // object id => type erased boxed object
-    traits: FnvHashMap<TypeId, TypeErasedPointer>, // trait id => type erased trait pointer
+    objects: FnvHashMap<ObjectId, Box<dyn Any>>, // object id => type erased boxed object
+    pointers: FnvHashMap<ObjectId, *mut dyn Any>, // object id => pointer to the boxed object
+    traits: FnvHashMap<(TypeId, InstanceKey), TypeErasedPointer>, // (trait id, instance key) => type erased trait pointer
     repeated: FnvHashMap<TypeId, Vec<TypeErasedPointer>>, // trait id => [type erased trait pointer]
-    refs: FnvHashMap<TypeId, ObjectRefs>, // object id => outstanding trait references on the object
+    shadowed: FnvHashMap<(TypeId, InstanceKey), Vec<TypeErasedPointer>>, // (trait id, instance key) => [overridden trait pointer]
+    implied: FnvHashSet<(TypeId, InstanceKey)>, // traits added only because they are supertraits
+    masked_traits: FnvHashSet<TypeId>,          // traits that are hidden from finds
+    masked_objects: FnvHashSet<ObjectId>,       // objects whose traits are hidden from finds
+    states: FnvHashMap<InstanceKey, Vec<(TypeId, TypeErasedPointer, bool)>>, // state name => [(trait id, trait pointer, repeated)] for inactive states
+    object_states: FnvHashMap<ObjectId, InstanceKey>, // object id => state the object belongs to
+    state: Option<InstanceKey>,                       // the current state
+    refs: FnvHashMap<ObjectId, M::Refs>, // object id => outstanding trait references on the object
+    prototype: Option<Arc<Component<M>>>, // used to find traits the component doesn't have
+    changed: u32, // change tick of the last time objects, traits, states, or masks changed
+    journal: Journal, // objects mutably borrowed within a transaction
+    buffers: Buffers<M>, // back buffers for double buffered objects
+    signals: Signals<M>, // subscribers and undelivered signals
     empty: Vec<TypeErasedPointer>,
 }
 
+/// A [`Component`] whose objects need not be `Send` or `Sync`. The component itself is
+/// neither `Send` nor `Sync`.
+///
+/// ```compile_fail
+/// use gear_objects::*;
+///
+/// fn requires_send<T: Send>(_t: T) {}
+/// requires_send(LocalComponent::new_local("button"));
+/// ```
+pub type LocalComponent = Component<Local>;
+
+/// A [`Component`] whose objects are `Send` but need not be `Sync`. The component itself
+/// is `Send` but not `Sync`.
+pub type SendComponent = Component<Sendable>;
+
+// Objects can only be added to a component if its threading marker admits them (see
+// add_object) so a component is Send or Sync exactly when all of its objects are.
+unsafe impl Send for Component<Shared> {}
+unsafe impl Sync for Component<Shared> {}
+unsafe impl Send for Component<Sendable> {}
+
+/// Returned by [`Component::exposed_traits`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExposedTrait {
+    pub trait_id: TypeId,
+
+    /// From [`TypeInfo`].
+    pub name: &'static str,
+
+    /// From [`TypeInfo`].
+    pub version: u32,
+
+    /// Slot name for keyed traits (empty for the default slot and repeated traits).
+    pub slot: InstanceKey,
+
+    pub repeated: bool,
+
+    /// Set if the trait is exposed via an adapter, e.g. an older version of a trait
+    /// shimmed by [`supersedes`].
+    pub adapted_from: Option<TypeId>,
+}
+
+impl ExposedTrait {
+    fn new(
+        trait_id: TypeId,
+        slot: InstanceKey,
+        repeated: bool,
+        adapted_from: Option<TypeId>,
+    ) -> ExposedTrait {
+        let info = type_info(trait_id).unwrap_or_else(|| TypeInfo::new("?"));
+        ExposedTrait {
+            trait_id,
+            name: info.name,
+            version: info.version,
+            slot,
+            repeated,
+            adapted_from,
+        }
+    }
+}
+
 impl Component {
     /// tag is used by the Debug trait on Component (and ComponentId).
     pub fn new(tag: &str) -> Component {
+        Component::with_id(next_component_id(tag))
+    }
+}
+
+impl LocalComponent {
+    /// Like [`Component::new`] except that the objects need not be `Send` or `Sync`.
+    pub fn new_local(tag: &str) -> LocalComponent {
+        Component::with_id(next_component_id(tag))
+    }
+}
+
+impl SendComponent {
+    /// Like [`Component::new`] except that the objects need not be `Sync`.
+    pub fn new_sendable(tag: &str) -> SendComponent {
+        Component::with_id(next_component_id(tag))
+    }
+}
+
+impl<M: SharesPrototypes> Component<M> {
+    /// Traits that are not found in the component will be searched for within the
+    /// prototype (and the prototype's prototype, etc). This allows many components to
+    /// share objects, e.g. stateless helper objects, and allows a component to override
+    /// a prototype's trait by providing the trait itself. Note that mutable finds of a
+    /// prototype's trait will mutate the object for all of the components sharing it.
+    ///
+    /// [`SendComponent`]s can't have prototypes because a prototype shared by components
+    /// on different threads would have to be `Sync`.
+    ///
+    /// ```compile_fail
+    /// use gear_objects::*;
+    /// use std::sync::Arc;
+    ///
+    /// let base = Arc::new(SendComponent::new_sendable("base"));
+    /// let mut component = SendComponent::new_sendable("wolf");
+    /// component.set_prototype(base);
+    /// ```
+    pub fn set_prototype(&mut self, prototype: Arc<Component<M>>) {
+        self.prototype = Some(prototype);
+        self.touch();
+    }
+}
+
+impl<M: Threading> Component<M> {
+    pub(crate) fn with_id(id: ComponentId) -> Component<M> {
         Component {
-            id: next_component_id(tag),
+            id,
             objects: FnvHashMap::default(),
+            pointers: FnvHashMap::default(),
             traits: FnvHashMap::default(),
             repeated: FnvHashMap::default(),
+            shadowed: FnvHashMap::default(),
+            implied: FnvHashSet::default(),
+            masked_traits: FnvHashSet::default(),
+            masked_objects: FnvHashSet::default(),
+            states: FnvHashMap::default(),
+            object_states: FnvHashMap::default(),
+            state: None,
             empty: Vec::new(),
             refs: FnvHashMap::default(),
+            prototype: None,
+            changed: change_tick(),
+            journal: Journal::default(),
+            buffers: Buffers::new(),
+            signals: Signals::new(),
         }
     }
 
+    pub fn prototype(&self) -> Option<&Arc<Component<M>>> {
+        self.prototype.as_ref()
+    }
+
+    /// Returns the traits the component exposes, including traits exposed by prototypes
+    /// and traits which are available via an adapter (e.g. older versions of a trait
+    /// superseded by a trait the component exposes). Sorted by name, version, and slot.
+    pub fn exposed_traits(&self) -> Vec<ExposedTrait> {
+        let mut exposed = Vec::new();
+        for c in self.chain() {
+            for ((trait_id, slot), erased) in c.traits.iter() {
+                if !c.masked_objects.contains(&erased.object_id) {
+                    exposed.push(ExposedTrait::new(*trait_id, *slot, false, None));
+                }
+            }
+            for (trait_id, erased) in c.repeated.iter() {
+                if erased
+                    .iter()
+                    .any(|e| !c.masked_objects.contains(&e.object_id))
+                {
+                    exposed.push(ExposedTrait::new(*trait_id, InstanceKey::new(), true, None));
+                }
+            }
+        }
+        exposed.retain(|e| !self.is_masked(e.trait_id));
+
+        let mut adapted = Vec::new();
+        for (target_id, source_id) in all_adapters() {
+            for e in exposed.iter().filter(|e| e.trait_id == source_id) {
+                adapted.push(ExposedTrait::new(
+                    target_id,
+                    e.slot,
+                    e.repeated,
+                    Some(source_id),
+                ));
+            }
+        }
+        exposed.extend(adapted.into_iter().filter(|e| !self.is_masked(e.trait_id)));
+
+        exposed.sort_by(|lhs, rhs| {
+            (lhs.name, lhs.version, lhs.slot, lhs.adapted_from).cmp(&(
+                rhs.name,
+                rhs.version,
+                rhs.slot,
+                rhs.adapted_from,
+            ))
+        });
+        exposed.dedup_by(|lhs, rhs| {
+            lhs.trait_id == rhs.trait_id && lhs.slot == rhs.slot && lhs.repeated == rhs.repeated
+        });
+        exposed
+    }
+
+    // Returns the component followed by its prototype chain.
+    fn chain(&self) -> impl Iterator<Item = &Component<M>> {
+        iter::successors(Some(self), |c| c.prototype.as_deref())
+    }
+
+    // Returns the trait pointer and refs for a non-repeated trait, searching prototypes
+    // if needed.
+    fn lookup(&self, key: (TypeId, InstanceKey)) -> Option<(&TypeErasedPointer, &M::Refs)> {
+        if self.is_masked(key.0) {
+            return None;
+        }
+        self.chain().find_map(|c| {
+            c.traits
+                .get(&key)
+                .filter(|erased| !c.masked_objects.contains(&erased.object_id))
+                .map(|erased| (erased, c.refs.get(&erased.object_id).unwrap()))
+        })
+    }
+
+    // Returns the unmasked pointers and refs for a repeated trait, including those from
+    // prototypes.
+    fn lookup_repeated(
+        &self,
+        trait_id: TypeId,
+    ) -> impl Iterator<Item = (&TypeErasedPointer, &M::Refs)> {
+        let masked = self.is_masked(trait_id);
+        self.chain().filter(move |_| !masked).flat_map(move |c| {
+            c.repeated
+                .get(&trait_id)
+                .unwrap_or(&c.empty)
+                .iter()
+                .filter(|e| !c.masked_objects.contains(&e.object_id))
+                .map(|e| (e, c.refs.get(&e.object_id).unwrap()))
+        })
+    }
+
+    // Traits masked by a prototype are also masked for components using the prototype.
+    fn is_masked(&self, trait_id: TypeId) -> bool {
+        self.chain().any(|c| c.masked_traits.contains(&trait_id))
+    }
+
+    // Like lookup except that it searches for traits which can be adapted to the trait.
+    fn lookup_adapted(
+        &self,
+        key: (TypeId, InstanceKey),
+    ) -> Option<(&TypeErasedPointer, &M::Refs, AdaptFn)> {
+        if self.is_masked(key.0) {
+            return None;
+        }
+        find_adapters(key.0).find_map(|(source_id, adapt)| {
+            self.lookup((source_id, key.1))
+                .map(|(erased, refs)| (erased, refs, adapt))
+        })
+    }
+
     // Normally the [`add_traits`]` macro would be used instead of calling this directly.
     #[doc(hidden)]
     pub fn add_trait<Trait, Object>(
         &mut self,
-        object_id: TypeId,
+        object_id: ObjectId,
         trait_id: TypeId,
         obj_ptr: *mut Object,
     ) where
@@ -49,15 +302,15 @@ impl Component {
         Object: Unsize<Trait> + 'static,
     {
         let erased = TypeErasedPointer::from_trait::<Object, Trait>(object_id, obj_ptr);
-        let old = self.traits.insert(trait_id, erased);
-        assert!(old.is_none(), "trait was already added to the component");
+        self.insert_trait(trait_id, erased, false);
+        self.touch();
     }
 
-    // Normally the [`add_repeated_traits`]` macro would be used instead of calling this directly.
+    // Normally the [`push_object`]` macro would be used instead of calling this directly.
     #[doc(hidden)]
-    pub fn add_repeated_trait<Trait, Object>(
+    pub fn override_trait<Trait, Object>(
         &mut self,
-        object_id: TypeId,
+        object_id: ObjectId,
         trait_id: TypeId,
         obj_ptr: *mut Object,
     ) where
@@ -65,342 +318,2047 @@ impl Component {
         Object: Unsize<Trait> + 'static,
     {
         let erased = TypeErasedPointer::from_trait::<Object, Trait>(object_id, obj_ptr);
-        let pointers = self.repeated.entry(trait_id).or_insert(vec![]);
-        pointers.push(erased);
+        self.shadow_trait(trait_id, erased);
+        self.touch();
     }
 
-    // Normally the [`add_object`]` macro would be used instead of calling this directly.
+    // Normally the [`add_repeated_traits`]` macro would be used instead of calling this directly.
     #[doc(hidden)]
-    pub fn add_object<Object>(&mut self, obj_id: TypeId, obj_ptr: *mut Object)
-    where
-        Object: Send + Sync + 'static,
+    pub fn add_repeated_trait<Trait, Object>(
+        &mut self,
+        object_id: ObjectId,
+        trait_id: TypeId,
+        obj_ptr: *mut Object,
+    ) where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Object: Unsize<Trait> + 'static,
     {
-        let erased: Box<dyn Any + Send + Sync> = unsafe { Box::from_raw(obj_ptr) };
-        let old = self.objects.insert(obj_id, erased);
-        assert!(
-            old.is_none(),
-            "object type was already added to the component"
-        );
+        let erased = TypeErasedPointer::from_trait::<Object, Trait>(object_id, obj_ptr);
+        self.insert_repeated(trait_id, erased);
+        self.touch();
+    }
 
-        self.refs.entry(obj_id).or_insert(ObjectRefs::new());
+    // Adds a trait along with the supertraits declared for it. Supertraits are implied:
+    // they don't replace existing traits and are replaced by traits added explicitly.
+    fn insert_trait(&mut self, trait_id: TypeId, erased: TypeErasedPointer, implied: bool) {
+        let key = (trait_id, erased.object_id.key);
+        self.insert_supertraits(trait_id, &erased, false);
+        let Some(erased) = self.stash(trait_id, erased, false) else {
+            return;
+        };
+        if implied {
+            if self.traits.contains_key(&key) {
+                return;
+            }
+            self.implied.insert(key);
+        } else if !self.implied.remove(&key) {
+            assert!(
+                !self.traits.contains_key(&key),
+                "trait was already added to the component"
+            );
+        }
+        self.traits.insert(key, erased);
     }
 
-    // TODO: May want to support remove_object. Would be kinda slow: probably need to
-    // change traits and repeated so that the value includes the object's type id. One
-    // nice thing is, that if we did do that, Debug and Display could print the traits
-    // associated with the corresponding object.
+    fn shadow_trait(&mut self, trait_id: TypeId, erased: TypeErasedPointer) {
+        self.insert_supertraits(trait_id, &erased, true);
+        if let Some(erased) = self.stash(trait_id, erased, false) {
+            self.attach_trait(trait_id, erased, false);
+        }
+    }
 
-    // Normally the [`has_trait`]` macro would be used instead of calling this directly.
-    #[doc(hidden)]
-    pub fn has<Trait>(&self, trait_id: TypeId) -> bool
-    where
-        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
-    {
-        self.traits.get(&trait_id).is_some()
+    fn insert_repeated(&mut self, trait_id: TypeId, erased: TypeErasedPointer) {
+        self.insert_supertraits(trait_id, &erased, false);
+        let Some(erased) = self.stash(trait_id, erased, true) else {
+            return;
+        };
+        let pointers = self.repeated.entry(trait_id).or_default();
+        if !pointers.iter().any(|e| e.object_id == erased.object_id) {
+            pointers.push(erased);
+        }
     }
 
-    // Normally the [`find_trait`]` macro would be used instead of calling this directly.
-    #[doc(hidden)]
-    pub fn find<Trait>(&self, trait_id: TypeId) -> Option<RefTrait<Trait>>
-    where
-        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
-    {
-        if let Some(erased) = self.traits.get(&trait_id) {
-            let refs = self.refs.get(&erased.object_id).unwrap();
-            let r = unsafe { erased.to_trait::<Trait>(refs) };
-            Some(r)
-        } else {
-            None
+    // Objects added to a state only expose their traits while the component is in that
+    // state. Returns the pointer if the object doesn't belong to a state.
+    fn stash(
+        &mut self,
+        trait_id: TypeId,
+        erased: TypeErasedPointer,
+        repeated: bool,
+    ) -> Option<TypeErasedPointer> {
+        match self.object_states.get(&erased.object_id).copied() {
+            Some(state) if Some(state) == self.state => {
+                self.attach_trait(trait_id, erased, repeated);
+                None
+            }
+            Some(state) => {
+                let stashed = self.states.entry(state).or_default();
+                stashed.push((trait_id, erased, repeated));
+                None
+            }
+            None => Some(erased),
         }
     }
 
-    // Normally the [`find_trait_mut`]` macro would be used instead of calling this directly.
-    #[doc(hidden)]
-    pub fn find_mut<Trait>(&self, trait_id: TypeId) -> Option<RefMutTrait<Trait>>
-    where
-        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
-    {
-        if let Some(erased) = self.traits.get(&trait_id) {
-            let refs = self.refs.get(&erased.object_id).unwrap();
-            let r = unsafe { erased.to_trait_mut::<Trait>(refs) };
-            Some(r)
+    // Adds a trait, overriding any existing trait.
+    fn attach_trait(&mut self, trait_id: TypeId, erased: TypeErasedPointer, repeated: bool) {
+        if repeated {
+            self.repeated.entry(trait_id).or_default().push(erased);
         } else {
-            None
+            let key = (trait_id, erased.object_id.key);
+            if let Some(old) = self.traits.insert(key, erased) {
+                self.shadowed.entry(key).or_default().push(old);
+            }
         }
     }
 
-    // Normally the [`find_repeated_trait`]` macro would be used instead of calling this directly.
-    #[doc(hidden)]
-    pub fn find_repeated<Trait>(&self, trait_id: TypeId) -> impl Iterator<Item = RefTrait<Trait>>
-    where
-        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
-    {
-        self.repeated
-            .get(&trait_id)
-            .unwrap_or(&self.empty)
+    // Removes all the traits of an object restoring any traits the object overrode.
+    // Returns (trait id, trait pointer, repeated) for the removed traits.
+    fn detach_traits(&mut self, obj_id: ObjectId) -> Vec<(TypeId, TypeErasedPointer, bool)> {
+        let mut detached = Vec::new();
+        for ((trait_id, _), pointers) in self.shadowed.iter_mut() {
+            let (removed, kept): (Vec<_>, Vec<_>) = mem::take(pointers)
+                .into_iter()
+                .partition(|e| e.object_id == obj_id);
+            *pointers = kept;
+            detached.extend(removed.into_iter().map(|e| (*trait_id, e, false)));
+        }
+
+        // If the object was overriding traits then restore the overridden traits.
+        let keys: Vec<_> = self
+            .traits
             .iter()
-            .map(|e| unsafe {
-                let refs = self.refs.get(&e.object_id).unwrap();
-                e.to_trait::<Trait>(refs)
-            })
+            .filter(|(_, e)| e.object_id == obj_id)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let erased = self.traits.remove(&key).unwrap();
+            detached.push((key.0, erased, false));
+            self.implied.remove(&key);
+            if let Some(erased) = self.shadowed.get_mut(&key).and_then(|p| p.pop()) {
+                self.traits.insert(key, erased);
+            }
+        }
+        self.shadowed.retain(|_, pointers| !pointers.is_empty());
+
+        for (trait_id, pointers) in self.repeated.iter_mut() {
+            let (removed, kept): (Vec<_>, Vec<_>) = mem::take(pointers)
+                .into_iter()
+                .partition(|e| e.object_id == obj_id);
+            *pointers = kept;
+            detached.extend(removed.into_iter().map(|e| (*trait_id, e, true)));
+        }
+        detached
     }
 
-    // Normally the [`find_repeated_trait_mut`]` macro would be used instead of calling this directly.
+    fn insert_supertraits(&mut self, trait_id: TypeId, erased: &TypeErasedPointer, shadow: bool) {
+        let Some(info) = type_info(trait_id) else {
+            return;
+        };
+        for supertrait in info.supertraits {
+            let upcast = (supertrait.upcast)(erased);
+            if supertrait.repeated {
+                self.insert_repeated(supertrait.trait_id, upcast);
+            } else if shadow {
+                self.shadow_trait(supertrait.trait_id, upcast);
+            } else {
+                self.insert_trait(supertrait.trait_id, upcast, true);
+            }
+        }
+    }
+
+    // Normally the [`add_object`]` macro would be used instead of calling this directly.
+    // Returns a pointer to the boxed object which remains valid as long as the object is
+    // within the component.
     #[doc(hidden)]
-    pub fn find_repeated_mut<Trait>(
-        &self,
-        trait_id: TypeId,
-    ) -> impl Iterator<Item = RefMutTrait<Trait>>
+    pub fn add_object<Object>(&mut self, obj_id: ObjectId, object: Object) -> *mut Object
     where
-        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Object: 'static,
+        M: Admits<Object>,
     {
-        self.repeated
-            .get(&trait_id)
-            .unwrap_or(&self.empty)
-            .iter()
-            .map(|e| unsafe {
-                let refs = self.refs.get(&e.object_id).unwrap();
-                e.to_trait_mut::<Trait>(refs)
-            })
+        let obj_ptr = Box::into_raw(Box::new(object));
+        let erased: Box<dyn Any> = unsafe { Box::from_raw(obj_ptr) };
+        let old = self.objects.insert(obj_id, erased);
+        assert!(old.is_none(), "object was already added to the component");
+
+        self.pointers.insert(obj_id, obj_ptr as *mut dyn Any);
+        self.refs.entry(obj_id).or_default();
+        self.buffers.add(obj_id, unsafe { &*obj_ptr });
+        self.touch();
+        obj_ptr
     }
-}
 
-/// Use this for all trait and object types used within components.
-///
-/// # Examples
-///
-/// ```
-/// #![feature(lazy_cell)]
-/// use gear_objects::*;
-/// use paste::paste;
-///
-/// trait Fruit {
-///     fn eat(&self) -> String;
-/// }
-/// register_type!(Fruit);
-/// ```
-#[macro_export]
-macro_rules! register_type {
-    ($type:ty) => {
-        paste! {
-            pub fn [<get_ $type:lower _id>]() -> TypeId {
-                unique_type_id!()
+    /// Returns true if the object is in the component (including objects within inactive
+    /// states and masked objects). Objects in the prototype are not included.
+    pub fn has_object(&self, obj_id: ObjectId) -> bool {
+        self.objects.contains_key(&obj_id)
+    }
+
+    // Returns true if an object in the component (not an implied supertrait or the
+    // prototype) provides the trait in the slot.
+    pub(crate) fn has_own_trait(&self, trait_id: TypeId, slot: &str) -> bool {
+        let key = (trait_id, instance_key(slot));
+        self.traits.contains_key(&key) && !self.implied.contains(&key)
+    }
+
+    // Normally the [`remove_object`]` macro would be used instead of calling this directly.
+    // Note that this is O(N) in the number of traits.
+    #[doc(hidden)]
+    pub fn remove_object(&mut self, obj_id: ObjectId) -> Result<Box<dyn Any>, ComponentError> {
+        self.check_movable(obj_id)?;
+        Ok(self.detach_object(obj_id).object)
+    }
+
+    /// Removes an object, and all of its traits, from the component so that it can be
+    /// added to another component using [`insert_object`]. Fails if the object isn't
+    /// part of the component or it is borrowed. If the object was overriding traits then
+    /// the overridden traits are restored.
+    ///
+    /// [`insert_object`]: Component::insert_object
+    pub fn take_object(&mut self, obj_id: ObjectId) -> Result<ObjectBundle<M>, ComponentError> {
+        self.check_movable(obj_id)?;
+        Ok(self.detach_object(obj_id))
+    }
+
+    /// Adds an object taken from another component along with its traits. The traits
+    /// override existing traits (like [`push_object`]). If the object belonged to a state
+    /// or was masked then it will still be in that state or masked. Panics if the
+    /// component already has the object.
+    ///
+    /// [`push_object`]: crate::push_object
+    pub fn insert_object(&mut self, bundle: ObjectBundle<M>) {
+        let obj_id = bundle.id;
+        let old = self.objects.insert(obj_id, bundle.object);
+        assert!(old.is_none(), "object was already added to the component");
+
+        self.pointers.insert(obj_id, bundle.pointer);
+        self.refs.entry(obj_id).or_default();
+        self.buffers.add(obj_id, unsafe { &*bundle.pointer });
+        self.touch();
+        if bundle.masked {
+            self.masked_objects.insert(obj_id);
+        }
+        if let Some(state) = bundle.state {
+            self.states.entry(state).or_default();
+            self.object_states.insert(obj_id, state);
+        }
+        for (trait_id, erased, repeated) in bundle.traits {
+            if let Some(erased) = self.stash(trait_id, erased, repeated) {
+                self.attach_trait(trait_id, erased, repeated);
             }
         }
-    };
-}
+    }
 
-// Use the [`add_object`] macro not this one.
-#[doc(hidden)]
-#[macro_export]
-macro_rules! add_traits {
-    ($component:expr, $obj_type:ty, $obj_ptr:expr, $trait1:ty) => {{
-        paste! {
-            $component.add_trait::<dyn $trait1, $obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                [<get_ $trait1:lower _id>](),
-                $obj_ptr);
+    /// Moves all of the objects in other into this component (other's prototype is not
+    /// used). Fails if an object in either component is borrowed. Panics if both
+    /// components have the same object.
+    pub fn merge(&mut self, other: &mut Component<M>) -> Result<(), ComponentError> {
+        if let Some(obj_id) = self
+            .refs
+            .iter()
+            .chain(other.refs.iter())
+            .find_map(|(obj_id, refs)| refs.is_borrowed().then_some(*obj_id))
+        {
+            return Err(ComponentError::Borrowed(obj_id));
         }
-    }};
-
-    ($component:expr, $obj_type:ty, $obj_ptr:expr, $trait1:ty, $($trait2:ty),+) => {{
-        add_traits!($component, $obj_type, $obj_ptr, $trait1);
-        add_traits!($component, $obj_type, $obj_ptr, $($trait2),+)
-    }};
-}
+        assert!(
+            other
+                .objects
+                .keys()
+                .all(|id| !self.objects.contains_key(id)),
+            "object was already added to the component"
+        );
 
-// Use the [`add_object`] macro not this one.
-#[doc(hidden)]
-#[macro_export]
-macro_rules! add_repeated_traits {
-    ($component:expr, $obj_type:ty, $obj_ptr:expr, $trait1:ty) => {{
-        paste! {
-            $component.add_repeated_trait::<dyn $trait1, $obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                [<get_ $trait1:lower _id>](),
-                $obj_ptr);
+        let mut obj_ids: Vec<_> = other.objects.keys().copied().collect();
+        obj_ids.sort();
+        for obj_id in obj_ids {
+            let bundle = other.detach_object(obj_id);
+            self.insert_object(bundle);
         }
-    }};
-
-    ($component:expr, $obj_type:ty, $obj_ptr:expr, $trait1:ty, $($trait2:ty),+) => {{
-        add_repeated_traits!($component, $obj_type, $obj_ptr, $trait1);
-        add_repeated_traits!($component, $obj_type, $obj_ptr, $($trait2),+)
-    }};
-}
+        Ok(())
+    }
 
-/// Use this to add an object along with its associated traits to a component. Note that
-/// repeated traits are listed in a second optional list.
-///
-/// # Examples
-///
-/// ```
-/// #![feature(lazy_cell)]
-/// use gear_objects::*;
-/// use core::fmt;
-/// use paste::paste;
-/// use std::fmt::{Display};
-///
-/// struct Apple {}
-/// register_type!(Apple);
-///
-/// trait Fruit {
-///     fn eat(&self) -> String;
-/// }
-/// register_type!(Fruit);
-///
-/// impl Fruit for Apple {
-///     fn eat(&self) -> String {
-///         "yum!".to_owned()
-///     }
-/// }
-///
-/// impl fmt::Display for Apple {
-///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-///         write!(f, "Apple")
-///     }
-/// }
-/// register_type!(Display);
-///
-/// let apple = Apple {};
-/// let mut component = Component::new("apple");
-/// add_object!(component, Apple, apple, [Fruit], [Display]);
-/// ```
-#[macro_export]
-macro_rules! add_object {
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty]) => {{   // 1 0
-        paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+    /// Moves objects into a new component which uses the same prototype and starts out
+    /// in the same state. Fails if an object isn't part of the component or it is
+    /// borrowed (in which case nothing is moved).
+    pub fn split(
+        &mut self,
+        tag: &str,
+        obj_ids: &[ObjectId],
+    ) -> Result<Component<M>, ComponentError> {
+        for obj_id in obj_ids {
+            self.check_movable(*obj_id)?;
         }
-    }};
 
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty], [$trait2:ty]) => {{ // 1 1
-        paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $trait2);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+        let mut component = Component::with_id(next_component_id(tag));
+        component.prototype = self.prototype.clone();
+        component.state = self.state;
+        for obj_id in obj_ids {
+            let bundle = self.detach_object(*obj_id);
+            component.insert_object(bundle);
         }
-    }};
+        Ok(component)
+    }
 
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty], [$trait2:ty, $($trait3:ty),+]) => {{   // 1 +
-        paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $trait2);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $($trait3),+);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+    /// Returns a deep copy of the component with a new [`ComponentId`]. All of the objects
+    /// within the component must have been registered using the clone option (see
+    /// [`register_type`]). The prototype is shared, not copied. Fails if an object isn't
+    /// clonable or is borrowed.
+    ///
+    /// [`register_type`]: crate::register_type
+    pub fn try_clone(&self) -> Result<Component<M>, ComponentError> {
+        if let Some((obj_id, _)) = self.refs.iter().find(|(_, refs)| refs.is_borrowed()) {
+            return Err(ComponentError::Borrowed(*obj_id));
         }
-    }};
 
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty, $($trait2:ty),+]) => {{  // + 0
-        paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            add_traits!($component, $obj_type, obj_ptr, $($trait2),+);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+        let mut objects = FnvHashMap::default();
+        for (obj_id, object) in self.objects.iter() {
+            let clone = type_info(obj_id.type_id)
+                .and_then(|info| info.clone)
+                .ok_or(ComponentError::NotClonable(*obj_id))?
+                .0;
+            objects.insert(*obj_id, clone(&**object));
         }
-    }};
 
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty, $($trait2:ty),+], [$trait3:ty]) => {{   // + 1
-        paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            add_traits!($component, $obj_type, obj_ptr, $($trait2),+);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $trait3);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+        let mut component = Component::with_id(self.id.renew());
+        for (obj_id, object) in objects {
+            let pointer = Box::into_raw(object);
+            component
+                .objects
+                .insert(obj_id, unsafe { Box::from_raw(pointer) });
+            component.pointers.insert(obj_id, pointer);
+            component.refs.insert(obj_id, M::Refs::default());
+            component.buffers.add(obj_id, unsafe { &*pointer });
         }
+
+        let pointers = &component.pointers;
+        let rebase = |e: &TypeErasedPointer| e.rebase(pointers[&e.object_id] as *mut ());
+        let traits = self.traits.iter().map(|(k, e)| (*k, rebase(e))).collect();
+        let repeated = self
+            .repeated
+            .iter()
+            .map(|(k, v)| (*k, v.iter().map(rebase).collect()))
+            .collect();
+        let shadowed = self
+            .shadowed
+            .iter()
+            .map(|(k, v)| (*k, v.iter().map(rebase).collect()))
+            .collect();
+        let states = self
+            .states
+            .iter()
+            .map(|(k, v)| {
+                (
+                    *k,
+                    v.iter().map(|(id, e, r)| (*id, rebase(e), *r)).collect(),
+                )
+            })
+            .collect();
+
+        component.traits = traits;
+        component.repeated = repeated;
+        component.shadowed = shadowed;
+        component.states = states;
+        component.implied = self.implied.clone();
+        component.masked_traits = self.masked_traits.clone();
+        component.masked_objects = self.masked_objects.clone();
+        component.object_states = self.object_states.clone();
+        component.state = self.state;
+        component.prototype = self.prototype.clone();
+        Ok(component)
+    }
+
+    // Used by views: calls copy for each object within the component and its prototypes
+    // (while the object is borrowed) and returns the visible traits rebased onto the
+    // copies. Adapted traits are not included.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn copy_traits(
+        &self,
+        mut copy: impl FnMut(ComponentId, ObjectId, u32, &dyn Any) -> Result<*mut (), ComponentError>,
+    ) -> Result<
+        (
+            FnvHashMap<(TypeId, InstanceKey), TypeErasedPointer>,
+            FnvHashMap<TypeId, Vec<TypeErasedPointer>>,
+        ),
+        ComponentError,
+    > {
+        let mut traits = FnvHashMap::default();
+        let mut repeated: FnvHashMap<TypeId, Vec<TypeErasedPointer>> = FnvHashMap::default();
+        for c in self.chain() {
+            let mut copies = FnvHashMap::default();
+            for (obj_id, pointer) in c.pointers.iter() {
+                let refs = c.refs.get(obj_id).unwrap();
+                if !refs.try_borrow() {
+                    return Err(ComponentError::Borrowed(*obj_id));
+                }
+                let result = copy(c.id, *obj_id, refs.changed(), unsafe { &**pointer });
+                refs.release();
+                copies.insert(*obj_id, result?);
+            }
+
+            let visible = |e: &&TypeErasedPointer| !c.masked_objects.contains(&e.object_id);
+            for (key, erased) in c.traits.iter() {
+                if visible(&erased) && !self.is_masked(key.0) {
+                    traits
+                        .entry(*key)
+                        .or_insert_with(|| erased.rebase(copies[&erased.object_id]));
+                }
+            }
+            for (trait_id, pointers) in c.repeated.iter() {
+                if !self.is_masked(*trait_id) {
+                    repeated.entry(*trait_id).or_default().extend(
+                        pointers
+                            .iter()
+                            .filter(visible)
+                            .map(|e| e.rebase(copies[&e.object_id])),
+                    );
+                }
+            }
+        }
+        Ok((traits, repeated))
+    }
+
+    // Returns the objects sorted by id along with their traits. Traits are listed with
+    // their depth within their slot (or state) so that overrides and the order of
+    // repeated traits can be restored by re-adding the traits in depth order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn saved_objects(&self) -> Vec<SavedObject<'_>> {
+        let mut traits: FnvHashMap<ObjectId, Vec<SavedTrait>> = FnvHashMap::default();
+        let mut save = |erased: &TypeErasedPointer, trait_id, repeated, depth, implied| {
+            traits
+                .entry(erased.object_id)
+                .or_default()
+                .push(SavedTrait {
+                    trait_id,
+                    repeated,
+                    depth,
+                    implied,
+                })
+        };
+        for (key, erased) in self.traits.iter() {
+            let depth = self.shadowed.get(key).map_or(0, |pointers| pointers.len());
+            save(erased, key.0, false, depth, self.implied.contains(key));
+        }
+        for (key, pointers) in self.shadowed.iter() {
+            for (depth, erased) in pointers.iter().enumerate() {
+                save(erased, key.0, false, depth, false);
+            }
+        }
+        for (trait_id, pointers) in self.repeated.iter() {
+            for (depth, erased) in pointers.iter().enumerate() {
+                save(erased, *trait_id, true, depth, false);
+            }
+        }
+        for stashed in self.states.values() {
+            for (depth, (trait_id, erased, repeated)) in stashed.iter().enumerate() {
+                save(erased, *trait_id, *repeated, depth, false);
+            }
+        }
+
+        let mut objects: Vec<_> = self
+            .objects
+            .iter()
+            .map(|(obj_id, object)| SavedObject {
+                id: *obj_id,
+                object: &**object,
+                state: self.object_states.get(obj_id).copied(),
+                masked: self.masked_objects.contains(obj_id),
+                traits: traits.remove(obj_id).unwrap_or_default(),
+            })
+            .collect();
+        objects.sort_by_key(|object| object.id);
+        objects
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn masked_trait_ids(&self) -> Vec<TypeId> {
+        self.masked_traits.iter().copied().collect()
+    }
+
+    // Rough number of bytes used by the objects (not including memory they point to).
+    pub(crate) fn objects_size(&self) -> usize {
+        self.objects
+            .values()
+            .map(|object| mem::size_of_val(&**object))
+            .sum()
+    }
+
+    // Used by transactions to overwrite the object state that mutable finds write to.
+    pub(crate) fn writable_any_mut(&mut self, obj_id: ObjectId) -> Option<&mut dyn Any> {
+        match self.buffers.get(obj_id) {
+            Some(back) => Some(unsafe { &mut *back }),
+            None => self.object_any_mut(obj_id),
+        }
+    }
+
+    // Used by replication and undo to overwrite objects. The object itself isn't moved so
+    // trait pointers remain valid. Pending writes to a back buffer are discarded so that
+    // the next swap doesn't undo the overwrite.
+    pub(crate) fn object_any_mut(&mut self, obj_id: ObjectId) -> Option<&mut dyn Any> {
+        self.refs.get(&obj_id)?.mark_changed();
+        self.buffers.discard(obj_id);
+        self.pointers
+            .get(&obj_id)
+            .map(|pointer| unsafe { &mut **pointer })
+    }
+
+    // Used when deserializing: the state should be restored before the objects.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_state(&mut self, state: Option<InstanceKey>) {
+        self.state = state;
+    }
+
+    // Used when deserializing. Returns a pointer to the boxed object.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_object(
+        &mut self,
+        obj_id: ObjectId,
+        object: Box<dyn Any>,
+        state: Option<InstanceKey>,
+        masked: bool,
+    ) -> *mut () {
+        let pointer = Box::into_raw(object);
+        let old = self
+            .objects
+            .insert(obj_id, unsafe { Box::from_raw(pointer) });
+        assert!(old.is_none(), "object was already added to the component");
+
+        self.pointers.insert(obj_id, pointer);
+        self.refs.entry(obj_id).or_default();
+        self.buffers.add(obj_id, unsafe { &*pointer });
+        if masked {
+            self.masked_objects.insert(obj_id);
+        }
+        if let Some(state) = state {
+            self.states.entry(state).or_default();
+            self.object_states.insert(obj_id, state);
+        }
+        pointer as *mut ()
+    }
+
+    // Used when deserializing. Traits should be restored in depth order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_trait(
+        &mut self,
+        trait_id: TypeId,
+        erased: TypeErasedPointer,
+        repeated: bool,
+        implied: bool,
+    ) {
+        let key = (trait_id, erased.object_id.key);
+        if let Some(erased) = self.stash(trait_id, erased, repeated) {
+            self.attach_trait(trait_id, erased, repeated);
+        }
+        if implied {
+            self.implied.insert(key);
+        }
+    }
+
+    fn check_movable(&self, obj_id: ObjectId) -> Result<(), ComponentError> {
+        let refs = self
+            .refs
+            .get(&obj_id)
+            .ok_or(ComponentError::MissingObject(obj_id))?;
+        if refs.is_borrowed() {
+            Err(ComponentError::Borrowed(obj_id))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Removes an object along with all of its traits.
+    fn detach_object(&mut self, obj_id: ObjectId) -> ObjectBundle<M> {
+        self.touch();
+        let mut traits = self.detach_traits(obj_id);
+        for stashed in self.states.values_mut() {
+            let (removed, kept): (Vec<_>, Vec<_>) = mem::take(stashed)
+                .into_iter()
+                .partition(|(_, e, _)| e.object_id == obj_id);
+            *stashed = kept;
+            traits.extend(removed);
+        }
+
+        self.refs.remove(&obj_id);
+        self.buffers.remove(obj_id);
+        ObjectBundle {
+            id: obj_id,
+            object: self.objects.remove(&obj_id).unwrap(),
+            pointer: self.pointers.remove(&obj_id).unwrap(),
+            traits,
+            state: self.object_states.remove(&obj_id),
+            masked: self.masked_objects.remove(&obj_id),
+            marker: PhantomData,
+        }
+    }
+
+    // Normally the [`remove_slot`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn remove_slot<Trait>(
+        &mut self,
+        trait_id: TypeId,
+        slot: &str,
+    ) -> Result<Box<dyn Any>, ComponentError>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let slot = instance_key(slot);
+        let erased = self
+            .traits
+            .get(&(trait_id, slot))
+            .ok_or(ComponentError::EmptySlot(trait_id, slot))?;
+        self.remove_object(erased.object_id)
+    }
+
+    // Normally the [`mask_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn mask_trait(&mut self, trait_id: TypeId) {
+        self.masked_traits.insert(trait_id);
+        self.touch();
+    }
+
+    // Normally the [`unmask_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn unmask_trait(&mut self, trait_id: TypeId) {
+        self.masked_traits.remove(&trait_id);
+        self.touch();
+    }
+
+    // Normally the [`mask_object`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn mask_object(&mut self, obj_id: ObjectId) -> Result<(), ComponentError> {
+        if !self.objects.contains_key(&obj_id) {
+            return Err(ComponentError::MissingObject(obj_id));
+        }
+        self.masked_objects.insert(obj_id);
+        self.touch();
+        Ok(())
+    }
+
+    // Normally the [`unmask_object`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn unmask_object(&mut self, obj_id: ObjectId) -> Result<(), ComponentError> {
+        if !self.objects.contains_key(&obj_id) {
+            return Err(ComponentError::MissingObject(obj_id));
+        }
+        self.masked_objects.remove(&obj_id);
+        self.touch();
+        Ok(())
+    }
+
+    // Normally the [`add_state_object`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn add_state_object<Object>(
+        &mut self,
+        state: &str,
+        obj_id: ObjectId,
+        object: Object,
+    ) -> *mut Object
+    where
+        Object: 'static,
+        M: Admits<Object>,
+    {
+        let state = instance_key(state);
+        self.states.entry(state).or_default();
+        self.object_states.insert(obj_id, state);
+        self.add_object(obj_id, object)
+    }
+
+    /// Switches the component to a state whose objects were added using
+    /// [`add_state_object`]. The traits of the old state's objects are removed (restoring
+    /// any traits they overrode) and the traits of the new state's objects are added
+    /// (overriding existing traits). Fails if the state is unknown or if an object in the
+    /// old or new state is borrowed (in which case nothing changes). Panics if the name
+    /// is longer than 16 bytes.
+    ///
+    /// [`add_state_object`]: crate::add_state_object
+    pub fn set_state(&mut self, name: &str) -> Result<(), ComponentError> {
+        let name = instance_key(name);
+        if !self.states.contains_key(&name) {
+            return Err(ComponentError::MissingState(name));
+        }
+        if self.state == Some(name) {
+            return Ok(());
+        }
+        if let Some((obj_id, _)) = self.object_states.iter().find(|(obj_id, state)| {
+            (**state == name || Some(**state) == self.state)
+                && self.refs.get(obj_id).unwrap().is_borrowed()
+        }) {
+            return Err(ComponentError::Borrowed(*obj_id));
+        }
+
+        if let Some(old) = self.state {
+            let obj_ids: Vec<_> = self
+                .object_states
+                .iter()
+                .filter(|(_, state)| **state == old)
+                .map(|(obj_id, _)| *obj_id)
+                .collect();
+            for obj_id in obj_ids {
+                let detached = self.detach_traits(obj_id);
+                self.states.get_mut(&old).unwrap().extend(detached);
+            }
+        }
+
+        self.state = Some(name);
+        for (trait_id, erased, repeated) in mem::take(self.states.get_mut(&name).unwrap()) {
+            self.attach_trait(trait_id, erased, repeated);
+        }
+        self.touch();
+        Ok(())
+    }
+
+    /// Makes the writes to double buffered objects visible: mutable finds write to a back
+    /// copy so that components can be updated in any order from the previous state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gear_objects::*;
+    /// use paste::paste;
+    ///
+    /// trait Counter {
+    ///     fn count(&self) -> i32;
+    ///     fn increment(&mut self);
+    /// }
+    /// register_type!(Counter);
+    ///
+    /// #[derive(Clone)]
+    /// struct Clicks {
+    ///     count: i32,
+    /// }
+    /// register_type!(Clicks, double_buffered);
+    ///
+    /// impl Counter for Clicks {
+    ///     fn count(&self) -> i32 {
+    ///         self.count
+    ///     }
+    ///
+    ///     fn increment(&mut self) {
+    ///         self.count += 1;
+    ///     }
+    /// }
+    ///
+    /// let mut component = Component::new("button");
+    /// add_object!(component, Clicks, Clicks { count: 0 }, [Counter]);
+    ///
+    /// find_trait_mut!(component, Counter).unwrap().increment();
+    /// find_trait_mut!(component, Counter).unwrap().increment();
+    /// assert_eq!(find_trait!(component, Counter).unwrap().count(), 0);
+    ///
+    /// component.swap_buffers();
+    /// assert_eq!(find_trait!(component, Counter).unwrap().count(), 2);
+    /// ```
+    pub fn swap_buffers(&mut self) {
+        for obj_id in self.buffers.swap(&self.pointers) {
+            self.refs.get(&obj_id).unwrap().mark_changed();
+        }
+    }
+
+    /// Returns true if an object was mutated or added or the component's traits, states,
+    /// or masks were changed at or after tick (see [`change_tick`]). Objects within the
+    /// prototype are not checked.
+    pub fn changed_since(&self, tick: u32) -> bool {
+        self.changed >= tick || self.refs.values().any(|refs| refs.changed() >= tick)
+    }
+
+    /// Returns the objects that were mutated or added at or after tick (see
+    /// [`change_tick`]) in sorted order.
+    pub fn changed_objects(&self, tick: u32) -> Vec<ObjectId> {
+        let mut obj_ids: Vec<_> = self
+            .refs
+            .iter()
+            .filter(|(_, refs)| refs.changed() >= tick)
+            .map(|(obj_id, _)| *obj_id)
+            .collect();
+        obj_ids.sort();
+        obj_ids
+    }
+
+    fn touch(&mut self) {
+        self.changed = change_tick();
+    }
+
+    /// Returns the name of the current state, see [`set_state`].
+    ///
+    /// [`set_state`]: Component::set_state
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_ref().map(|name| name.as_str())
+    }
+
+    // Normally the [`find_slots`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_slots<Trait>(&self, trait_id: TypeId) -> Vec<InstanceKey>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let mut ids = vec![trait_id];
+        ids.extend(find_adapters(trait_id).map(|(id, _)| id));
+        let mut slots: Vec<InstanceKey> = self
+            .chain()
+            .flat_map(|c| c.traits.keys())
+            .filter_map(|(id, slot)| if ids.contains(id) { Some(*slot) } else { None })
+            .collect();
+        slots.sort();
+        slots.dedup();
+        slots.retain(|slot| {
+            let key = (trait_id, *slot);
+            self.lookup(key).is_some() || self.lookup_adapted(key).is_some()
+        });
+        slots
+    }
+
+    // Normally the [`has_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn has<Trait>(&self, trait_id: TypeId, key: &str) -> bool
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let Ok(key) = InstanceKey::try_from_str(key) else {
+            return false; // too long to be a key
+        };
+        let key = (trait_id, key);
+        self.lookup(key).is_some() || self.lookup_adapted(key).is_some()
+    }
+
+    // Normally the [`find_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find<Trait>(&self, trait_id: TypeId, key: &str) -> Option<RefTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let key = (trait_id, InstanceKey::try_from_str(key).ok()?);
+        if let Some((erased, refs)) = self.lookup(key) {
+            let r = unsafe { erased.borrow_trait::<Trait, _>(refs) };
+            Some(r)
+        } else if let Some((erased, refs, adapt)) = self.lookup_adapted(key) {
+            let r = unsafe { erased.borrow_adapted::<Trait, _>(refs, adapt) };
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    // Normally the [`find_trait_mut`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_mut<Trait>(
+        &self,
+        trait_id: TypeId,
+        key: &str,
+    ) -> Option<RefMutTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let key = (trait_id, InstanceKey::try_from_str(key).ok()?);
+        if let Some((erased, refs)) = self.lookup(key) {
+            Some(unsafe { self.borrow_trait_mut::<Trait>(erased, refs, None) })
+        } else if let Some((erased, refs, adapt)) = self.lookup_adapted(key) {
+            Some(unsafe { self.borrow_trait_mut::<Trait>(erased, refs, Some(adapt)) })
+        } else {
+            None
+        }
+    }
+
+    // Normally the [`find_object`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_object<Object>(&self, obj_id: ObjectId) -> Option<RefTrait<'_, Object, M::Refs>>
+    where
+        Object: 'static,
+    {
+        self.object_pointer::<Object>(obj_id)
+            .map(|(pointer, refs)| unsafe { borrow_pointer(pointer, refs) })
+    }
+
+    // Normally the [`find_object_mut`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_object_mut<Object>(
+        &self,
+        obj_id: ObjectId,
+    ) -> Option<RefMutTrait<'_, Object, M::Refs>>
+    where
+        Object: 'static,
+    {
+        let r = self
+            .writable_pointer::<Object>(obj_id)
+            .map(|(pointer, refs)| unsafe { borrow_pointer_mut(pointer, refs) });
+        self.record(obj_id);
+        r
+    }
+
+    // Normally the [`find_trait_of`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_trait_of<Object, Trait>(
+        &self,
+        obj_id: ObjectId,
+    ) -> Option<RefTrait<'_, Trait, M::Refs>>
+    where
+        Object: Unsize<Trait> + 'static,
+        Trait: ?Sized,
+    {
+        self.object_pointer::<Object>(obj_id)
+            .map(|(pointer, refs)| unsafe {
+                let pointer: *mut Trait = pointer;
+                borrow_pointer(pointer, refs)
+            })
+    }
+
+    // Normally the [`find_trait_of_mut`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_trait_of_mut<Object, Trait>(
+        &self,
+        obj_id: ObjectId,
+    ) -> Option<RefMutTrait<'_, Trait, M::Refs>>
+    where
+        Object: Unsize<Trait> + 'static,
+        Trait: ?Sized,
+    {
+        let r = self
+            .writable_pointer::<Object>(obj_id)
+            .map(|(pointer, refs)| unsafe {
+                let pointer: *mut Trait = pointer;
+                borrow_pointer_mut(pointer, refs)
+            });
+        self.record(obj_id);
+        r
+    }
+
+    pub(crate) fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    pub(crate) fn signals(&self) -> &Signals<M> {
+        &self.signals
+    }
+
+    // Returns true if any object within the component, or its prototypes, is borrowed.
+    pub(crate) fn is_borrowed(&self) -> bool {
+        iter::successors(Some(self), |c| c.prototype.as_deref())
+            .any(|c| c.refs.values().any(|refs| refs.is_borrowed()) || c.buffers.is_borrowed())
+    }
+
+    // Snapshots the object if a transaction is in progress. This is called after the object
+    // has been mutably borrowed so that nothing else can be changing it.
+    fn record(&self, obj_id: ObjectId) {
+        if self.journal.is_active() {
+            let pointer = self.buffers.get(obj_id);
+            if let Some(pointer) = pointer.or_else(|| self.pointers.get(&obj_id).copied()) {
+                self.journal.record(obj_id, unsafe { &*pointer });
+            }
+        }
+    }
+
+    // Mutably borrows a trait (adapting it if adapt is set). Double buffered objects are
+    // borrowed from their back buffer.
+    unsafe fn borrow_trait_mut<'a, Trait>(
+        &'a self,
+        erased: &TypeErasedPointer,
+        refs: &'a M::Refs,
+        adapt: Option<AdaptFn>,
+    ) -> RefMutTrait<'a, Trait, M::Refs>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let back = self.writable(erased.object_id, refs);
+        let rebased = back.map(|(pointer, _)| erased.rebase(pointer as *mut ()));
+        let target = rebased.as_ref().unwrap_or(erased);
+        let refs = back.map_or(refs, |(_, refs)| refs);
+        let r = match adapt {
+            Some(adapt) => target.borrow_adapted_mut::<Trait, _>(refs, adapt),
+            None => target.borrow_trait_mut::<Trait, _>(refs),
+        };
+        self.record(erased.object_id);
+        r
+    }
+
+    // Like object_pointer except that double buffered objects return their back buffer.
+    fn writable_pointer<Object: 'static>(
+        &self,
+        obj_id: ObjectId,
+    ) -> Option<(*mut Object, &M::Refs)> {
+        let (pointer, refs) = self.object_pointer::<Object>(obj_id)?;
+        match self.writable(obj_id, refs) {
+            Some((back, refs)) => Some((back as *mut Object, refs)),
+            None => Some((pointer, refs)),
+        }
+    }
+
+    // Returns the back buffer and its refs if the object is double buffered. refs is used
+    // to find the component within the prototype chain that owns the object.
+    fn writable<'a>(
+        &'a self,
+        obj_id: ObjectId,
+        refs: &M::Refs,
+    ) -> Option<(*mut dyn Any, &'a M::Refs)> {
+        self.chain()
+            .filter(|c| !c.buffers.is_empty())
+            .find(|c| c.refs.get(&obj_id).is_some_and(|r| ptr::eq(r, refs)))
+            .and_then(|c| c.buffers.back(obj_id, c.pointers[&obj_id]))
+    }
+
+    // Returns a pointer to the object and its refs, searching prototypes if needed.
+    fn object_pointer<Object: 'static>(&self, obj_id: ObjectId) -> Option<(*mut Object, &M::Refs)> {
+        self.chain().find_map(|c| {
+            let pointer = *c.pointers.get(&obj_id)?;
+            if unsafe { (*pointer).is::<Object>() } {
+                Some((pointer as *mut Object, c.refs.get(&obj_id).unwrap()))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Normally the [`find_super_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_super<Trait>(
+        &self,
+        trait_id: TypeId,
+        obj_id: ObjectId,
+    ) -> Option<RefTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        match self.super_pointer(trait_id, obj_id) {
+            Some(erased) => {
+                let refs = self.refs.get(&erased.object_id).unwrap();
+                Some(unsafe { erased.borrow_trait::<Trait, _>(refs) })
+            }
+            None if self.overrides_prototype(trait_id, obj_id) => self
+                .prototype
+                .as_ref()
+                .and_then(|p| p.find::<Trait>(trait_id, &obj_id.key)),
+            None => None,
+        }
+    }
+
+    // Normally the [`find_super_trait_mut`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_super_mut<Trait>(
+        &self,
+        trait_id: TypeId,
+        obj_id: ObjectId,
+    ) -> Option<RefMutTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        match self.super_pointer(trait_id, obj_id) {
+            Some(erased) => {
+                let refs = self.refs.get(&erased.object_id).unwrap();
+                Some(unsafe { self.borrow_trait_mut::<Trait>(erased, refs, None) })
+            }
+            None if self.overrides_prototype(trait_id, obj_id) => self
+                .prototype
+                .as_ref()
+                .and_then(|p| p.find_mut::<Trait>(trait_id, &obj_id.key)),
+            None => None,
+        }
+    }
+
+    // Returns true if obj_id is the bottom-most local provider of the trait (and so
+    // overrides the prototype's trait).
+    fn overrides_prototype(&self, trait_id: TypeId, obj_id: ObjectId) -> bool {
+        let key = (trait_id, obj_id.key);
+        match self.shadowed.get(&key) {
+            Some(pointers) => pointers[0].object_id == obj_id,
+            None => self.traits.get(&key).is_some_and(|e| e.object_id == obj_id),
+        }
+    }
+
+    // Returns the trait pointer that obj_id's trait overrides.
+    fn super_pointer(&self, trait_id: TypeId, obj_id: ObjectId) -> Option<&TypeErasedPointer> {
+        let key = (trait_id, obj_id.key);
+        let pointers = self.shadowed.get(&key)?;
+        if self.traits.get(&key)?.object_id == obj_id {
+            pointers.last()
+        } else {
+            let index = pointers.iter().position(|e| e.object_id == obj_id)?;
+            index.checked_sub(1).map(|i| &pointers[i])
+        }
+    }
+
+    // Normally the [`find_repeated_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_repeated<Trait>(
+        &self,
+        trait_id: TypeId,
+    ) -> impl Iterator<Item = RefTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let masked = self.is_masked(trait_id);
+        let adapted =
+            find_adapters(trait_id)
+                .filter(move |_| !masked)
+                .flat_map(move |(source_id, adapt)| {
+                    self.lookup_repeated(source_id)
+                        .map(move |(e, refs)| unsafe { e.borrow_adapted::<Trait, _>(refs, adapt) })
+                });
+        self.lookup_repeated(trait_id)
+            .map(|(e, refs)| unsafe { e.borrow_trait::<Trait, _>(refs) })
+            .chain(adapted)
+    }
+
+    // Normally the [`find_repeated_trait_mut`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_repeated_mut<Trait>(
+        &self,
+        trait_id: TypeId,
+    ) -> impl Iterator<Item = RefMutTrait<'_, Trait, M::Refs>>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let masked = self.is_masked(trait_id);
+        let adapted =
+            find_adapters(trait_id)
+                .filter(move |_| !masked)
+                .flat_map(move |(source_id, adapt)| {
+                    self.lookup_repeated(source_id)
+                        .map(move |(e, refs)| unsafe {
+                            self.borrow_trait_mut::<Trait>(e, refs, Some(adapt))
+                        })
+                });
+        self.lookup_repeated(trait_id)
+            .map(|(e, refs)| unsafe { self.borrow_trait_mut::<Trait>(e, refs, None) })
+            .chain(adapted)
+    }
+}
+
+/// Use this for all trait and object types used within components. This may be followed
+/// by options:
+/// - `version = N` sets the version reported by [`TypeInfo`], defaults to 1.
+/// - `supertraits = [A, B]` for traits: objects added with this trait will also expose
+///   the supertraits (unless the component already has them).
+/// - `repeated_supertraits = [A, B]` like supertraits except that they are exposed as
+///   repeated traits.
+/// - `clone` for objects that implement Clone: allows components containing the object
+///   to be cloned with [`Component::try_clone`].
+/// - `double_buffered` for objects that implement Clone: mutable finds write to a copy of
+///   the object which becomes visible when [`Component::swap_buffers`] is called. Implies
+///   clone.
+/// - `traits = [A, B]` and `repeated_traits = [A, B]` for objects: the traits the
+///   object may expose. These are needed to rebuild trait pointers when deserializing.
+/// - `serde` for `Send + Sync` objects that implement Serialize and Deserialize: allows
+///   components containing the object to be serialized (requires the serde feature).
+///
+/// # Examples
+///
+/// ```
+/// use core::fmt::{self, Debug};
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Fruit {
+///     fn eat(&self) -> String;
+/// }
+/// register_type!(Fruit);
+///
+/// trait FruitV2: Fruit + Debug {
+///     fn peel(&mut self);
+/// }
+/// register_type!(FruitV2, version = 2, supertraits = [Fruit], repeated_supertraits = [Debug]);
+///
+/// #[derive(Debug)]
+/// struct Apple {}
+/// register_type!(Apple);
+///
+/// impl Fruit for Apple {
+///     fn eat(&self) -> String {
+///         "yum!".to_owned()
+///     }
+/// }
+///
+/// impl FruitV2 for Apple {
+///     fn peel(&mut self) {}
+/// }
+///
+/// let mut component = Component::new("apple");
+/// add_object!(component, Apple, Apple {}, [FruitV2]);
+/// assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+/// assert_eq!(find_repeated_trait!(component, Debug).count(), 1);
+/// ```
+#[macro_export]
+macro_rules! register_type {
+    ($type:ty $(, $($option:tt)+)?) => {
+        paste! {
+            pub fn [<get_ $type:lower _id>]() -> TypeId {
+                unique_type_id!(type_options!(
+                    $type,
+                    TypeInfo::new(stringify!($type))
+                    $(, $($option)+)?
+                ))
+            }
+        }
+    };
+}
+
+// Use the [`register_type`] macro not this one.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! type_options {
+    ($type:ty, $info:expr) => {
+        $info
+    };
+
+    ($type:ty, $info:expr, clone $(, $($rest:tt)+)?) => {
+        type_options!($type, $info.with_clone(clone_object::<$type>, assign_object::<$type>) $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, double_buffered $(, $($rest:tt)+)?) => {
+        type_options!($type, $info
+            .with_clone(clone_object::<$type>, assign_object::<$type>)
+            .with_double_buffering(swap_objects::<$type>) $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, version = $version:expr $(, $($rest:tt)+)?) => {
+        type_options!($type, $info.with_version($version) $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, supertraits = [$($super:ty),*] $(, $($rest:tt)+)?) => {
+        type_options!($type, paste! {
+            $info $(.with_supertrait(
+                [<get_ $super:lower _id>](),
+                false,
+                upcast_trait::<dyn $type, dyn $super>))*
+        } $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, repeated_supertraits = [$($super:ty),*] $(, $($rest:tt)+)?) => {
+        type_options!($type, paste! {
+            $info $(.with_supertrait(
+                [<get_ $super:lower _id>](),
+                true,
+                upcast_trait::<dyn $type, dyn $super>))*
+        } $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, traits = [$($trait:ty),*] $(, $($rest:tt)+)?) => {
+        type_options!($type, paste! {
+            $info $(.with_trait(
+                [<get_ $trait:lower _id>](),
+                false,
+                expose_trait::<$type, dyn $trait>))*
+        } $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, repeated_traits = [$($trait:ty),*] $(, $($rest:tt)+)?) => {
+        type_options!($type, paste! {
+            $info $(.with_trait(
+                [<get_ $trait:lower _id>](),
+                true,
+                expose_trait::<$type, dyn $trait>))*
+        } $(, $($rest)+)?)
+    };
+
+    ($type:ty, $info:expr, serde $(, $($rest:tt)+)?) => {
+        type_options!($type, $info.with_serde(serde_fns::<$type>()) $(, $($rest)+)?)
+    };
+}
+
+// Use the [`add_object`] macro not this one.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! add_traits {
+    ($component:expr, $obj_type:ty, $obj_id:expr, $obj_ptr:expr, $($trait:ty),*) => {{
+        paste! {
+            $(
+                $component.add_trait::<dyn $trait, $obj_type>(
+                    $obj_id,
+                    [<get_ $trait:lower _id>](),
+                    $obj_ptr);
+            )*
+        }
+    }};
+}
+
+// Use the [`push_object`] macro not this one.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! override_traits {
+    ($component:expr, $obj_type:ty, $obj_id:expr, $obj_ptr:expr, $($trait:ty),*) => {{
+        paste! {
+            $(
+                $component.override_trait::<dyn $trait, $obj_type>(
+                    $obj_id,
+                    [<get_ $trait:lower _id>](),
+                    $obj_ptr);
+            )*
+        }
+    }};
+}
+
+// Use the [`add_object`] macro not this one.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! add_repeated_traits {
+    ($component:expr, $obj_type:ty, $obj_id:expr, $obj_ptr:expr, $($trait:ty),*) => {{
+        paste! {
+            $(
+                $component.add_repeated_trait::<dyn $trait, $obj_type>(
+                    $obj_id,
+                    [<get_ $trait:lower _id>](),
+                    $obj_ptr);
+            )*
+        }
+    }};
+}
+
+/// Use this to add an object along with its associated traits to a component. Note that
+/// repeated traits are listed in a second optional list.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use core::fmt;
+/// use paste::paste;
+/// use std::fmt::{Display};
+///
+/// struct Apple {}
+/// register_type!(Apple);
+///
+/// trait Fruit {
+///     fn eat(&self) -> String;
+/// }
+/// register_type!(Fruit);
+///
+/// impl Fruit for Apple {
+///     fn eat(&self) -> String {
+///         "yum!".to_owned()
+///     }
+/// }
+///
+/// impl fmt::Display for Apple {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "Apple")
+///     }
+/// }
+/// register_type!(Display);
+///
+/// let apple = Apple {};
+/// let mut component = Component::new("apple");
+/// add_object!(component, Apple, apple, [Fruit], [Display]);
+/// ```
+#[macro_export]
+macro_rules! add_object {
+    ($component:expr, $obj_type:ty, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        add_keyed_object!($component, $obj_type, "", $object, [$($trait1),*] $(, [$($trait2),*])?)
+    }};
+}
+
+/// Like [`add_object`] except that the object is identified by an instance key which
+/// allows multiple objects of the same type to be added to a component. Non-repeated
+/// traits are then found using the key, repeated traits include all of the instances.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Hunger {
+///     fn get(&self) -> i32;
+/// }
+/// register_type!(Hunger);
+///
+/// struct Hungers {
+///     hunger: i32,
+/// }
+/// register_type!(Hungers);
+///
+/// impl Hunger for Hungers {
+///     fn get(&self) -> i32 {
+///         self.hunger
+///     }
+/// }
+///
+/// let mut component = Component::new("rabbit");
+/// add_keyed_object!(component, Hungers, "food", Hungers { hunger: 10 }, [Hunger]);
+/// add_keyed_object!(component, Hungers, "water", Hungers { hunger: 3 }, [Hunger]);
+///
+/// let thirst = find_trait!(component, Hunger, "water");
+/// assert_eq!(thirst.unwrap().get(), 3);
+/// ```
+#[macro_export]
+macro_rules! add_keyed_object {
+    ($component:expr, $obj_type:ty, $key:expr, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        paste! {
+            let obj_id = ObjectId::new([<get_ $obj_type:lower _id>](), $key);
+            let obj_ptr = $component.add_object::<$obj_type>(obj_id, $object);
+            add_traits!($component, $obj_type, obj_id, obj_ptr, $($trait1),*);
+            $(add_repeated_traits!($component, $obj_type, obj_id, obj_ptr, $($trait2),*);)?
+        }
+    }};
+}
+
+/// Like [`add_object`] except that the object's (non-repeated) traits override traits
+/// already in the component. Finds will return the new object's traits and the object
+/// can use [`find_super_trait`] to call the trait it overrode. Removing the object
+/// restores the overridden traits.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Render {
+///     fn render(&self) -> String;
+/// }
+/// register_type!(Render);
+///
+/// struct Button {}
+/// register_type!(Button);
+///
+/// impl Render for Button {
+///     fn render(&self) -> String {
+///         "button".to_owned()
+///     }
+/// }
+///
+/// struct Highlight {}
+/// register_type!(Highlight);
+///
+/// impl Render for Highlight {
+///     fn render(&self) -> String {
+///         "highlighted".to_owned()
+///     }
+/// }
+///
+/// let mut component = Component::new("button");
+/// add_object!(component, Button, Button {}, [Render]);
+/// push_object!(component, Highlight, Highlight {}, [Render]);
+/// assert_eq!(find_trait!(component, Render).unwrap().render(), "highlighted");
+///
+/// remove_object!(component, Highlight).unwrap();
+/// assert_eq!(find_trait!(component, Render).unwrap().render(), "button");
+/// ```
+#[macro_export]
+macro_rules! push_object {
+    ($component:expr, $obj_type:ty, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        push_keyed_object!($component, $obj_type, "", $object, [$($trait1),*] $(, [$($trait2),*])?)
+    }};
+}
+
+/// Like [`push_object`] except that the object is identified by an instance key (and
+/// overrides the traits in the slot with that key).
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! push_keyed_object {
+    ($component:expr, $obj_type:ty, $key:expr, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        paste! {
+            let obj_id = ObjectId::new([<get_ $obj_type:lower _id>](), $key);
+            let obj_ptr = $component.add_object::<$obj_type>(obj_id, $object);
+            override_traits!($component, $obj_type, obj_id, obj_ptr, $($trait1),*);
+            $(add_repeated_traits!($component, $obj_type, obj_id, obj_ptr, $($trait2),*);)?
+        }
+    }};
+}
+
+/// Like [`add_object`] except that the object belongs to a named state and only exposes
+/// its traits while the component is in that state, see [`Component::set_state`]. This
+/// allows a component to act like a state machine where the objects providing traits
+/// change with the state. Objects in the current state override traits provided by
+/// objects that don't belong to a state.
+///
+/// # Panics
+///
+/// If the state name is longer than 16 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Render {
+///     fn render(&self) -> String;
+/// }
+/// register_type!(Render);
+///
+/// struct Rabbit {}
+/// register_type!(Rabbit);
+///
+/// impl Render for Rabbit {
+///     fn render(&self) -> String {
+///         "r".to_owned()
+///     }
+/// }
+///
+/// struct Skeleton {}
+/// register_type!(Skeleton);
+///
+/// impl Render for Skeleton {
+///     fn render(&self) -> String {
+///         "*".to_owned()
+///     }
+/// }
+///
+/// let mut component = Component::new("rabbit");
+/// add_state_object!(component, "alive", Rabbit, Rabbit {}, [Render]);
+/// add_state_object!(component, "dead", Skeleton, Skeleton {}, [Render]);
+/// assert!(!has_trait!(component, Render));
+///
+/// component.set_state("alive").unwrap();
+/// assert_eq!(find_trait!(component, Render).unwrap().render(), "r");
+///
+/// component.set_state("dead").unwrap();
+/// assert_eq!(find_trait!(component, Render).unwrap().render(), "*");
+/// ```
+#[macro_export]
+macro_rules! add_state_object {
+    ($component:expr, $state:expr, $obj_type:ty, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        paste! {
+            let obj_id = ObjectId::new([<get_ $obj_type:lower _id>](), "");
+            let obj_ptr = $component.add_state_object::<$obj_type>($state, obj_id, $object);
+            add_traits!($component, $obj_type, obj_id, obj_ptr, $($trait1),*);
+            $(add_repeated_traits!($component, $obj_type, obj_id, obj_ptr, $($trait2),*);)?
+        }
+    }};
+}
+
+/// Used by an object added with [`push_object`] to find the trait it overrode. This is
+/// similar to a super call in languages that support implementation inheritance.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Render {
+///     fn render(&self, component: &Component) -> String;
+/// }
+/// register_type!(Render);
+///
+/// struct Button {}
+/// register_type!(Button);
+///
+/// impl Render for Button {
+///     fn render(&self, _component: &Component) -> String {
+///         "button".to_owned()
+///     }
+/// }
+///
+/// struct Flashing {}
+/// register_type!(Flashing);
+///
+/// impl Render for Flashing {
+///     fn render(&self, component: &Component) -> String {
+///         let base = find_super_trait!(component, Render, Flashing).unwrap();
+///         format!("flashing {}", base.render(component))
+///     }
+/// }
+///
+/// let mut component = Component::new("button");
+/// add_object!(component, Button, Button {}, [Render]);
+/// push_object!(component, Flashing, Flashing {}, [Render]);
+///
+/// let render = find_trait!(component, Render).unwrap();
+/// assert_eq!(render.render(&component), "flashing button");
+/// ```
+#[macro_export]
+macro_rules! find_super_trait {
+    ($component:expr, $trait:ty, $obj_type:ty) => {{
+        find_super_trait!($component, $trait, $obj_type, "")
+    }};
+
+    ($component:expr, $trait:ty, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component.find_super::<dyn $trait>(
+                [<get_ $trait:lower _id>](),
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Mutable version of [`find_super_trait`].
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! find_super_trait_mut {
+    ($component:expr, $trait:ty, $obj_type:ty) => {{
+        find_super_trait_mut!($component, $trait, $obj_type, "")
+    }};
+
+    ($component:expr, $trait:ty, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component.find_super_mut::<dyn $trait>(
+                [<get_ $trait:lower _id>](),
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Removes an object, and all of its traits, from the component. Fails if the object
+/// isn't part of the component or it is borrowed. If the object was added with
+/// [`push_object`] then the traits it overrode are restored.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Fruit {
+///     fn eat(&self) -> String;
+/// }
+/// register_type!(Fruit);
+///
+/// struct Apple {}
+/// register_type!(Apple);
+///
+/// impl Fruit for Apple {
+///     fn eat(&self) -> String {
+///         "yum!".to_owned()
+///     }
+/// }
+///
+/// let mut component = Component::new("apple");
+/// add_object!(component, Apple, Apple {}, [Fruit]);
+/// assert!(remove_object!(component, Apple).is_ok());
+/// assert!(!has_trait!(component, Fruit));
+/// ```
+#[macro_export]
+macro_rules! remove_object {
+    ($component:expr, $obj_type:ty) => {{
+        remove_object!($component, $obj_type, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component
+                .remove_object(ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+                .map(|_| ())
+        }
+    }};
+}
+
+/// Returns the [`ObjectId`] for an object type and optional instance key. This is used
+/// with methods like [`Component::take_object`] and [`Component::split`].
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Hunger {
+///     fn get(&self) -> i32;
+/// }
+/// register_type!(Hunger);
+///
+/// struct Hungers {
+///     hunger: i32,
+/// }
+/// register_type!(Hungers);
+///
+/// impl Hunger for Hungers {
+///     fn get(&self) -> i32 {
+///         self.hunger
+///     }
+/// }
+///
+/// let mut parent = Component::new("parent");
+/// add_object!(parent, Hungers, Hungers { hunger: 5 }, [Hunger]);
+///
+/// let mut child = Component::new("child");
+/// let bundle = parent.take_object(object_id!(Hungers)).unwrap();
+/// child.insert_object(bundle);
+/// assert!(!has_trait!(parent, Hunger));
+/// assert_eq!(find_trait!(child, Hunger).unwrap().get(), 5);
+/// ```
+#[macro_export]
+macro_rules! object_id {
+    ($obj_type:ty) => {{
+        object_id!($obj_type, "")
+    }};
+
+    ($obj_type:ty, $key:expr) => {{
+        paste! {
+            ObjectId::new([<get_ $obj_type:lower _id>](), $key)
+        }
+    }};
+}
+
+/// Hides a trait so that the component no longer exposes it (including traits from
+/// prototypes and adapters). All slots and repeated instances of the trait are hidden.
+/// Masking is cheap and doesn't affect the objects implementing the trait.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Render {
+///     fn render(&self) -> String;
+/// }
+/// register_type!(Render);
+///
+/// struct Sprite {}
+/// register_type!(Sprite);
+///
+/// impl Render for Sprite {
+///     fn render(&self) -> String {
+///         "*".to_owned()
+///     }
+/// }
+///
+/// let mut component = Component::new("unit");
+/// add_object!(component, Sprite, Sprite {}, [Render]);
+///
+/// mask_trait!(component, Render);
+/// assert!(!has_trait!(component, Render));
+///
+/// unmask_trait!(component, Render);
+/// assert_eq!(find_trait!(component, Render).unwrap().render(), "*");
+/// ```
+#[macro_export]
+macro_rules! mask_trait {
+    ($component:expr, $trait:ty) => {{
+        paste! {
+            $component.mask_trait([<get_ $trait:lower _id>]())
+        }
+    }};
+}
+
+/// Undoes [`mask_trait`].
+#[macro_export]
+macro_rules! unmask_trait {
+    ($component:expr, $trait:ty) => {{
+        paste! {
+            $component.unmask_trait([<get_ $trait:lower _id>]())
+        }
+    }};
+}
+
+/// Hides all the traits of an object while leaving the object within the component. If
+/// the object was overriding a trait the overridden trait is not restored (use
+/// [`remove_object`] for that) but a prototype's trait will be used. Fails if the object
+/// isn't part of the component.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! mask_object {
+    ($component:expr, $obj_type:ty) => {{
+        mask_object!($component, $obj_type, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component.mask_object(ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Undoes [`mask_object`].
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! unmask_object {
+    ($component:expr, $obj_type:ty) => {{
+        unmask_object!($component, $obj_type, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component.unmask_object(ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Slots allow a trait to be exposed multiple times by a component under distinct names,
+/// e.g. a Weapon trait in "left_hand" and "right_hand" slots. Objects are added to slots
+/// using [`add_keyed_object`] where the instance key is the slot name. This returns the
+/// sorted names of the slots for which an object provides the trait.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Weapon {
+///     fn damage(&self) -> i32;
+/// }
+/// register_type!(Weapon);
+///
+/// struct Sword {}
+/// register_type!(Sword);
+///
+/// impl Weapon for Sword {
+///     fn damage(&self) -> i32 {
+///         6
+///     }
+/// }
+///
+/// struct Axe {}
+/// register_type!(Axe);
+///
+/// impl Weapon for Axe {
+///     fn damage(&self) -> i32 {
+///         8
+///     }
+/// }
+///
+/// let mut component = Component::new("warrior");
+/// add_keyed_object!(component, Sword, "left_hand", Sword {}, [Weapon]);
+/// add_keyed_object!(component, Sword, "right_hand", Sword {}, [Weapon]);
+/// let slots = find_slots!(component, Weapon);
+/// assert_eq!(slots[0].as_str(), "left_hand");
+/// assert_eq!(slots[1].as_str(), "right_hand");
+///
+/// replace_slot!(component, Weapon, "right_hand", Axe, Axe {}, [Weapon]).unwrap();
+/// assert_eq!(find_trait!(component, Weapon, "right_hand").unwrap().damage(), 8);
+/// ```
+#[macro_export]
+macro_rules! find_slots {
+    ($component:expr, $trait:ty) => {{
+        paste! {
+            $component.find_slots::<dyn $trait>([<get_ $trait:lower _id>]())
+        }
+    }};
+}
+
+/// Removes the object providing a trait in a slot. Fails if the slot is empty or the
+/// object is borrowed.
+///
+/// # Panics
+///
+/// If the slot name is longer than 16 bytes.
+#[macro_export]
+macro_rules! remove_slot {
+    ($component:expr, $trait:ty, $slot:expr) => {{
+        paste! {
+            $component
+                .remove_slot::<dyn $trait>([<get_ $trait:lower _id>](), $slot)
+                .map(|_| ())
+        }
+    }};
+}
+
+/// Replaces the object (if any) providing a trait in a slot with a new object keyed by
+/// the slot name. Fails, without adding the new object, if the old object is borrowed.
+///
+/// # Panics
+///
+/// If the slot name is longer than 16 bytes.
+#[macro_export]
+macro_rules! replace_slot {
+    ($component:expr, $trait:ty, $slot:expr, $obj_type:ty, $object:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        match remove_slot!($component, $trait, $slot) {
+            Ok(_) | Err(ComponentError::EmptySlot(_, _)) => {
+                add_keyed_object!($component, $obj_type, $slot, $object, [$($trait1),*] $(, [$($trait2),*])?);
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! has_trait {
+    ($component:expr, $trait:ty) => {{
+        has_trait!($component, $trait, "")
+    }};
+
+    ($component:expr, $trait:ty, $key:expr) => {{
+        paste! {
+            $component.has::<dyn $trait>([<get_ $trait:lower _id>](), $key)
+        }
+    }};
+}
+
+/// Returns an optional reference to a trait for an object within the component.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// struct Apple {}
+/// register_type!(Apple);
+///
+/// trait Fruit {
+///     fn eat(&self) -> String;
+/// }
+/// register_type!(Fruit);
+///
+/// impl Fruit for Apple {
+///     fn eat(&self) -> String {
+///         "yum!".to_owned()
+///     }
+/// }
+///
+/// let apple = Apple {};
+/// let mut component = Component::new("apple");
+/// add_object!(component, Apple, apple, [Fruit]);
+///
+/// let fruit = find_trait!(component, Fruit);
+/// assert_eq!(fruit.unwrap().eat(), "yum!");
+/// ```
+#[macro_export]
+macro_rules! find_trait {
+    ($component:expr, $trait:ty) => {{
+        find_trait!($component, $trait, "")
     }};
 
-    ($component:expr, $obj_type:ty, $object:expr, [$trait1:ty, $($trait2:ty),+], [$trait3:ty, $($trait4:ty),+]) => {{   // + +
+    ($component:expr, $trait:ty, $key:expr) => {{
         paste! {
-            let boxed = Box::new($object);
-            let obj_ptr = Box::into_raw(boxed);
-            add_traits!($component, $obj_type, obj_ptr, $trait1);
-            add_traits!($component, $obj_type, obj_ptr, $($trait2),+);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $trait3);
-            add_repeated_traits!($component, $obj_type, obj_ptr, $($trait4),+);
-            $component.add_object::<$obj_type>(
-                [<get_ $obj_type:lower _id>](),
-                obj_ptr);
+            $component.find::<dyn $trait>([<get_ $trait:lower _id>](), $key)
         }
     }};
 }
 
+/// The borrowing rules for components are the standard rust rules: mutable references are
+/// exclusive references. But they apply to individual objects within a component so it's
+/// possible to simultaneously get two mutable references to two different objects within
+/// a component but not two mutable references to the same object (this is checked at
+/// runtime).
 #[macro_export]
-macro_rules! has_trait {
+macro_rules! find_trait_mut {
     ($component:expr, $trait:ty) => {{
+        find_trait_mut!($component, $trait, "")
+    }};
+
+    ($component:expr, $trait:ty, $key:expr) => {{
         paste! {
-            $component.has::<dyn $trait>([<get_ $trait:lower _id>]())
+            $component.find_mut::<dyn $trait>([<get_ $trait:lower _id>](), $key)
         }
     }};
 }
 
-/// Returns an optional reference to a trait for an object within the component.
+/// Returns an optional reference to an object within the component. This is an escape
+/// hatch for code that legitimately knows the concrete type, e.g. the module that owns
+/// the object or unit tests. Other code should stick to traits. Borrows are checked in
+/// the same way as for traits.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
 ///
 /// # Examples
 ///
 /// ```
-/// #![feature(lazy_cell)]
 /// use gear_objects::*;
 /// use paste::paste;
 ///
-/// struct Apple {}
-/// register_type!(Apple);
+/// struct Banana {
+///     ripeness: i32,
+/// }
+/// register_type!(Banana);
 ///
 /// trait Fruit {
 ///     fn eat(&self) -> String;
 /// }
 /// register_type!(Fruit);
 ///
-/// impl Fruit for Apple {
+/// impl Fruit for Banana {
 ///     fn eat(&self) -> String {
-///         "yum!".to_owned()
+///         format!("ripeness {}", self.ripeness)
 ///     }
 /// }
 ///
-/// let apple = Apple {};
-/// let mut component = Component::new("apple");
-/// add_object!(component, Apple, apple, [Fruit]);
+/// let mut component = Component::new("banana");
+/// add_object!(component, Banana, Banana { ripeness: 2 }, [Fruit]);
 ///
-/// let fruit = find_trait!(component, Fruit);
-/// assert_eq!(fruit.unwrap().eat(), "yum!");
+/// find_object_mut!(component, Banana).unwrap().ripeness += 1;
+/// assert_eq!(find_object!(component, Banana).unwrap().ripeness, 3);
+/// assert_eq!(find_trait_of!(component, Banana, Fruit).unwrap().eat(), "ripeness 3");
 /// ```
 #[macro_export]
-macro_rules! find_trait {
-    ($component:expr, $trait:ty) => {{
+macro_rules! find_object {
+    ($component:expr, $obj_type:ty) => {{
+        find_object!($component, $obj_type, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $key:expr) => {{
         paste! {
-            $component.find::<dyn $trait>([<get_ $trait:lower _id>]())
+            $component.find_object::<$obj_type>(
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
         }
     }};
 }
 
-/// The borrowing rules for components are the standard rust rules: mutable references are
-/// exclusive references. But they apply to individual objects within a component so it's
-/// possible to simultaneously get two mutable references to two different objects within
-/// a component but not two mutable references to the same object (this is checked at
-/// runtime).
+/// Like [`find_object`] except that a mutable reference is returned.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
 #[macro_export]
-macro_rules! find_trait_mut {
-    ($component:expr, $trait:ty) => {{
+macro_rules! find_object_mut {
+    ($component:expr, $obj_type:ty) => {{
+        find_object_mut!($component, $obj_type, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $key:expr) => {{
+        paste! {
+            $component.find_object_mut::<$obj_type>(
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Returns an optional reference to the trait as implemented by a particular object
+/// within the component. Like [`find_object`] this is an escape hatch: the object's
+/// implementation is used even if another object provides (or overrides) the trait.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! find_trait_of {
+    ($component:expr, $obj_type:ty, $trait:ty) => {{
+        find_trait_of!($component, $obj_type, $trait, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $trait:ty, $key:expr) => {{
+        paste! {
+            $component.find_trait_of::<$obj_type, dyn $trait>(
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
+        }
+    }};
+}
+
+/// Like [`find_trait_of`] except that a mutable reference is returned.
+///
+/// # Panics
+///
+/// If the key is longer than 16 bytes.
+#[macro_export]
+macro_rules! find_trait_of_mut {
+    ($component:expr, $obj_type:ty, $trait:ty) => {{
+        find_trait_of_mut!($component, $obj_type, $trait, "")
+    }};
+
+    ($component:expr, $obj_type:ty, $trait:ty, $key:expr) => {{
         paste! {
-            $component.find_mut::<dyn $trait>([<get_ $trait:lower _id>]())
+            $component.find_trait_of_mut::<$obj_type, dyn $trait>(
+                ObjectId::new([<get_ $obj_type:lower _id>](), $key))
         }
     }};
 }
@@ -427,33 +2385,33 @@ macro_rules! find_repeated_trait_mut {
     }};
 }
 
-impl PartialEq for Component {
-    fn eq(&self, other: &Component) -> bool {
+impl<M: Threading> PartialEq for Component<M> {
+    fn eq(&self, other: &Component<M>) -> bool {
         self.id == other.id
     }
 }
 
-impl Eq for Component {}
+impl<M: Threading> Eq for Component<M> {}
 
-impl Ord for Component {
+impl<M: Threading> Ord for Component<M> {
     fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
         self.id.cmp(&rhs.id)
     }
 }
 
-impl PartialOrd for Component {
+impl<M: Threading> PartialOrd for Component<M> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Hash for Component {
+impl<M: Threading> Hash for Component<M> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.id.hash(state);
     }
 }
 
-impl Debug for Component {
+impl<M: Threading> Debug for Component<M> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{:?}", self.id)?;
         for d in find_repeated_trait!(self, Debug) {
@@ -470,6 +2428,7 @@ mod tests {
     use std::fmt::Display;
     use std::sync::atomic::AtomicU8;
     use std::sync::atomic::Ordering;
+    use std::sync::Arc;
 
     trait Fruit {
         fn eat(&self) -> String;
@@ -535,6 +2494,15 @@ mod tests {
         }
     }
 
+    struct Softball {}
+    register_type!(Softball);
+
+    impl Ball for Softball {
+        fn throw(&self) -> String {
+            "underhand".to_owned()
+        }
+    }
+
     static DROP_COUNT: AtomicU8 = AtomicU8::new(0);
 
     struct Football {}
@@ -651,6 +2619,593 @@ mod tests {
                 || (displays[1] == "Apple" && displays[0] == "Banana")
         );
     }
+
+    #[test]
+    fn keyed() {
+        let mut component = Component::new("bananas");
+        add_keyed_object!(
+            component,
+            Banana,
+            "green",
+            Banana { ripeness: 0 },
+            [Ripe],
+            [Display]
+        );
+        add_keyed_object!(
+            component,
+            Banana,
+            "yellow",
+            Banana { ripeness: 5 },
+            [Ripe],
+            [Display]
+        );
+
+        assert!(has_trait!(component, Ripe, "green"));
+        assert!(!has_trait!(component, Ripe));
+        assert!(find_trait!(component, Ripe).is_none());
+        assert!(find_trait!(component, Ripe, "much_too_long_to_be_a_key").is_none());
+
+        {
+            let mut green = find_trait_mut!(component, Ripe, "green").unwrap();
+            let yellow = find_trait!(component, Ripe, "yellow").unwrap(); // different object so this is OK
+            green.ripen();
+            assert_eq!(green.ripeness(), 1);
+            assert_eq!(yellow.ripeness(), 5);
+        }
+
+        assert_eq!(find_repeated_trait!(component, Display).count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "object was already added to the component")]
+    fn duplicate_key() {
+        let mut component = Component::new("bananas");
+        add_keyed_object!(component, Banana, "green", Banana { ripeness: 0 }, [Ripe]);
+        add_keyed_object!(component, Banana, "green", Banana { ripeness: 1 }, [Fruit]);
+    }
+
+    static REMOVED_COUNT: AtomicU8 = AtomicU8::new(0);
+
+    struct Baseball {}
+    register_type!(Baseball);
+
+    impl Ball for Baseball {
+        fn throw(&self) -> String {
+            "strike".to_owned()
+        }
+    }
+
+    impl Drop for Baseball {
+        fn drop(&mut self) {
+            REMOVED_COUNT.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let mut component = Component::new("stuff");
+        add_object!(
+            component,
+            Banana,
+            Banana { ripeness: 0 },
+            [Fruit],
+            [Display]
+        );
+        add_object!(component, Baseball, Baseball {}, [Ball]);
+
+        assert!(remove_object!(component, Baseball).is_ok());
+        assert_eq!(REMOVED_COUNT.load(Ordering::Relaxed), 1);
+        assert!(!has_trait!(component, Ball));
+        assert_eq!(
+            remove_object!(component, Baseball),
+            Err(ComponentError::MissingObject(ObjectId::new(
+                get_baseball_id(),
+                ""
+            )))
+        );
+
+        let ripe = find_trait!(component, Fruit).unwrap();
+        std::mem::forget(ripe); // leaks the reference count
+        assert!(matches!(
+            remove_object!(component, Banana),
+            Err(ComponentError::Borrowed(_))
+        ));
+        assert_eq!(find_repeated_trait!(component, Display).count(), 1);
+    }
+
+    #[test]
+    fn slots() {
+        let mut component = Component::new("bananas");
+        assert!(find_slots!(component, Ripe).is_empty());
+        assert!(matches!(
+            remove_slot!(component, Ripe, "left"),
+            Err(ComponentError::EmptySlot(_, _))
+        ));
+
+        add_keyed_object!(
+            component,
+            Banana,
+            "right",
+            Banana { ripeness: 1 },
+            [Ripe],
+            [Display]
+        );
+        replace_slot!(
+            component,
+            Ripe,
+            "left",
+            Banana,
+            Banana { ripeness: 2 },
+            [Ripe],
+            [Display]
+        )
+        .unwrap();
+        let slots = find_slots!(component, Ripe);
+        assert_eq!(
+            slots.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+            vec!["left", "right"]
+        );
+
+        replace_slot!(
+            component,
+            Ripe,
+            "left",
+            Banana,
+            Banana { ripeness: 3 },
+            [Ripe]
+        )
+        .unwrap();
+        assert_eq!(find_trait!(component, Ripe, "left").unwrap().ripeness(), 3);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 1);
+
+        remove_slot!(component, Ripe, "right").unwrap();
+        let slots = find_slots!(component, Ripe);
+        assert_eq!(
+            slots.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+            vec!["left"]
+        );
+    }
+
+    struct Frozen {
+        ripeness: i32,
+    }
+    register_type!(Frozen);
+
+    impl Ripe for Frozen {
+        fn ripeness(&self) -> i32 {
+            self.ripeness
+        }
+
+        fn ripen(&mut self) {}
+    }
+
+    #[test]
+    fn overrides() {
+        let mut component = Component::new("banana");
+        add_object!(component, Banana, Banana { ripeness: 1 }, [Fruit, Ripe]);
+        push_object!(component, Frozen, Frozen { ripeness: 10 }, [Ripe]);
+        push_keyed_object!(component, Frozen, "deep", Frozen { ripeness: 20 }, [Ripe]);
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 10);
+        assert_eq!(find_trait!(component, Ripe, "deep").unwrap().ripeness(), 20);
+        assert!(find_super_trait!(component, Ripe, Frozen, "deep").is_none());
+
+        push_object!(component, Apple, Apple {}, [Fruit]);
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+        {
+            // the overriding object and the object it overrides can both be borrowed
+            let mut top = find_trait_mut!(component, Ripe).unwrap();
+            let mut base = find_super_trait_mut!(component, Ripe, Frozen).unwrap();
+            top.ripen();
+            base.ripen();
+            assert_eq!(top.ripeness(), 10);
+            assert_eq!(base.ripeness(), 2);
+        }
+        assert!(find_super_trait!(component, Ripe, Banana).is_none());
+
+        remove_object!(component, Apple).unwrap();
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "mushy");
+
+        remove_object!(component, Frozen).unwrap();
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 2);
+        assert_eq!(find_trait!(component, Ripe, "deep").unwrap().ripeness(), 20);
+    }
+
+    #[test]
+    fn remove_overridden() {
+        let mut component = Component::new("banana");
+        add_object!(component, Banana, Banana { ripeness: 1 }, [Ripe]);
+        push_object!(component, Frozen, Frozen { ripeness: 10 }, [Ripe]);
+
+        // Removing the overridden object leaves the override in place.
+        remove_object!(component, Banana).unwrap();
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 10);
+        assert!(find_super_trait!(component, Ripe, Frozen).is_none());
+
+        remove_object!(component, Frozen).unwrap();
+        assert!(!has_trait!(component, Ripe));
+    }
+
+    #[test]
+    fn prototypes() {
+        let mut base = Component::new("base");
+        add_object!(base, Apple, Apple {}, [Fruit, Ball], [Display]);
+        let base = Arc::new(base);
+
+        let mut middle = Component::new("middle");
+        add_object!(middle, Banana, Banana { ripeness: 3 }, [Ripe], [Display]);
+        middle.set_prototype(base.clone());
+        let middle = Arc::new(middle);
+
+        let mut component = Component::new("component");
+        component.set_prototype(middle.clone());
+        assert!(has_trait!(component, Ripe));
+        assert!(has_trait!(component, Fruit));
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 3);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 2);
+
+        // Traits in the component shadow those in the prototype.
+        add_object!(component, Softball, Softball {}, [Ball]);
+        assert_eq!(find_trait!(component, Ball).unwrap().throw(), "underhand");
+        assert_eq!(find_trait!(middle, Ball).unwrap().throw(), "splat");
+
+        // Pushed objects can call through to the prototype.
+        push_object!(component, Frozen, Frozen { ripeness: 10 }, [Ripe]);
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 10);
+        let base_ripe = find_super_trait!(component, Ripe, Frozen).unwrap();
+        assert_eq!(base_ripe.ripeness(), 3);
+    }
+
+    trait Edible: Fruit + Debug {
+        fn calories(&self) -> i32;
+    }
+    register_type!(
+        Edible,
+        supertraits = [Fruit],
+        repeated_supertraits = [Debug]
+    );
+
+    #[derive(Debug)]
+    struct Cherry {}
+    register_type!(Cherry);
+
+    impl Fruit for Cherry {
+        fn eat(&self) -> String {
+            "pit".to_owned()
+        }
+    }
+
+    impl Edible for Cherry {
+        fn calories(&self) -> i32 {
+            5
+        }
+    }
+
+    #[test]
+    fn supertraits() {
+        let mut component = Component::new("cherry");
+        add_object!(component, Cherry, Cherry {}, [Edible], [Debug]);
+        assert_eq!(find_trait!(component, Edible).unwrap().calories(), 5);
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "pit");
+        assert_eq!(find_repeated_trait!(component, Debug).count(), 1);
+
+        // Explicitly added traits replace supertraits.
+        add_object!(component, Apple, Apple {}, [Fruit]);
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+        remove_object!(component, Cherry).unwrap();
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+        assert!(!has_trait!(component, Edible));
+        assert_eq!(find_repeated_trait!(component, Debug).count(), 0);
+    }
+
+    #[test]
+    fn objects() {
+        let mut component = Component::new("bananas");
+        add_object!(component, Banana, Banana { ripeness: 1 }, [Ripe]);
+        push_keyed_object!(component, Frozen, "ice", Frozen { ripeness: 10 }, [Ripe]);
+        assert!(find_object!(component, Apple).is_none());
+        assert!(find_object!(component, Frozen).is_none());
+
+        find_object_mut!(component, Frozen, "ice").unwrap().ripeness += 1;
+        assert_eq!(find_object!(component, Frozen, "ice").unwrap().ripeness, 11);
+
+        // Frozen overrides the "ice" slot but the banana's Ripe can still be used.
+        push_object!(component, Frozen, Frozen { ripeness: 20 }, [Ripe]);
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 20);
+        assert_eq!(
+            find_trait_of!(component, Banana, Ripe).unwrap().ripeness(),
+            1
+        );
+    }
+
+    #[test]
+    fn masking() {
+        let mut base = Component::new("base");
+        add_object!(base, Apple, Apple {}, [Fruit, Ball], [Display]);
+        let base = Arc::new(base);
+
+        let mut component = Component::new("component");
+        add_object!(component, Banana, Banana { ripeness: 2 }, [Ripe], [Display]);
+        add_keyed_object!(component, Banana, "green", Banana { ripeness: 0 }, [Ripe]);
+        add_object!(component, Softball, Softball {}, [Ball]);
+        component.set_prototype(base);
+
+        mask_trait!(component, Ripe);
+        assert!(!has_trait!(component, Ripe));
+        assert!(find_slots!(component, Ripe).is_empty());
+        assert_eq!(find_object!(component, Banana).unwrap().ripeness, 2);
+        unmask_trait!(component, Ripe);
+        assert_eq!(find_slots!(component, Ripe).len(), 2);
+
+        // Masked prototype traits are hidden too.
+        mask_trait!(component, Display);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 0);
+        unmask_trait!(component, Display);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 2);
+
+        // Masking an object exposes the prototype's trait.
+        mask_object!(component, Softball).unwrap();
+        assert_eq!(find_trait!(component, Ball).unwrap().throw(), "splat");
+        mask_object!(component, Banana).unwrap();
+        assert_eq!(find_repeated_trait!(component, Display).count(), 1);
+        assert_eq!(find_trait!(component, Ripe, "green").unwrap().ripeness(), 0);
+        assert!(!has_trait!(component, Ripe));
+
+        unmask_object!(component, Softball).unwrap();
+        assert_eq!(find_trait!(component, Ball).unwrap().throw(), "underhand");
+        assert_eq!(
+            mask_object!(component, Frozen),
+            Err(ComponentError::MissingObject(ObjectId::new(
+                get_frozen_id(),
+                ""
+            )))
+        );
+    }
+
+    #[test]
+    fn states() {
+        let mut component = Component::new("fruit");
+        add_object!(component, Banana, Banana { ripeness: 1 }, [Ripe], [Display]);
+        add_state_object!(component, "fresh", Apple, Apple {}, [Fruit], [Display]);
+        add_state_object!(component, "frozen", Frozen, Frozen { ripeness: 10 }, [Ripe]);
+        assert_eq!(component.state(), None);
+        assert!(!has_trait!(component, Fruit));
+        assert_eq!(find_repeated_trait!(component, Display).count(), 1);
+
+        component.set_state("fresh").unwrap();
+        assert_eq!(component.state(), Some("fresh"));
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 1);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 2);
+
+        // State objects override stateless objects.
+        component.set_state("frozen").unwrap();
+        assert!(!has_trait!(component, Fruit));
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 10);
+        assert_eq!(find_repeated_trait!(component, Display).count(), 1);
+
+        component.set_state("fresh").unwrap();
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 1);
+        assert_eq!(find_trait!(component, Fruit).unwrap().eat(), "yum!");
+
+        let err = component.set_state("rotten");
+        assert_eq!(
+            err,
+            Err(ComponentError::MissingState(instance_key("rotten")))
+        );
+        assert_eq!(component.state(), Some("fresh"));
+
+        remove_object!(component, Frozen).unwrap();
+        component.set_state("frozen").unwrap();
+        assert_eq!(find_trait!(component, Ripe).unwrap().ripeness(), 1);
+    }
+
+    #[test]
+    fn borrowed_state() {
+        let mut component = Component::new("fruit");
+        add_state_object!(component, "fresh", Apple, Apple {}, [Fruit]);
+        add_state_object!(component, "frozen", Frozen, Frozen { ripeness: 10 }, [Ripe]);
+        component.set_state("fresh").unwrap();
+
+        // Leak a borrow: normally the borrow checker prevents this.
+        std::mem::forget(find_trait!(component, Fruit).unwrap());
+        let err = component.set_state("frozen");
+        assert_eq!(
+            err,
+            Err(ComponentError::Borrowed(ObjectId::new(get_apple_id(), "")))
+        );
+        assert!(has_trait!(component, Fruit));
+        assert!(!has_trait!(component, Ripe));
+    }
+
+    #[test]
+    fn moving() {
+        let mut first = Component::new("first");
+        add_object!(first, Banana, Banana { ripeness: 1 }, [Ripe], [Display]);
+        push_object!(first, Frozen, Frozen { ripeness: 10 }, [Ripe]);
+        add_state_object!(first, "fresh", Apple, Apple {}, [Fruit]);
+
+        // Taking an overriding object restores the overridden trait.
+        let bundle = first.take_object(object_id!(Frozen)).unwrap();
+        assert_eq!(bundle.id, object_id!(Frozen));
+        assert_eq!(find_trait!(first, Ripe).unwrap().ripeness(), 1);
+        assert_eq!(
+            first.take_object(object_id!(Frozen)).err(),
+            Some(ComponentError::MissingObject(object_id!(Frozen)))
+        );
+
+        let mut second = Component::new("second");
+        second.insert_object(bundle);
+        assert_eq!(find_trait!(second, Ripe).unwrap().ripeness(), 10);
+
+        // Objects keep their state.
+        let bundle = first.take_object(object_id!(Apple)).unwrap();
+        second.insert_object(bundle);
+        assert!(!has_trait!(second, Fruit));
+        second.set_state("fresh").unwrap();
+        assert_eq!(find_trait!(second, Fruit).unwrap().eat(), "yum!");
+
+        // Merged traits override existing traits.
+        second.merge(&mut first).unwrap();
+        assert!(!has_trait!(first, Ripe));
+        assert_eq!(find_trait!(second, Ripe).unwrap().ripeness(), 1);
+        assert_eq!(find_repeated_trait!(second, Display).count(), 1);
+
+        let third = second
+            .split("third", &[object_id!(Banana), object_id!(Apple)])
+            .unwrap();
+        assert_eq!(find_trait!(second, Ripe).unwrap().ripeness(), 10);
+        assert!(!has_trait!(second, Fruit));
+        assert_eq!(third.state(), Some("fresh"));
+        assert_eq!(find_trait!(third, Ripe).unwrap().ripeness(), 1);
+        assert_eq!(find_trait!(third, Fruit).unwrap().eat(), "yum!");
+        assert_eq!(find_repeated_trait!(third, Display).count(), 1);
+    }
+
+    #[test]
+    fn moving_borrowed() {
+        let mut first = Component::new("first");
+        add_object!(first, Banana, Banana { ripeness: 1 }, [Ripe]);
+        add_object!(first, Apple, Apple {}, [Fruit]);
+        let mut second = Component::new("second");
+        add_object!(second, Softball, Softball {}, [Ball]);
+
+        // Leak a borrow: normally the borrow checker prevents this.
+        std::mem::forget(find_trait!(first, Ripe).unwrap());
+        let banana = object_id!(Banana);
+        let err = Err(ComponentError::Borrowed(banana));
+        assert_eq!(first.take_object(banana).err(), err.err());
+        assert_eq!(
+            first.split("split", &[object_id!(Apple), banana]).err(),
+            err.err()
+        );
+        assert_eq!(second.merge(&mut first), err);
+        assert!(has_trait!(first, Fruit));
+        assert!(!has_trait!(second, Fruit));
+    }
+
+    #[derive(Clone)]
+    struct Plum {
+        ripeness: i32,
+    }
+    register_type!(Plum, clone);
+
+    impl Ripe for Plum {
+        fn ripeness(&self) -> i32 {
+            self.ripeness
+        }
+
+        fn ripen(&mut self) {
+            self.ripeness += 1;
+        }
+    }
+
+    impl fmt::Display for Plum {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Plum")
+        }
+    }
+
+    #[derive(Clone)]
+    struct Prune {
+        ripeness: i32,
+    }
+    register_type!(Prune, clone);
+
+    impl Ripe for Prune {
+        fn ripeness(&self) -> i32 {
+            self.ripeness
+        }
+
+        fn ripen(&mut self) {}
+    }
+
+    #[derive(Clone)]
+    struct Pit {}
+    register_type!(Pit, clone);
+
+    impl Fruit for Pit {
+        fn eat(&self) -> String {
+            "pit".to_owned()
+        }
+    }
+
+    #[test]
+    fn cloning() {
+        let mut original = Component::new("original");
+        add_object!(original, Plum, Plum { ripeness: 1 }, [Ripe], [Display]);
+        push_object!(original, Prune, Prune { ripeness: 10 }, [Ripe]);
+        add_state_object!(original, "fresh", Pit, Pit {}, [Fruit]);
+
+        let mut copy = original.try_clone().unwrap();
+        assert_ne!(copy.id, original.id);
+        assert_eq!(find_trait!(copy, Ripe).unwrap().ripeness(), 10);
+        assert_eq!(find_super_trait!(copy, Ripe, Prune).unwrap().ripeness(), 1);
+        assert_eq!(find_repeated_trait!(copy, Display).count(), 1);
+
+        // Copies are independent of the original.
+        find_object_mut!(copy, Prune).unwrap().ripeness = 20;
+        assert_eq!(find_trait!(copy, Ripe).unwrap().ripeness(), 20);
+        assert_eq!(find_trait!(original, Ripe).unwrap().ripeness(), 10);
+        remove_object!(copy, Prune).unwrap();
+        assert_eq!(find_trait!(copy, Ripe).unwrap().ripeness(), 1);
+        copy.set_state("fresh").unwrap();
+        assert_eq!(find_trait!(copy, Fruit).unwrap().eat(), "pit");
+        assert!(!has_trait!(original, Fruit));
+
+        add_object!(original, Apple, Apple {}, [Fruit]);
+        assert_eq!(
+            original.try_clone().err(),
+            Some(ComponentError::NotClonable(object_id!(Apple)))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "immutable_ref already exists")]
+    fn object_borrows() {
+        let mut component = Component::new("banana");
+        add_object!(component, Banana, Banana { ripeness: 1 }, [Ripe]);
+
+        // Object borrows and trait borrows share the same accounting.
+        let _banana = find_object!(component, Banana).unwrap();
+        let _ripe = find_trait_mut!(component, Ripe);
+    }
+
+    #[test]
+    fn changes() {
+        let mut component = Component::new("banana");
+        add_object!(component, Banana, Banana { ripeness: 0 }, [Ripe]);
+        add_keyed_object!(component, Banana, "spare", Banana { ripeness: 0 }, [Ripe]);
+        let tick = advance_change_tick();
+        assert!(!component.changed_since(tick));
+
+        // Mutable borrows only count once they are written through.
+        assert_eq!(find_trait_mut!(component, Ripe).unwrap().ripeness(), 0);
+        assert!(!component.changed_since(tick));
+
+        find_trait_mut!(component, Ripe, "spare").unwrap().ripen();
+        let spare = ObjectId::new(get_banana_id(), "spare");
+        assert_eq!(component.changed_objects(tick), vec![spare]);
+
+        let tick = advance_change_tick();
+        mask_trait!(component, Ripe);
+        assert!(component.changed_since(tick));
+        assert!(component.changed_objects(tick).is_empty());
+
+        let id = component.id;
+        let mut store = ComponentStore::new();
+        store.insert(component);
+        let tick = advance_change_tick();
+        assert!(store.changed_since(tick).is_empty());
+
+        find_object_mut!(store.get(id).unwrap(), Banana, "spare")
+            .unwrap()
+            .ripeness += 1;
+        let added = Component::new("banana");
+        let added_id = added.id;
+        store.insert(added);
+        assert_eq!(store.changed_since(tick), vec![id, added_id]);
+    }
 }
 
 #[cfg(test)]
@@ -732,3 +3287,105 @@ mod thread_tests {
         assert!(name.ends_with("world"));
     }
 }
+
+#[cfg(test)]
+mod local_tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::thread;
+
+    trait Counter {
+        fn count(&self) -> i32;
+        fn bump(&mut self);
+    }
+    register_type!(Counter);
+
+    // Rc and RefCell are neither Send nor Sync.
+    struct SharedCount {
+        count: Rc<RefCell<i32>>,
+    }
+    register_type!(SharedCount);
+
+    impl Counter for SharedCount {
+        fn count(&self) -> i32 {
+            *self.count.borrow()
+        }
+
+        fn bump(&mut self) {
+            *self.count.borrow_mut() += 1;
+        }
+    }
+
+    // Cell is Send but not Sync.
+    struct CellCount {
+        count: Cell<i32>,
+    }
+    register_type!(CellCount);
+
+    impl Counter for CellCount {
+        fn count(&self) -> i32 {
+            self.count.get()
+        }
+
+        fn bump(&mut self) {
+            self.count.set(self.count.get() + 1);
+        }
+    }
+
+    #[test]
+    fn local() {
+        let count = Rc::new(RefCell::new(0));
+        let mut component = LocalComponent::new_local("local");
+        add_object!(
+            component,
+            SharedCount,
+            SharedCount {
+                count: count.clone()
+            },
+            [Counter]
+        );
+
+        find_trait_mut!(component, Counter).unwrap().bump();
+        find_trait_mut!(component, Counter).unwrap().bump();
+        assert_eq!(find_trait!(component, Counter).unwrap().count(), 2);
+        assert_eq!(*count.borrow(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "mutable reference already exists")]
+    fn local_borrows() {
+        let mut component = LocalComponent::new_local("local");
+        add_object!(
+            component,
+            SharedCount,
+            SharedCount {
+                count: Rc::new(RefCell::new(0))
+            },
+            [Counter]
+        );
+
+        let _counter = find_trait_mut!(component, Counter).unwrap();
+        let _counter2 = find_trait!(component, Counter).unwrap();
+    }
+
+    #[test]
+    fn sendable() {
+        let mut component = SendComponent::new_sendable("sendable");
+        add_object!(
+            component,
+            CellCount,
+            CellCount {
+                count: Cell::new(0)
+            },
+            [Counter]
+        );
+
+        let thread = thread::spawn(move || {
+            find_trait_mut!(component, Counter).unwrap().bump();
+            component
+        });
+        let component = thread.join().unwrap();
+        assert_eq!(find_trait!(component, Counter).unwrap().count(), 1);
+    }
+}
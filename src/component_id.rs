@@ -1,5 +1,7 @@
 use arraystring::{typenum::U16, ArrayString};
 use core::sync::atomic::AtomicU32;
+#[cfg(feature = "serde")]
+use std::cell::Cell;
 use std::fmt::{self, Formatter};
 use std::sync::atomic::Ordering;
 
@@ -29,6 +31,19 @@ impl ComponentId {
     }
 }
 
+impl ComponentId {
+    // Returns a new id with the same tag.
+    #[cfg(debug_assertions)]
+    pub(crate) fn renew(&self) -> ComponentId {
+        next_component_id(self.tag.as_str())
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub(crate) fn renew(&self) -> ComponentId {
+        next_component_id("")
+    }
+}
+
 static NEXT_COMPONENT_ID: AtomicU32 = AtomicU32::new(1);
 
 #[doc(hidden)]
@@ -43,6 +58,85 @@ pub fn next_component_id(_tag: &str) -> ComponentId {
     ComponentId::new(NEXT_COMPONENT_ID.fetch_add(1, Ordering::Relaxed))
 }
 
+#[cfg(feature = "serde")]
+thread_local! {
+    // Set while reading a snapshot to the smallest id that should be allocated next.
+    static DEFERRED_RESERVE: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Components created when deserializing keep their ids so ids allocated afterwards need
+// to be larger. Returns None if no larger id can be allocated.
+#[cfg(feature = "serde")]
+fn reserve_component_id(value: u32) -> Option<()> {
+    let next = value.checked_add(1)?;
+    let deferred = DEFERRED_RESERVE.with(|deferred| {
+        let next = deferred.get()?.max(next);
+        deferred.set(Some(next));
+        Some(next)
+    });
+    if deferred.is_none() {
+        NEXT_COMPONENT_ID.fetch_max(next, Ordering::Relaxed);
+    }
+    Some(())
+}
+
+// Calls f without reserving the ids of deserialized components. Returns the smallest id
+// that should be allocated next if the caller keeps the components.
+#[cfg(feature = "serde")]
+pub(crate) fn defer_reserving_ids<T>(f: impl FnOnce() -> T) -> (T, u32) {
+    struct Restore(Option<u32>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEFERRED_RESERVE.with(|deferred| deferred.set(self.0));
+        }
+    }
+
+    let _restore = Restore(DEFERRED_RESERVE.with(|deferred| deferred.replace(Some(0))));
+    let result = f();
+    (
+        result,
+        DEFERRED_RESERVE.with(|deferred| deferred.get().unwrap()),
+    )
+}
+
+// Snapshots save the allocator state so that ids aren't reused after a restore, even for
+// components that were removed before the snapshot was taken.
+#[cfg(feature = "serde")]
+pub(crate) fn peek_next_component_id() -> u32 {
+    NEXT_COMPONENT_ID.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn restore_next_component_id(value: u32) {
+    NEXT_COMPONENT_ID.fetch_max(value, Ordering::Relaxed);
+}
+
+// Tags are always serialized so that debug and release builds use the same format.
+#[cfg(feature = "serde")]
+impl serde::Serialize for ComponentId {
+    #[cfg(debug_assertions)]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.tag.as_str(), self.id).serialize(serializer)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ("", self.id).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ComponentId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (tag, value): (String, u32) = serde::Deserialize::deserialize(deserializer)?;
+        reserve_component_id(value).ok_or_else(|| {
+            serde::de::Error::custom(format!("component id {value} is too large"))
+        })?;
+        Ok(ComponentId::new(&tag, value))
+    }
+}
+
 impl fmt::Debug for ComponentId {
     #[cfg(debug_assertions)]
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
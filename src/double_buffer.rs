@@ -0,0 +1,212 @@
+use super::*;
+use fnv::FnvHashMap;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The next state of a double buffered object.
+struct Buffer<M: Threading> {
+    back: *mut dyn Any,
+    stale: AtomicBool, // set when back has to be refreshed from the front object before use
+    refs: M::Refs,     // outstanding mutable references to back
+    swap: SwapFn,
+    refresh: (CloneFn, AssignFn),
+}
+
+impl<M: Threading> Drop for Buffer<M> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.back) });
+    }
+}
+
+// Back buffers for the double buffered objects within a component.
+pub(crate) struct Buffers<M: Threading> {
+    buffers: FnvHashMap<ObjectId, Buffer<M>>,
+}
+
+impl<M: Threading> Buffers<M> {
+    pub(crate) fn new() -> Buffers<M> {
+        Buffers {
+            buffers: FnvHashMap::default(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub(crate) fn is_borrowed(&self) -> bool {
+        self.buffers
+            .values()
+            .any(|buffer| buffer.refs.is_borrowed())
+    }
+
+    // Called when an object is added to the component.
+    pub(crate) fn add(&mut self, obj_id: ObjectId, front: &dyn Any) {
+        let Some(info) = type_info(obj_id.type_id) else {
+            return;
+        };
+        if let (Some(swap), Some((clone, assign))) = (info.double_buffered, info.clone) {
+            let buffer = Buffer {
+                back: Box::into_raw(clone(front)),
+                stale: AtomicBool::new(true),
+                refs: M::Refs::default(),
+                swap,
+                refresh: (clone, assign),
+            };
+            self.buffers.insert(obj_id, buffer);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, obj_id: ObjectId) {
+        self.buffers.remove(&obj_id);
+    }
+
+    // Discards writes to the back buffer, e.g. because the front object was overwritten.
+    pub(crate) fn discard(&self, obj_id: ObjectId) {
+        if let Some(buffer) = self.buffers.get(&obj_id) {
+            buffer.stale.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Returns the back buffer and its refs if the object is double buffered. If the back
+    // buffer hasn't been written to since the last swap then front is first copied into
+    // it.
+    pub(crate) fn back(
+        &self,
+        obj_id: ObjectId,
+        front: *mut dyn Any,
+    ) -> Option<(*mut dyn Any, &M::Refs)> {
+        let buffer = self.buffers.get(&obj_id)?;
+        if buffer.stale.load(Ordering::Relaxed) {
+            buffer.refs.borrow_mut();
+            if buffer.stale.swap(false, Ordering::Relaxed) {
+                let (clone, assign) = buffer.refresh;
+                unsafe { assign(&mut *buffer.back, clone(&*front)) };
+            }
+            buffer.refs.release_mut();
+        }
+        Some((buffer.back, &buffer.refs))
+    }
+
+    // Returns the back buffer without refreshing it.
+    pub(crate) fn get(&self, obj_id: ObjectId) -> Option<*mut dyn Any> {
+        self.buffers.get(&obj_id).map(|buffer| buffer.back)
+    }
+
+    // Makes the back buffers that were written to the front objects. Returns the ids of
+    // the objects that were swapped.
+    pub(crate) fn swap(&mut self, pointers: &FnvHashMap<ObjectId, *mut dyn Any>) -> Vec<ObjectId> {
+        let mut swapped = Vec::new();
+        for (obj_id, buffer) in self.buffers.iter_mut() {
+            if !buffer.stale.swap(true, Ordering::Relaxed) {
+                unsafe { (buffer.swap)(&mut *pointers[obj_id], &mut *buffer.back) };
+                swapped.push(*obj_id);
+            }
+        }
+        swapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+
+    trait Heat {
+        fn heat(&self) -> i32;
+        fn set_heat(&mut self, heat: i32);
+    }
+    register_type!(Heat);
+
+    #[derive(Clone)]
+    struct Cell {
+        heat: i32,
+    }
+    register_type!(Cell, double_buffered);
+
+    impl Heat for Cell {
+        fn heat(&self) -> i32 {
+            self.heat
+        }
+
+        fn set_heat(&mut self, heat: i32) {
+            self.heat = heat;
+        }
+    }
+
+    #[test]
+    fn order_independent() {
+        let mut store = ComponentStore::new();
+        let mut ids = Vec::new();
+        for heat in [0, 40] {
+            let mut component = Component::new("cell");
+            add_object!(component, Cell, Cell { heat }, [Heat]);
+            ids.push(component.id);
+            store.insert(component);
+        }
+
+        // Each cell takes its neighbor's heat. Because the neighbor's previous state is read
+        // the heats are swapped.
+        for (id, neighbor) in [(ids[0], ids[1]), (ids[1], ids[0])] {
+            let heat = find_trait!(store.get(neighbor).unwrap(), Heat)
+                .unwrap()
+                .heat();
+            find_trait_mut!(store.get(id).unwrap(), Heat)
+                .unwrap()
+                .set_heat(heat);
+        }
+        let heat = |id| find_trait!(store.get(id).unwrap(), Heat).unwrap().heat();
+        assert_eq!(heat(ids[0]), 0); // nothing is visible until the swap
+
+        store.swap_buffers();
+        let heat = |id| find_trait!(store.get(id).unwrap(), Heat).unwrap().heat();
+        assert_eq!((heat(ids[0]), heat(ids[1])), (40, 0));
+    }
+
+    #[test]
+    fn swaps() {
+        let mut component = Component::new("cell");
+        add_object!(component, Cell, Cell { heat: 1 }, [Heat]);
+        let obj_id = ObjectId::new(get_cell_id(), "");
+        find_object_mut!(component, Cell).unwrap().heat = 2;
+        assert_eq!(find_trait!(component, Heat).unwrap().heat(), 1);
+
+        let tick = advance_change_tick();
+        component.swap_buffers();
+        assert_eq!(find_trait!(component, Heat).unwrap().heat(), 2);
+        assert_eq!(component.changed_objects(tick), vec![obj_id]);
+
+        // Objects that weren't written to are left alone.
+        let tick = advance_change_tick();
+        component.swap_buffers();
+        assert_eq!(find_trait!(component, Heat).unwrap().heat(), 2);
+        assert!(component.changed_objects(tick).is_empty());
+
+        // Writes start from the current state.
+        let mut heat = find_trait_mut!(component, Heat).unwrap();
+        assert_eq!(heat.heat(), 2);
+        let next = heat.heat() + 1;
+        heat.set_heat(next);
+        drop(heat);
+
+        // Failed transactions discard writes to the back buffer.
+        let result = component.transaction(|c| {
+            find_trait_mut!(c, Heat).unwrap().set_heat(10);
+            Err::<(), _>("overheated")
+        });
+        assert!(result.is_err());
+        component.swap_buffers();
+        assert_eq!(find_trait!(component, Heat).unwrap().heat(), 3);
+
+        // Overwriting the front object discards pending writes.
+        find_trait_mut!(component, Heat).unwrap().set_heat(5);
+        let front = component.object_any_mut(obj_id).unwrap();
+        front.downcast_mut::<Cell>().unwrap().heat = 3;
+        component.swap_buffers();
+        assert_eq!(find_trait!(component, Heat).unwrap().heat(), 3);
+
+        let object = component.remove_object(obj_id).unwrap();
+        assert_eq!(object.downcast_ref::<Cell>().unwrap().heat, 3);
+        component.swap_buffers();
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use std::error::Error;
+use std::fmt::{self, Formatter};
+
+/// Returned by [`Component`] operations that can fail.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComponentError {
+    /// The object has outstanding trait references.
+    Borrowed(ObjectId),
+
+    /// The object is not part of the component.
+    MissingObject(ObjectId),
+
+    /// No object provides the trait (identified by its type id) in the slot.
+    EmptySlot(TypeId, InstanceKey),
+
+    /// The component has no state with the name.
+    MissingState(InstanceKey),
+
+    /// The object was not registered with the clone option.
+    NotClonable(ObjectId),
+}
+
+impl fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ComponentError::Borrowed(id) => write!(f, "object {id:?} is borrowed"),
+            ComponentError::MissingObject(id) => write!(f, "object {id:?} is not in the component"),
+            ComponentError::EmptySlot(id, slot) => write!(f, "slot '{slot}' of {id:?} is empty"),
+            ComponentError::MissingState(name) => {
+                write!(f, "state '{name}' is not in the component")
+            }
+            ComponentError::NotClonable(id) => write!(f, "object {id:?} is not clonable"),
+        }
+    }
+}
+
+impl Error for ComponentError {}
+
+/// Returned by [`find_type`] when a name doesn't identify a single type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TypeNameError {
+    /// No type with the name has been registered.
+    Unknown(String),
+
+    /// More than one registered type has the name.
+    Ambiguous(String),
+}
+
+impl fmt::Display for TypeNameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeNameError::Unknown(name) => write!(f, "unknown type '{name}'"),
+            TypeNameError::Ambiguous(name) => write!(f, "more than one type is named '{name}'"),
+        }
+    }
+}
+
+impl Error for TypeNameError {}
@@ -1,12 +1,55 @@
-#![feature(lazy_cell)]
 #![feature(ptr_metadata)]
 #![feature(unsize)]
 
+mod adapter;
+mod archetype;
+#[cfg(feature = "serde")]
+mod binary;
+mod bundle;
+mod change_tick;
 mod component;
 mod component_id;
+mod double_buffer;
+mod error;
+#[cfg(feature = "serde")]
+mod migration;
+mod object_id;
+mod prefab;
+#[cfg(feature = "serde")]
+mod replication;
+#[cfg(feature = "serde")]
+mod serialization;
+mod signal;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod store;
+mod threading;
+mod transaction;
 mod type_erased_ptr;
 mod type_id;
+mod undo;
+mod view;
 
+pub use adapter::*;
+pub use archetype::*;
+pub use bundle::*;
+pub use change_tick::*;
 pub use component::*;
 pub use component_id::*;
+pub use error::*;
+#[cfg(feature = "serde")]
+pub use migration::*;
+pub use object_id::*;
+pub use prefab::*;
+#[cfg(feature = "serde")]
+pub use replication::*;
+#[cfg(feature = "serde")]
+pub use serialization::*;
+pub use signal::*;
+#[cfg(feature = "serde")]
+pub use snapshot::*;
+pub use store::*;
+pub use threading::*;
 pub use type_id::*;
+pub use undo::*;
+pub use view::*;
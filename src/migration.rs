@@ -0,0 +1,262 @@
+//! Upgrades persisted objects saved with older versions or names of their types, see
+//! [`register_migration`] and [`register_rename`].
+use crate::serialization::DeserializeFn;
+use fnv::FnvHashMap;
+use serde::de::DeserializeOwned;
+use std::any::Any;
+use std::sync::{Arc, LazyLock, RwLock};
+
+type UpgradeFn = Box<dyn Fn(Box<dyn Any>) -> Result<Box<dyn Any>, String> + Send + Sync>;
+
+struct Migration {
+    deserialize: DeserializeFn, // used when the data starts out at this version
+    upgrade: UpgradeFn,         // to the next version
+}
+
+type Migrations = FnvHashMap<(String, u32), Arc<Migration>>;
+
+// (object type name, version) => migration to version + 1
+static MIGRATIONS: LazyLock<RwLock<Migrations>> =
+    LazyLock::new(|| RwLock::new(FnvHashMap::default()));
+
+// old object type name => new name
+static RENAMES: LazyLock<RwLock<FnvHashMap<String, String>>> =
+    LazyLock::new(|| RwLock::new(FnvHashMap::default()));
+
+/// Registers a function used to upgrade persisted data for an object type from version
+/// to version + 1. Old is a type that can deserialize the data saved by the old version.
+/// New is the type for the next version: either the object type itself or the Old type
+/// used by the next migration. Name is the object type's current name (as it appears in
+/// [`register_type`]).
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+/// use serde::{Deserialize, Serialize};
+///
+/// // Version 1 of Hungers had only the hunger field.
+/// #[derive(Deserialize)]
+/// struct HungersV1 {
+///     hunger: i32,
+/// }
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Hungers {
+///     hunger: i32,
+///     max_hunger: i32,
+/// }
+/// register_type!(Hungers, version = 2, serde);
+///
+/// register_migration("Hungers", 1, |old: HungersV1| {
+///     Ok(Hungers {
+///         hunger: old.hunger,
+///         max_hunger: 100,
+///     })
+/// });
+/// preload_types!(Hungers);
+///
+/// let text = r#"{"id":["rabbit",1],"state":null,"masked_traits":[],"objects":[
+///     {"type":"Hungers","version":1,"key":"","state":null,"masked":false,"traits":[],
+///      "data":{"hunger":20}}
+/// ]}"#;
+/// let component: Component = serde_json::from_str(text).unwrap();
+/// let hungers = find_object!(component, Hungers).unwrap();
+/// assert_eq!(hungers.max_hunger, 100);
+/// ```
+///
+/// [`register_type`]: crate::register_type
+pub fn register_migration<Old, New>(
+    name: &str,
+    version: u32,
+    upgrade: impl Fn(Old) -> Result<New, String> + Send + Sync + 'static,
+) where
+    Old: DeserializeOwned + 'static,
+    New: 'static,
+{
+    fn deserialize<Old: DeserializeOwned + 'static>(
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Box<dyn Any>, erased_serde::Error> {
+        let object: Old = erased_serde::deserialize(deserializer)?;
+        Ok(Box::new(object))
+    }
+
+    let upgrade = move |object: Box<dyn Any>| match object.downcast::<Old>() {
+        Ok(old) => upgrade(*old).map(|new| Box::new(new) as Box<dyn Any>),
+        Err(_) => Err(format!(
+            "the previous migration didn't produce the type version {version} expects"
+        )),
+    };
+    let migration = Migration {
+        deserialize: deserialize::<Old>,
+        upgrade: Box::new(upgrade),
+    };
+
+    let mut migrations = MIGRATIONS.write().unwrap();
+    migrations.insert((name.to_owned(), version), Arc::new(migration));
+}
+
+/// Allows data persisted using an old object type name to be loaded after the type was
+/// renamed. Migrations should be registered using the new name.
+pub fn register_rename(old_name: &str, new_name: &str) {
+    let mut renames = RENAMES.write().unwrap();
+    renames.insert(old_name.to_owned(), new_name.to_owned());
+}
+
+// Returns the current name for a type that was renamed (possibly more than once).
+pub(crate) fn renamed_type(name: &str) -> Result<Option<String>, String> {
+    let renames = RENAMES.read().unwrap();
+    let Some(mut current) = renames.get(name) else {
+        return Ok(None);
+    };
+    let mut count = 0;
+    while let Some(next) = renames.get(current) {
+        current = next;
+        count += 1;
+        if count >= renames.len() {
+            return Err(format!("{name} was renamed in a cycle"));
+        }
+    }
+    Ok(Some(current.clone()))
+}
+
+fn find_migration(name: &str, version: u32) -> Result<Arc<Migration>, String> {
+    let migrations = MIGRATIONS.read().unwrap();
+    migrations
+        .get(&(name.to_owned(), version))
+        .cloned()
+        .ok_or_else(|| format!("there is no migration from version {version}"))
+}
+
+// Deserializes data saved using an old version of an object and upgrades it to the
+// current version.
+pub(crate) fn migrate(
+    name: &str,
+    version: u32,
+    current: u32,
+    deserializer: &mut dyn erased_serde::Deserializer,
+) -> Result<Box<dyn Any>, String> {
+    let migration = find_migration(name, version)?;
+    let mut object = (migration.deserialize)(deserializer).map_err(|err| err.to_string())?;
+    for version in version..current {
+        let migration = find_migration(name, version)?;
+        object = (migration.upgrade)(object)
+            .map_err(|err| format!("the version {version} migration failed: {err}"))?;
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use paste::paste;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize)]
+    struct FernV1 {
+        height: i32,
+    }
+
+    #[derive(Deserialize)]
+    struct FernV2 {
+        height: i32,
+        color: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Fern {
+        height: i32,
+        color: String,
+        watered: bool,
+    }
+    register_type!(Fern, version = 3, serde);
+
+    // Like Fern but without a migration from version 1.
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Weed {
+        height: i32,
+    }
+    register_type!(Weed, version = 2, serde);
+
+    #[test]
+    fn upgrades() {
+        register_migration("Fern", 1, |old: FernV1| {
+            Ok(FernV2 {
+                height: old.height,
+                color: "green".to_owned(),
+            })
+        });
+        register_migration("Fern", 2, |old: FernV2| {
+            Ok(Fern {
+                height: old.height,
+                color: old.color,
+                watered: false,
+            })
+        });
+        register_rename("Bracken", "Fern");
+        preload_types!(Fern);
+
+        let text = r#"{"id":["garden",1],"state":null,"masked_traits":[],"objects":[
+            {"type":"Fern","version":1,"key":"","state":null,"masked":false,"traits":[],
+             "data":{"height":3}}]}"#;
+        let component: Component = serde_json::from_str(text).unwrap();
+        let plant = find_object!(component, Fern).unwrap();
+        assert_eq!(plant.height, 3);
+        assert_eq!(plant.color, "green");
+
+        let text = r#"{"id":["garden",2],"state":null,"masked_traits":[],"objects":[
+            {"type":"Bracken","version":2,"key":"","state":null,"masked":false,"traits":[],
+             "data":{"height":4,"color":"red"}}]}"#;
+        let component: Component = serde_json::from_str(text).unwrap();
+        assert_eq!(find_object!(component, Fern).unwrap().color, "red");
+
+        let text = r#"{"id":["garden",3],"state":null,"masked_traits":[],"objects":[
+            {"type":"Fern","version":3,"key":"","state":null,"masked":false,"traits":[],
+             "data":{"height":5,"color":"red","watered":true}}]}"#;
+        let component: Component = serde_json::from_str(text).unwrap();
+        assert!(find_object!(component, Fern).unwrap().watered);
+    }
+
+    #[test]
+    fn errors() {
+        preload_types!(Weed);
+        let text = r#"{"id":["garden",4],"state":null,"masked_traits":[],"objects":[
+            {"type":"Weed","version":1,"key":"pot","state":null,"masked":false,"traits":[],
+             "data":{"height":-3}}]}"#;
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err.to_string().starts_with(
+            "couldn't migrate Weed/pot version 1: there is no migration from version 1"
+        ));
+
+        register_migration("Weed", 1, |old: FernV1| {
+            if old.height < 0 {
+                return Err("height was negative".to_owned());
+            }
+            Ok(FernV1 { height: old.height })
+        });
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err.to_string().starts_with(
+            "couldn't migrate Weed/pot version 1: the version 1 migration failed: height was negative"
+        ));
+
+        let text = text.replace("-3", "3");
+        let err = serde_json::from_str::<Component>(&text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("migrating Weed/pot from version 1 produced the wrong type"));
+
+        let text = text.replace(r#""version":1"#, r#""version":3"#);
+        let err = serde_json::from_str::<Component>(&text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Weed version 3 is newer than version 2"));
+
+        register_rename("Ivy", "Vine");
+        register_rename("Vine", "Ivy");
+        let text = text.replace("Weed", "Ivy");
+        let err = serde_json::from_str::<Component>(&text).unwrap_err();
+        assert!(err.to_string().starts_with("Ivy was renamed in a cycle"));
+    }
+}
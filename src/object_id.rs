@@ -0,0 +1,40 @@
+use super::*;
+use arraystring::{typenum::U16, ArrayString};
+use std::fmt::{self, Formatter};
+
+/// Key used to distinguish multiple objects of the same type within a component.
+pub type InstanceKey = ArrayString<U16>;
+
+/// Used to identify objects within a component: the object's type along with an
+/// instance key. The key is empty unless the object was added using
+/// [`add_keyed_object`].
+#[derive(Copy, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ObjectId {
+    pub type_id: TypeId,
+    pub key: InstanceKey,
+}
+
+impl ObjectId {
+    /// Panics if the key is longer than 16 bytes.
+    pub fn new(type_id: TypeId, key: &str) -> ObjectId {
+        ObjectId {
+            type_id,
+            key: instance_key(key),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn instance_key(key: &str) -> InstanceKey {
+    InstanceKey::try_from_str(key).unwrap_or_else(|_| panic!("instance key '{key}' is too long"))
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{:?}", self.type_id)
+        } else {
+            write!(f, "{:?}/{}", self.type_id, self.key)
+        }
+    }
+}
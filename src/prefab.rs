@@ -0,0 +1,659 @@
+//! Prefabs are component recipes defined using text, e.g.
+//! ```text
+//! # Comments start with a hash.
+//! component rabbit
+//! object Rabbit
+//!     state = alive       # optional, see add_state_object
+//!     max_age = 25        # parameters are passed to the object's factory
+//!     traits = Action, Animal, Render
+//!     repeated = Debug
+//! object Hungers
+//!     initial = 25
+//!     max = 45
+//!     traits = Hunger
+//! ```
+//! Objects are created using factories registered with [`register_factory`].
+use super::*;
+use fnv::FnvHashMap;
+use std::error::Error;
+use std::fmt::{self, Formatter};
+use std::marker::Unsize;
+use std::path::Path;
+use std::ptr::{DynMetadata, Pointee};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// Returned when a prefab cannot be parsed or instantiated.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrefabError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl PrefabError {
+    fn new(file: &str, line: usize, message: String) -> PrefabError {
+        PrefabError {
+            file: file.to_owned(),
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for PrefabError {}
+
+/// The parameters for an object within a prefab. These are passed to the object's
+/// factory.
+#[derive(Clone, Debug)]
+pub struct PrefabParams {
+    file: Arc<str>,
+    line: usize,                                 // of the object
+    values: FnvHashMap<String, (String, usize)>, // name => (value, line)
+}
+
+impl PrefabParams {
+    /// Parses the named parameter. Fails if the parameter is missing or malformed.
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, PrefabError> {
+        let Some((value, line)) = self.values.get(name) else {
+            return Err(self.error(self.line, format!("missing parameter '{name}'")));
+        };
+        value
+            .parse()
+            .map_err(|_| self.error(*line, format!("bad value '{value}' for parameter '{name}'")))
+    }
+
+    fn error(&self, line: usize, message: String) -> PrefabError {
+        PrefabError::new(&self.file, line, message)
+    }
+}
+
+// Adds a trait for an object that was created by a factory.
+type AddTraitFn = fn(&mut Component, ObjectId, TypeId, *mut ());
+
+// Creates an object using parameters and adds it to a component, optionally in a state.
+type CreateFn = Box<
+    dyn Fn(&mut Component, ObjectId, Option<&str>, &PrefabParams) -> Result<*mut (), PrefabError>
+        + Send
+        + Sync,
+>;
+
+// Normally the [`register_factory`]` macro would be used instead of using this directly.
+#[doc(hidden)]
+pub struct ObjectFactory {
+    name: &'static str,
+    type_id: TypeId,
+    create: CreateFn,
+    traits: Vec<(&'static str, TypeId, AddTraitFn)>,
+    repeated: Vec<(&'static str, TypeId, AddTraitFn)>,
+}
+
+impl ObjectFactory {
+    pub fn new<Object>(
+        name: &'static str,
+        type_id: TypeId,
+        factory: fn(&PrefabParams) -> Result<Object, PrefabError>,
+    ) -> ObjectFactory
+    where
+        Object: Send + Sync + 'static,
+    {
+        let create = move |component: &mut Component,
+                           obj_id: ObjectId,
+                           state: Option<&str>,
+                           params: &PrefabParams| {
+            let object = factory(params)?;
+            let obj_ptr = match state {
+                Some(state) => component.add_state_object(state, obj_id, object),
+                None => component.add_object(obj_id, object),
+            };
+            Ok(obj_ptr as *mut ())
+        };
+        ObjectFactory {
+            name,
+            type_id,
+            create: Box::new(create),
+            traits: Vec::new(),
+            repeated: Vec::new(),
+        }
+    }
+
+    pub fn with_trait<Object, Trait>(mut self, name: &'static str, trait_id: TypeId) -> Self
+    where
+        Object: Unsize<Trait> + 'static,
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        fn add<Object, Trait>(c: &mut Component, obj_id: ObjectId, id: TypeId, ptr: *mut ())
+        where
+            Object: Unsize<Trait> + 'static,
+            Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        {
+            c.add_trait::<Trait, Object>(obj_id, id, ptr as *mut Object);
+        }
+        self.traits.push((name, trait_id, add::<Object, Trait>));
+        self
+    }
+
+    pub fn with_repeated<Object, Trait>(mut self, name: &'static str, trait_id: TypeId) -> Self
+    where
+        Object: Unsize<Trait> + 'static,
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        fn add<Object, Trait>(c: &mut Component, obj_id: ObjectId, id: TypeId, ptr: *mut ())
+        where
+            Object: Unsize<Trait> + 'static,
+            Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        {
+            c.add_repeated_trait::<Trait, Object>(obj_id, id, ptr as *mut Object);
+        }
+        self.repeated.push((name, trait_id, add::<Object, Trait>));
+        self
+    }
+}
+
+static FACTORIES: LazyLock<RwLock<FnvHashMap<&'static str, Arc<ObjectFactory>>>> =
+    LazyLock::new(|| RwLock::new(FnvHashMap::default()));
+
+// Normally the [`register_factory`]` macro would be used instead of calling this directly.
+#[doc(hidden)]
+pub fn register_object_factory(factory: ObjectFactory) {
+    let mut factories = FACTORIES.write().unwrap();
+    factories.insert(factory.name, Arc::new(factory));
+}
+
+fn find_factory(name: &str) -> Option<Arc<ObjectFactory>> {
+    let factories = FACTORIES.read().unwrap();
+    factories.get(name).cloned()
+}
+
+/// Registers a factory used to create objects listed in prefabs. The factory is given
+/// the object's [`PrefabParams`]. Prefabs may only expose the listed traits (the
+/// first list is for normal traits and the optional second list is for repeated
+/// traits).
+///
+/// # Examples
+///
+/// ```
+/// use core::fmt::Debug;
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Hunger {
+///     fn get(&self) -> i32;
+/// }
+/// register_type!(Hunger);
+///
+/// #[derive(Debug)]
+/// struct Hungers {
+///     hunger: i32,
+/// }
+/// register_type!(Hungers);
+///
+/// impl Hunger for Hungers {
+///     fn get(&self) -> i32 {
+///         self.hunger
+///     }
+/// }
+///
+/// register_factory!(
+///     Hungers,
+///     |params| Ok(Hungers { hunger: params.get("initial")? }),
+///     [Hunger],
+///     [Debug]
+/// );
+///
+/// let text = "
+///     component wolf
+///     object Hungers
+///         initial = 120
+///         traits = Hunger
+/// ";
+/// let prefabs = parse_prefabs("wolf.prefab", text).unwrap();
+/// let component = prefabs[0].instantiate().unwrap();
+/// assert_eq!(find_trait!(component, Hunger).unwrap().get(), 120);
+///
+/// let text = "
+///     component wolf
+///     object Hungers
+///         traits = Hunger, Debug
+/// ";
+/// let err = parse_prefabs("wolf.prefab", text).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "wolf.prefab:4: 'Debug' is a repeated trait for 'Hungers'"
+/// );
+/// ```
+#[macro_export]
+macro_rules! register_factory {
+    ($obj_type:ty, $factory:expr, [$($trait1:ty),*] $(, [$($trait2:ty),*])?) => {{
+        paste! {
+            let factory = ObjectFactory::new::<$obj_type>(
+                stringify!($obj_type),
+                [<get_ $obj_type:lower _id>](),
+                $factory)
+            $(.with_trait::<$obj_type, dyn $trait1>(
+                stringify!($trait1),
+                [<get_ $trait1:lower _id>]()))*
+            $($(.with_repeated::<$obj_type, dyn $trait2>(
+                stringify!($trait2),
+                [<get_ $trait2:lower _id>]()))*)?;
+            register_object_factory(factory);
+        }
+    }};
+}
+
+// An object within a prefab. Traits have been checked against the factory.
+#[derive(Clone, Debug)]
+struct PrefabObject {
+    factory: &'static str,
+    state: Option<String>,
+    params: PrefabParams,
+    traits: Vec<TypeId>,
+    repeated: Vec<TypeId>,
+}
+
+/// A component recipe parsed using [`parse_prefabs`] or [`load_prefabs`].
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    file: Arc<str>,
+    line: usize,
+    objects: Vec<PrefabObject>,
+}
+
+impl Prefab {
+    /// Creates a new component (tagged with the prefab's name) containing the prefab's
+    /// objects. Fails if an object factory fails.
+    pub fn instantiate(&self) -> Result<Component, PrefabError> {
+        let mut component = Component::new(&self.name);
+        self.add_to(&mut component)?;
+        Ok(component)
+    }
+
+    /// Adds the prefab's objects to an existing component. Fails if an object factory
+    /// fails or if an object or trait is already in the component (the component may be
+    /// left with some of the objects).
+    pub fn add_to(&self, component: &mut Component) -> Result<(), PrefabError> {
+        for object in self.objects.iter() {
+            let error = |message| PrefabError::new(&self.file, object.params.line, message);
+            let Some(factory) = find_factory(object.factory) else {
+                return Err(error(format!("unknown object '{}'", object.factory)));
+            };
+            let obj_id = ObjectId::new(factory.type_id, "");
+            if component.has_object(obj_id) {
+                return Err(error(format!(
+                    "'{}' is already in the component",
+                    factory.name
+                )));
+            }
+
+            let find = |traits: &[(&'static str, TypeId, AddTraitFn)], trait_id| {
+                traits
+                    .iter()
+                    .find(|t| t.1 == trait_id)
+                    .copied()
+                    .ok_or_else(|| {
+                        let name = type_info(trait_id).map_or("?", |info| info.name);
+                        error(format!("'{}' doesn't implement '{name}'", factory.name))
+                    })
+            };
+            let mut traits = Vec::new();
+            for trait_id in object.traits.iter() {
+                let (name, _, add) = find(&factory.traits, *trait_id)?;
+                let exposed = object.state.is_none() && component.has_own_trait(*trait_id, "");
+                if exposed || traits.iter().any(|(id, _)| id == trait_id) {
+                    return Err(error(format!("'{name}' is already in the component")));
+                }
+                traits.push((*trait_id, add));
+            }
+            let mut repeated = Vec::new();
+            for trait_id in object.repeated.iter() {
+                let (_, _, add) = find(&factory.repeated, *trait_id)?;
+                repeated.push((*trait_id, add));
+            }
+
+            let state = object.state.as_deref();
+            let obj_ptr = (factory.create)(component, obj_id, state, &object.params)?;
+            for (trait_id, add) in traits.into_iter().chain(repeated) {
+                add(component, obj_id, trait_id, obj_ptr);
+            }
+        }
+        Ok(())
+    }
+
+    /// The file and line where the prefab was defined.
+    pub fn location(&self) -> (&str, usize) {
+        (&self.file, self.line)
+    }
+}
+
+/// Reads a prefab file, see [`parse_prefabs`].
+pub fn load_prefabs(path: impl AsRef<Path>) -> Result<Vec<Prefab>, PrefabError> {
+    let path = path.as_ref();
+    let file = path.to_string_lossy();
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| PrefabError::new(&file, 0, format!("couldn't read the file: {err}")))?;
+    parse_prefabs(&file, &text)
+}
+
+/// Parses prefab text (see the [`prefab`] module docs for the format). File is used for
+/// error messages. Objects must have registered factories (see [`register_factory`]) and
+/// traits must be among those listed in the factory registration.
+///
+/// [`prefab`]: crate::prefab
+pub fn parse_prefabs(file: &str, text: &str) -> Result<Vec<Prefab>, PrefabError> {
+    let mut parser = Parser {
+        file: Arc::from(file),
+        prefabs: Vec::new(),
+        factory: None,
+    };
+    for (index, line) in text.lines().enumerate() {
+        let line_num = index + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if !line.is_empty() {
+            parser.parse_line(line_num, line)?;
+        }
+    }
+    Ok(parser.prefabs)
+}
+
+struct Parser {
+    file: Arc<str>,
+    prefabs: Vec<Prefab>,
+    factory: Option<Arc<ObjectFactory>>, // for the current object
+}
+
+impl Parser {
+    fn parse_line(&mut self, line_num: usize, line: &str) -> Result<(), PrefabError> {
+        if let Some(name) = line.strip_prefix("component ") {
+            self.prefabs.push(Prefab {
+                name: name.trim().to_owned(),
+                file: self.file.clone(),
+                line: line_num,
+                objects: Vec::new(),
+            });
+            self.factory = None;
+            Ok(())
+        } else if let Some(name) = line.strip_prefix("object ") {
+            self.parse_object(line_num, name.trim())
+        } else if let Some((name, value)) = line.split_once('=') {
+            self.parse_param(line_num, name.trim(), value.trim())
+        } else {
+            Err(self.error(
+                line_num,
+                format!("expected component, object, or name = value but found '{line}'"),
+            ))
+        }
+    }
+
+    fn parse_object(&mut self, line_num: usize, name: &str) -> Result<(), PrefabError> {
+        let Some(prefab) = self.prefabs.last_mut() else {
+            return Err(self.error(line_num, "object outside of a component".to_owned()));
+        };
+        let Some(factory) = find_factory(name) else {
+            return Err(self.error(line_num, format!("unknown object '{name}'")));
+        };
+        if prefab.objects.iter().any(|o| o.factory == factory.name) {
+            return Err(self.error(line_num, format!("'{name}' is already in the component")));
+        }
+
+        prefab.objects.push(PrefabObject {
+            factory: factory.name,
+            state: None,
+            params: PrefabParams {
+                file: self.file.clone(),
+                line: line_num,
+                values: FnvHashMap::default(),
+            },
+            traits: Vec::new(),
+            repeated: Vec::new(),
+        });
+        self.factory = Some(factory);
+        Ok(())
+    }
+
+    fn parse_param(&mut self, line_num: usize, name: &str, value: &str) -> Result<(), PrefabError> {
+        let Some(factory) = self.factory.clone() else {
+            return Err(self.error(line_num, format!("'{name}' is outside of an object")));
+        };
+        match name {
+            "traits" => {
+                let ids = self.parse_traits(line_num, &factory, value, false)?;
+                self.object().traits.extend(ids);
+            }
+            "repeated" => {
+                let ids = self.parse_traits(line_num, &factory, value, true)?;
+                self.object().repeated.extend(ids);
+            }
+            "state" => self.object().state = Some(value.to_owned()),
+            _ => {
+                let values = &mut self.object().params.values;
+                if values
+                    .insert(name.to_owned(), (value.to_owned(), line_num))
+                    .is_some()
+                {
+                    return Err(self.error(line_num, format!("'{name}' was already set")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_traits(
+        &self,
+        line_num: usize,
+        factory: &ObjectFactory,
+        value: &str,
+        repeated: bool,
+    ) -> Result<Vec<TypeId>, PrefabError> {
+        let (traits, others) = if repeated {
+            (&factory.repeated, &factory.traits)
+        } else {
+            (&factory.traits, &factory.repeated)
+        };
+        let mut ids = Vec::new();
+        for name in value.split(',').map(|name| name.trim()) {
+            if let Some((_, id, _)) = traits.iter().find(|t| t.0 == name) {
+                ids.push(*id);
+            } else if others.iter().any(|t| t.0 == name) {
+                let kind = if repeated {
+                    "not a repeated"
+                } else {
+                    "a repeated"
+                };
+                let message = format!("'{name}' is {kind} trait for '{}'", factory.name);
+                return Err(self.error(line_num, message));
+            } else {
+                let message = match find_type(name) {
+                    Ok(_) => format!("'{}' doesn't implement '{name}'", factory.name),
+                    Err(TypeNameError::Unknown(_)) => format!("unknown trait '{name}'"),
+                    Err(err) => err.to_string(),
+                };
+                return Err(self.error(line_num, message));
+            }
+        }
+        Ok(ids)
+    }
+
+    fn object(&mut self) -> &mut PrefabObject {
+        self.prefabs.last_mut().unwrap().objects.last_mut().unwrap()
+    }
+
+    fn error(&self, line: usize, message: String) -> PrefabError {
+        PrefabError::new(&self.file, line, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Debug;
+    use paste::paste;
+
+    trait Speed {
+        fn speed(&self) -> i32;
+    }
+    register_type!(Speed);
+
+    trait Name {
+        fn name(&self) -> String;
+    }
+    register_type!(Name);
+
+    #[allow(dead_code)]
+    trait Unused {}
+    register_type!(Unused);
+
+    #[derive(Debug)]
+    struct Horse {
+        speed: i32,
+        name: String,
+    }
+    register_type!(Horse);
+
+    impl Speed for Horse {
+        fn speed(&self) -> i32 {
+            self.speed
+        }
+    }
+
+    impl Name for Horse {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    struct Mule {}
+    register_type!(Mule);
+
+    impl Speed for Mule {
+        fn speed(&self) -> i32 {
+            5
+        }
+    }
+
+    fn register() {
+        register_factory!(
+            Horse,
+            |params| Ok(Horse {
+                speed: params.get("speed")?,
+                name: params.get("name")?,
+            }),
+            [Speed, Name],
+            [Debug]
+        );
+        let _ = get_unused_id(); // so that find_type knows about it
+    }
+
+    #[test]
+    fn instantiate() {
+        register();
+        let text = "
+            # horses are fast
+            component horse
+            object Horse    # the only object
+                speed = 40
+                name = Ed
+                traits = Speed, Name
+                repeated = Debug
+        ";
+        let prefabs = parse_prefabs("horse.prefab", text).unwrap();
+        assert_eq!(prefabs.len(), 1);
+        assert_eq!(prefabs[0].location(), ("horse.prefab", 3));
+
+        let component = prefabs[0].instantiate().unwrap();
+        assert_eq!(find_trait!(component, Speed).unwrap().speed(), 40);
+        assert_eq!(find_trait!(component, Name).unwrap().name(), "Ed");
+        assert_eq!(find_repeated_trait!(component, Debug).count(), 1);
+
+        let mut component = Component::new("horse");
+        prefabs[0].add_to(&mut component).unwrap();
+        let err = prefabs[0].add_to(&mut component).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "horse.prefab:4: 'Horse' is already in the component"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        register();
+        let check = |text: &str, expected: &str| {
+            let err = parse_prefabs("test.prefab", text).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        };
+        check(
+            "component c\nobject Pony",
+            "test.prefab:2: unknown object 'Pony'",
+        );
+        check(
+            "object Horse",
+            "test.prefab:1: object outside of a component",
+        );
+        check(
+            "component c\nspeed = 3",
+            "test.prefab:2: 'speed' is outside of an object",
+        );
+        check(
+            "component c\nobject Horse\nobject Horse",
+            "test.prefab:3: 'Horse' is already in the component",
+        );
+        check(
+            "component c\nobject Horse\n\ntraits = Bogus",
+            "test.prefab:4: unknown trait 'Bogus'",
+        );
+        check(
+            "component c\nobject Horse\ntraits = Unused",
+            "test.prefab:3: 'Horse' doesn't implement 'Unused'",
+        );
+        check(
+            "component c\nobject Horse\nrepeated = Speed",
+            "test.prefab:3: 'Speed' is not a repeated trait for 'Horse'",
+        );
+        check(
+            "component c\nobject Horse\nspeed = 3\nspeed = 4",
+            "test.prefab:4: 'speed' was already set",
+        );
+        check(
+            "component c\nobject Horse\nfast",
+            "test.prefab:3: expected component, object, or name = value but found 'fast'",
+        );
+
+        let text = "component c\nobject Horse\nspeed = fast\nname = Ed";
+        let prefabs = parse_prefabs("test.prefab", text).unwrap();
+        let err = prefabs[0].instantiate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test.prefab:3: bad value 'fast' for parameter 'speed'"
+        );
+
+        let text = "component c\nobject Horse\nspeed = 3";
+        let prefabs = parse_prefabs("test.prefab", text).unwrap();
+        let err = prefabs[0].instantiate().unwrap_err();
+        assert_eq!(err.to_string(), "test.prefab:2: missing parameter 'name'");
+
+        let text = "component c\nobject Horse\nspeed = 3\nname = Ed\ntraits = Speed, Speed";
+        let prefabs = parse_prefabs("test.prefab", text).unwrap();
+        let err = prefabs[0].instantiate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test.prefab:2: 'Speed' is already in the component"
+        );
+
+        let text = "component c\nobject Horse\nspeed = 3\nname = Ed\ntraits = Speed";
+        let prefabs = parse_prefabs("test.prefab", text).unwrap();
+        let mut component = Component::new("mule");
+        add_object!(component, Mule, Mule {}, [Speed]);
+        let err = prefabs[0].add_to(&mut component).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test.prefab:2: 'Speed' is already in the component"
+        );
+        assert!(!component.has_object(object_id!(Horse)));
+    }
+}
@@ -0,0 +1,344 @@
+//! Replication keeps a remote [`ComponentStore`] in sync with a local one by sending the
+//! changes made each tick. Objects must be registered with the serde option.
+use super::*;
+use crate::binary::{BinaryReader, BinaryWriter};
+use crate::serialization::parse_key;
+use fnv::FnvHashMap;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::io::{Read, Write};
+
+#[derive(Deserialize, Serialize)]
+enum Change {
+    Spawn(Vec<u8>), // also used when a component's objects, traits, or states change
+    Despawn(ComponentId),
+    Update(ComponentId, Vec<ObjectChange>),
+}
+
+#[derive(Deserialize, Serialize)]
+struct ObjectChange {
+    type_name: String, // type ids may differ between processes
+    key: String,
+    data: Vec<u8>,
+}
+
+// What was last sent for a component.
+struct Image {
+    structure: Vec<u8>,    // the component without the object data
+    objects: Vec<Vec<u8>>, // data for each object, in saved_objects order
+}
+
+/// Records the changes made to a store so that they can be applied to a remote store.
+/// Only components that changed since the previous call (see [`change_tick`]) are
+/// examined.
+pub struct Replicator {
+    images: FnvHashMap<ComponentId, Image>,
+    sent: Option<u32>, // change tick of the last write_changes call
+}
+
+fn encode<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>, SnapshotError> {
+    let mut bytes = Vec::new();
+    value.serialize(&mut BinaryWriter::new(&mut bytes))?;
+    Ok(bytes)
+}
+
+impl Replicator {
+    pub fn new() -> Replicator {
+        Replicator {
+            images: FnvHashMap::default(),
+            sent: None,
+        }
+    }
+
+    /// Writes the changes made to store since the last call (or all the components for
+    /// the first call). Normally called once per tick.
+    pub fn write_changes<M: Threading>(
+        &mut self,
+        store: &ComponentStore<M>,
+        mut writer: impl Write,
+    ) -> Result<(), SnapshotError> {
+        let mut changes = Vec::new();
+        let mut images = FnvHashMap::default();
+        let sent = self.sent.replace(change_tick());
+        for id in store.ids() {
+            let component = store.get(id).unwrap();
+            if sent.is_some_and(|tick| !component.changed_since(tick)) {
+                if let Some(image) = self.images.remove(&id) {
+                    images.insert(id, image);
+                    continue;
+                }
+            }
+            let saved = component.saved_objects();
+            let mut out = ComponentOut::new(component, &saved).map_err(SnapshotError::Format)?;
+            let objects = out
+                .objects
+                .iter()
+                .map(|object| encode(object.data))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let data: Vec<_> = out
+                .objects
+                .iter_mut()
+                .map(|object| std::mem::replace(&mut object.data, &()))
+                .collect();
+            let structure = encode(&out)?;
+
+            match self.images.remove(&id) {
+                Some(old) if old.structure == structure => {
+                    let updates: Vec<_> = out
+                        .objects
+                        .iter()
+                        .zip(old.objects.iter().zip(objects.iter()))
+                        .filter(|(_, (old, new))| old != new)
+                        .map(|(object, (_, new))| ObjectChange {
+                            type_name: object.type_name.to_owned(),
+                            key: object.key.to_owned(),
+                            data: new.clone(),
+                        })
+                        .collect();
+                    if !updates.is_empty() {
+                        changes.push(Change::Update(id, updates));
+                    }
+                }
+                _ => {
+                    for (object, data) in out.objects.iter_mut().zip(data) {
+                        object.data = data;
+                    }
+                    changes.push(Change::Spawn(encode(&out)?));
+                }
+            }
+            images.insert(id, Image { structure, objects });
+        }
+
+        let mut despawned: Vec<_> = self.images.keys().copied().collect();
+        despawned.sort();
+        changes.extend(despawned.into_iter().map(Change::Despawn));
+        self.images = images;
+
+        changes.serialize(&mut BinaryWriter::new(&mut writer))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for Replicator {
+    fn default() -> Self {
+        Replicator::new()
+    }
+}
+
+// A change that was decoded and checked against the store.
+enum Decoded<M: Threading> {
+    Spawn(Box<Component<M>>),
+    Despawn(ComponentId),
+    Update(ComponentId, Vec<(ObjectId, SerdeFns, Box<dyn Any>)>),
+}
+
+fn decode_object<M: Threading>(
+    component: &Component<M>,
+    change: ObjectChange,
+) -> Result<(ObjectId, SerdeFns, Box<dyn Any>), SnapshotError> {
+    let id = component.id;
+    let label = || format!("{}/{}", change.type_name, change.key);
+    let type_id = find_type(&change.type_name).map_err(|err| match err {
+        TypeNameError::Unknown(_) => {
+            SnapshotError::Format(format!("unknown object type '{}'", label()))
+        }
+        err => SnapshotError::Format(err.to_string()),
+    })?;
+    let info = type_info(type_id).unwrap();
+    let serde = info.serde.ok_or_else(|| {
+        let message = format!("{} wasn't registered with the serde option", info.name);
+        SnapshotError::Format(message)
+    })?;
+    let obj_id = ObjectId {
+        type_id,
+        key: parse_key(&change.key).map_err(SnapshotError::Format)?,
+    };
+    if !component.has_object(obj_id) {
+        return Err(SnapshotError::Format(format!(
+            "component {id} is missing {}",
+            label()
+        )));
+    }
+
+    let mut reader = BinaryReader::new(change.data.as_slice());
+    let mut erased = <dyn erased_serde::Deserializer>::erase(&mut reader);
+    let object =
+        (serde.deserialize)(&mut erased).map_err(|err| SnapshotError::Format(err.to_string()))?;
+    Ok((obj_id, serde, object))
+}
+
+/// Applies the changes written by one call to [`Replicator::write_changes`]. The store
+/// is left unchanged if an error is returned.
+pub fn read_changes<M: Threading>(
+    store: &mut ComponentStore<M>,
+    reader: impl Read,
+) -> Result<(), SnapshotError> {
+    let changes = Vec::<Change>::deserialize(&mut BinaryReader::new(reader))?;
+    let missing = |id| SnapshotError::Format(format!("component {id} isn't in the store"));
+    let mut decoded = Vec::with_capacity(changes.len());
+    for change in changes {
+        decoded.push(match change {
+            Change::Spawn(bytes) => Decoded::Spawn(Box::new(Component::deserialize(
+                &mut BinaryReader::new(bytes.as_slice()),
+            )?)),
+            Change::Despawn(id) => {
+                store.get(id).ok_or_else(|| missing(id))?;
+                Decoded::Despawn(id)
+            }
+            Change::Update(id, objects) => {
+                let component = store.get(id).ok_or_else(|| missing(id))?;
+                let objects = objects
+                    .into_iter()
+                    .map(|object| decode_object(component, object))
+                    .collect::<Result<_, _>>()?;
+                Decoded::Update(id, objects)
+            }
+        });
+    }
+
+    for change in decoded {
+        match change {
+            Decoded::Spawn(component) => {
+                store.insert(*component);
+            }
+            Decoded::Despawn(id) => {
+                store.remove(id);
+            }
+            Decoded::Update(id, objects) => {
+                let component = store.get_mut(id).unwrap();
+                for (obj_id, serde, object) in objects {
+                    (serde.assign)(component.object_any_mut(obj_id).unwrap(), object);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::io::BufReader;
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
+
+    trait Position {
+        fn position(&self) -> (i32, i32);
+        fn moved(&mut self, dx: i32, dy: i32);
+    }
+    register_type!(Position);
+
+    #[derive(Deserialize, Serialize)]
+    struct Boat {
+        x: i32,
+        y: i32,
+    }
+    register_type!(Boat, serde, traits = [Position]);
+
+    impl Position for Boat {
+        fn position(&self) -> (i32, i32) {
+            (self.x, self.y)
+        }
+
+        fn moved(&mut self, dx: i32, dy: i32) {
+            self.x += dx;
+            self.y += dy;
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn streams() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let mut receiver = BufReader::new(receiver);
+        let mut replicator = Replicator::new();
+        let mut local = ComponentStore::new();
+        let mut remote: ComponentStore = ComponentStore::new();
+
+        let mut first = Component::new("boat");
+        add_object!(first, Boat, Boat { x: 1, y: 2 }, [Position]);
+        let first_id = first.id;
+        local.insert(first);
+        let mut second = Component::new("boat");
+        add_object!(second, Boat, Boat { x: 3, y: 4 }, [Position]);
+        let second_id = second.id;
+        local.insert(second);
+        replicator.write_changes(&local, &sender).unwrap();
+        read_changes(&mut remote, &mut receiver).unwrap();
+        assert_eq!(remote.ids(), local.ids());
+        let position = find_trait!(remote.get(second_id).unwrap(), Position)
+            .unwrap()
+            .position();
+        assert_eq!(position, (3, 4));
+
+        // Only the boat that moved is sent.
+        find_trait_mut!(local.get(first_id).unwrap(), Position)
+            .unwrap()
+            .moved(10, 0);
+        let mut bytes = Vec::new();
+        replicator.write_changes(&local, &mut bytes).unwrap();
+        assert!(bytes.len() < 20);
+        read_changes(&mut remote, bytes.as_slice()).unwrap();
+        let position = find_trait!(remote.get(first_id).unwrap(), Position)
+            .unwrap()
+            .position();
+        assert_eq!(position, (11, 2));
+
+        // Spawns, despawns, and structural changes.
+        local.remove(first_id);
+        let mut spawned = Component::new("boat");
+        add_object!(spawned, Boat, Boat { x: 5, y: 6 }, [Position]);
+        add_keyed_object!(spawned, Boat, "tender", Boat { x: 0, y: 0 }, [Position]);
+        let spawned_id = spawned.id;
+        local.insert(spawned);
+        replicator.write_changes(&local, &sender).unwrap();
+        replicator.write_changes(&local, &sender).unwrap(); // nothing changed
+        read_changes(&mut remote, &mut receiver).unwrap();
+        read_changes(&mut remote, &mut receiver).unwrap();
+        assert_eq!(remote.ids(), local.ids());
+        let tender = find_trait!(remote.get(spawned_id).unwrap(), Position, "tender");
+        assert!(tender.is_some());
+    }
+
+    #[test]
+    fn errors() {
+        let mut replicator = Replicator::new();
+        let mut local = ComponentStore::new();
+        let mut remote: ComponentStore = ComponentStore::new();
+        let mut first = Component::new("boat");
+        add_object!(first, Boat, Boat { x: 1, y: 2 }, [Position]);
+        let first_id = first.id;
+        local.insert(first);
+        let second = Component::new("boat");
+        let second_id = second.id;
+        local.insert(second);
+        let mut bytes = Vec::new();
+        replicator.write_changes(&local, &mut bytes).unwrap();
+        read_changes(&mut remote, bytes.as_slice()).unwrap();
+
+        // Nothing is applied if any change fails.
+        find_trait_mut!(local.get(first_id).unwrap(), Position)
+            .unwrap()
+            .moved(10, 0);
+        local.remove(second_id);
+        let mut bytes = Vec::new();
+        replicator.write_changes(&local, &mut bytes).unwrap();
+        let second = remote.remove(second_id).unwrap();
+        let err = read_changes(&mut remote, bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("component {second_id} isn't in the store")
+        );
+        let position = find_trait!(remote.get(first_id).unwrap(), Position)
+            .unwrap()
+            .position();
+        assert_eq!(position, (1, 2));
+
+        remote.insert(second);
+        read_changes(&mut remote, bytes.as_slice()).unwrap();
+        assert_eq!(remote.ids(), vec![first_id]);
+    }
+}
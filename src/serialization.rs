@@ -0,0 +1,818 @@
+//! Serde support for components and stores. Objects must be registered with the serde
+//! option and are found by name when deserializing, see [`preload_types`].
+//!
+//! [`preload_types`]: crate::preload_types
+use super::*;
+use crate::migration::{migrate, renamed_type};
+use crate::type_erased_ptr::TypeErasedPointer;
+use fnv::FnvHashMap;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::sync::{Arc, LazyLock, RwLock};
+
+pub(crate) type DeserializeFn =
+    fn(&mut dyn erased_serde::Deserializer) -> Result<Box<dyn Any>, erased_serde::Error>;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub struct SerdeFns {
+    pub serialize: fn(&dyn Any) -> &dyn erased_serde::Serialize,
+    pub deserialize: DeserializeFn,
+    pub is_object: fn(&dyn Any) -> bool,
+    pub assign: AssignFn,
+}
+
+// Used by the serde option of register_type.
+#[doc(hidden)]
+pub fn serde_fns<Object>() -> SerdeFns
+where
+    Object: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn serialize<Object: Serialize + 'static>(object: &dyn Any) -> &dyn erased_serde::Serialize {
+        object.downcast_ref::<Object>().unwrap()
+    }
+
+    fn deserialize<Object: DeserializeOwned + 'static>(
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Box<dyn Any>, erased_serde::Error> {
+        let object: Object = erased_serde::deserialize(deserializer)?;
+        Ok(Box::new(object))
+    }
+
+    fn is_object<Object: 'static>(object: &dyn Any) -> bool {
+        object.is::<Object>()
+    }
+
+    SerdeFns {
+        serialize: serialize::<Object>,
+        deserialize: deserialize::<Object>,
+        is_object: is_object::<Object>,
+        assign: assign_object::<Object>,
+    }
+}
+
+/// Registers types so that they can be found by name, e.g. when deserializing
+/// components. Normally types are registered the first time their id is used.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Counter {
+///     count: i32,
+/// }
+/// register_type!(Counter, serde);
+///
+/// preload_types!(Counter);
+/// assert!(find_type("Counter").is_ok());
+/// ```
+#[macro_export]
+macro_rules! preload_types {
+    ($($type:ty),+) => {{
+        paste! {
+            $([<get_ $type:lower _id>]();)+
+        }
+    }};
+}
+
+// prototype name => prototype
+static PROTOTYPES: LazyLock<RwLock<FnvHashMap<String, Arc<Component>>>> =
+    LazyLock::new(|| RwLock::new(FnvHashMap::default()));
+
+/// Registers a prototype so that components using it can be serialized. Components are
+/// serialized with the name of their prototype and deserialized components share the
+/// registered prototype.
+pub fn register_prototype(name: &str, prototype: Arc<Component>) {
+    let mut prototypes = PROTOTYPES.write().unwrap();
+    prototypes.insert(name.to_owned(), prototype);
+}
+
+fn prototype_name<M: Threading>(prototype: &Arc<Component<M>>) -> Option<String> {
+    let prototypes = PROTOTYPES.read().unwrap();
+    let ptr = Arc::as_ptr(prototype) as *const ();
+    prototypes
+        .iter()
+        .find(|(_, p)| Arc::as_ptr(p) as *const () == ptr)
+        .map(|(name, _)| name.clone())
+}
+
+fn find_prototype(name: &str) -> Result<Arc<Component>, String> {
+    let prototypes = PROTOTYPES.read().unwrap();
+    prototypes
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("unknown prototype '{name}' (see register_prototype)"))
+}
+
+// Used by Component::saved_objects.
+pub(crate) struct SavedObject<'a> {
+    pub id: ObjectId,
+    pub object: &'a dyn Any,
+    pub state: Option<InstanceKey>,
+    pub masked: bool,
+    pub traits: Vec<SavedTrait>,
+}
+
+pub(crate) struct SavedTrait {
+    pub trait_id: TypeId,
+    pub repeated: bool,
+    pub depth: usize,  // position within the slot, repeated trait list, or state
+    pub implied: bool, // exposed because it's a supertrait
+}
+
+#[derive(Deserialize, Serialize)]
+struct TraitData {
+    name: String,
+    repeated: bool,
+    depth: u32,
+    implied: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ObjectOut<'a> {
+    #[serde(rename = "type")]
+    pub type_name: &'static str,
+    version: u32,
+    pub key: &'a str,
+    state: Option<&'a str>,
+    masked: bool,
+    traits: Vec<TraitData>,
+    pub data: &'a dyn erased_serde::Serialize,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ComponentOut<'a> {
+    id: ComponentId,
+    state: Option<&'a str>,
+    masked_traits: Vec<&'static str>,
+    pub objects: Vec<ObjectOut<'a>>,
+    prototype: Option<String>,
+}
+
+fn type_name(id: TypeId) -> &'static str {
+    type_info(id).map_or("?", |info| info.name)
+}
+
+// Returns the (trait id, repeated) pairs an object can expose: the traits listed with
+// register_type along with their supertraits.
+fn exposable_traits(info: &TypeInfo) -> Vec<(TypeId, bool)> {
+    let mut traits: Vec<_> = info
+        .traits
+        .iter()
+        .map(|t| (t.trait_id, t.repeated))
+        .collect();
+    let mut index = 0;
+    while index < traits.len() {
+        if let Some(info) = type_info(traits[index].0) {
+            for supertrait in info.supertraits {
+                let exposed = (supertrait.trait_id, supertrait.repeated);
+                if !traits.contains(&exposed) {
+                    traits.push(exposed);
+                }
+            }
+        }
+        index += 1;
+    }
+    traits
+}
+
+// Creates the trait pointer for an object, upcasting if the trait is a supertrait of a
+// trait listed with register_type.
+fn expose(
+    info: &TypeInfo,
+    trait_id: TypeId,
+    obj_id: ObjectId,
+    obj_ptr: *mut (),
+) -> Option<TypeErasedPointer> {
+    fn upcast_to(
+        from: TypeId,
+        erased: &TypeErasedPointer,
+        to: TypeId,
+    ) -> Option<TypeErasedPointer> {
+        type_info(from)?.supertraits.iter().find_map(|supertrait| {
+            let upcast = (supertrait.upcast)(erased);
+            if supertrait.trait_id == to {
+                Some(upcast)
+            } else {
+                upcast_to(supertrait.trait_id, &upcast, to)
+            }
+        })
+    }
+
+    info.traits.iter().find_map(|t| {
+        let erased = (t.expose)(obj_id, obj_ptr);
+        if t.trait_id == trait_id {
+            Some(erased)
+        } else {
+            upcast_to(t.trait_id, &erased, trait_id)
+        }
+    })
+}
+
+impl<'a> ObjectOut<'a> {
+    fn new(saved: &'a SavedObject<'a>) -> Result<ObjectOut<'a>, String> {
+        let info = type_info(saved.id.type_id).ok_or("object type isn't registered")?;
+        let serialize = info
+            .serde
+            .ok_or_else(|| format!("{} wasn't registered with the serde option", info.name))?
+            .serialize;
+
+        let exposable = exposable_traits(&info);
+        let mut traits = Vec::new();
+        for t in saved.traits.iter() {
+            if !exposable.contains(&(t.trait_id, t.repeated)) {
+                let option = if t.repeated {
+                    "repeated_traits"
+                } else {
+                    "traits"
+                };
+                return Err(format!(
+                    "{} exposes {} but it isn't listed in the {option} option",
+                    info.name,
+                    type_name(t.trait_id)
+                ));
+            }
+            traits.push(TraitData {
+                name: type_name(t.trait_id).to_owned(),
+                repeated: t.repeated,
+                depth: t.depth as u32,
+                implied: t.implied,
+            });
+        }
+        traits.sort_by(|lhs, rhs| {
+            (&lhs.name, lhs.repeated, lhs.depth).cmp(&(&rhs.name, rhs.repeated, rhs.depth))
+        });
+
+        Ok(ObjectOut {
+            type_name: info.name,
+            version: info.version,
+            key: saved.id.key.as_str(),
+            state: saved.state.as_ref().map(|s| s.as_str()),
+            masked: saved.masked,
+            traits,
+            data: serialize(saved.object),
+        })
+    }
+}
+
+impl<'a> ComponentOut<'a> {
+    // Saved should be component.saved_objects().
+    pub(crate) fn new<M: Threading>(
+        component: &'a Component<M>,
+        saved: &'a [SavedObject<'a>],
+    ) -> Result<ComponentOut<'a>, String> {
+        let objects = saved
+            .iter()
+            .map(ObjectOut::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut masked_traits: Vec<_> = component
+            .masked_trait_ids()
+            .into_iter()
+            .map(type_name)
+            .collect();
+        masked_traits.sort();
+        let prototype = match component.prototype() {
+            Some(prototype) => Some(prototype_name(prototype).ok_or_else(|| {
+                format!(
+                    "{}'s prototype isn't registered (see register_prototype)",
+                    component.id
+                )
+            })?),
+            None => None,
+        };
+        Ok(ComponentOut {
+            id: component.id,
+            state: component.state(),
+            masked_traits,
+            objects,
+            prototype,
+        })
+    }
+}
+
+impl<M: Threading> Serialize for Component<M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let saved = self.saved_objects();
+        ComponentOut::new(self, &saved)
+            .map_err(ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+// An object along with the information needed to add it to a component.
+struct ObjectIn {
+    type_id: TypeId,
+    info: TypeInfo,
+    key: String,
+    state: Option<String>,
+    masked: bool,
+    traits: Vec<TraitData>,
+    object: Box<dyn Any>,
+}
+
+// Deserializes an object using the functions registered with the serde option, migrating
+// the data if it was saved using an older version of the object.
+struct ObjectSeed {
+    type_id: TypeId,
+    info: TypeInfo,
+    serde: SerdeFns,
+    version: u32, // of the saved data
+    key: String,
+}
+
+impl ObjectSeed {
+    fn new(name: &str, version: u32, key: &str) -> Result<ObjectSeed, String> {
+        let name = renamed_type(name)?.unwrap_or_else(|| name.to_owned());
+        let type_id = find_type(&name).map_err(|err| match err {
+            TypeNameError::Unknown(_) => {
+                format!("unknown object type '{name}' (see preload_types)")
+            }
+            err => err.to_string(),
+        })?;
+        let info = type_info(type_id).unwrap();
+        let serde = info
+            .serde
+            .ok_or_else(|| format!("{name} wasn't registered with the serde option"))?;
+        if version > info.version {
+            return Err(format!(
+                "{name} version {version} is newer than version {}",
+                info.version
+            ));
+        }
+        Ok(ObjectSeed {
+            type_id,
+            info,
+            serde,
+            version,
+            key: key.to_owned(),
+        })
+    }
+
+    // The object type and key, formatted like ObjectId's Debug.
+    fn label(&self) -> String {
+        if self.key.is_empty() {
+            self.info.name.to_owned()
+        } else {
+            format!("{}/{}", self.info.name, self.key)
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ObjectSeed {
+    type Value = (TypeId, TypeInfo, Box<dyn Any>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        let object = if self.version == self.info.version {
+            (self.serde.deserialize)(&mut erased).map_err(de::Error::custom)?
+        } else {
+            migrate(self.info.name, self.version, self.info.version, &mut erased).map_err(
+                |err| {
+                    let label = self.label();
+                    de::Error::custom(format!(
+                        "couldn't migrate {label} version {}: {err}",
+                        self.version
+                    ))
+                },
+            )?
+        };
+        if !(self.serde.is_object)(&*object) {
+            let label = self.label();
+            let message = format!(
+                "migrating {label} from version {} produced the wrong type",
+                self.version
+            );
+            return Err(de::Error::custom(message));
+        }
+        Ok((self.type_id, self.info, object))
+    }
+}
+
+const OBJECT_FIELDS: &[&str] = &[
+    "type", "version", "key", "state", "masked", "traits", "data",
+];
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum ObjectField {
+    Type,
+    Version,
+    Key,
+    State,
+    Masked,
+    Traits,
+    Data,
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = ObjectIn;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("a gear object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ObjectIn, A::Error> {
+        let missing = |index| de::Error::invalid_length(index, &self);
+        let name: String = seq.next_element()?.ok_or_else(|| missing(0))?;
+        let version: u32 = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let key: String = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let seed = ObjectSeed::new(&name, version, &key).map_err(de::Error::custom)?;
+        let state = seq.next_element()?.ok_or_else(|| missing(3))?;
+        let masked = seq.next_element()?.ok_or_else(|| missing(4))?;
+        let traits = seq.next_element()?.ok_or_else(|| missing(5))?;
+        let (type_id, info, object) = seq.next_element_seed(seed)?.ok_or_else(|| missing(6))?;
+        Ok(ObjectIn {
+            type_id,
+            info,
+            key,
+            state,
+            masked,
+            traits,
+            object,
+        })
+    }
+
+    // The type, version, and key must appear before the data.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ObjectIn, A::Error> {
+        let mut name: Option<String> = None;
+        let mut version = None;
+        let mut key: Option<String> = None;
+        let mut state = None;
+        let mut masked = None;
+        let mut traits = None;
+        let mut object = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                ObjectField::Type => name = Some(map.next_value()?),
+                ObjectField::Version => version = Some(map.next_value()?),
+                ObjectField::Key => key = Some(map.next_value()?),
+                ObjectField::State => state = Some(map.next_value()?),
+                ObjectField::Masked => masked = Some(map.next_value()?),
+                ObjectField::Traits => traits = Some(map.next_value()?),
+                ObjectField::Data => {
+                    let (Some(name), Some(version), Some(key)) = (&name, version, &key) else {
+                        let message = "object data preceded its type, version, or key";
+                        return Err(de::Error::custom(message));
+                    };
+                    let seed = ObjectSeed::new(name, version, key).map_err(de::Error::custom)?;
+                    object = Some(map.next_value_seed(seed)?);
+                }
+            }
+        }
+        let (type_id, info, object) = object.ok_or_else(|| de::Error::missing_field("data"))?;
+        Ok(ObjectIn {
+            type_id,
+            info,
+            key: key.ok_or_else(|| de::Error::missing_field("key"))?,
+            state: state.unwrap_or_default(),
+            masked: masked.unwrap_or_default(),
+            traits: traits.unwrap_or_default(),
+            object,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectIn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("ObjectOut", OBJECT_FIELDS, ObjectVisitor)
+    }
+}
+
+pub(crate) fn parse_key(key: &str) -> Result<InstanceKey, String> {
+    InstanceKey::try_from_str(key).map_err(|_| format!("'{key}' is too long to be a key"))
+}
+
+#[derive(Deserialize)]
+struct ComponentIn {
+    id: ComponentId,
+    state: Option<String>,
+    masked_traits: Vec<String>,
+    objects: Vec<ObjectIn>,
+    prototype: Option<String>,
+}
+
+impl ComponentIn {
+    fn into_component<M: Threading>(self) -> Result<Component<M>, String> {
+        let mut component = Component::with_id(self.id);
+        if let Some(name) = self.prototype.as_deref() {
+            let prototype = find_prototype(name)?;
+            let shared: &mut dyn Any = &mut component;
+            shared
+                .downcast_mut::<Component>()
+                .ok_or_else(|| format!("prototype '{name}' has the wrong threading type"))?
+                .set_prototype(prototype);
+        }
+        component.restore_state(self.state.as_deref().map(parse_key).transpose()?);
+        for name in self.masked_traits.iter() {
+            let trait_id = find_type(name).map_err(|err| err.to_string())?;
+            component.mask_trait(trait_id);
+        }
+
+        let mut traits = Vec::new();
+        for object in self.objects {
+            let info = object.info;
+            let obj_id = ObjectId {
+                type_id: object.type_id,
+                key: parse_key(&object.key)?,
+            };
+            if component.has_object(obj_id) {
+                return Err(format!("object {} appears twice", info.name));
+            }
+            let state = object.state.as_deref().map(parse_key).transpose()?;
+            let obj_ptr = component.restore_object(obj_id, object.object, state, object.masked);
+
+            let exposable = exposable_traits(&info);
+            for t in object.traits {
+                let (trait_id, _) = exposable
+                    .iter()
+                    .copied()
+                    .find(|(id, repeated)| type_name(*id) == t.name && *repeated == t.repeated)
+                    .ok_or_else(|| format!("{} can't expose trait '{}'", info.name, t.name))?;
+                let erased = expose(&info, trait_id, obj_id, obj_ptr).unwrap();
+                traits.push((t.depth, trait_id, erased, t.repeated, t.implied));
+            }
+        }
+
+        traits.sort_by_key(|t| t.0);
+        for (_, trait_id, erased, repeated, implied) in traits {
+            component.restore_trait(trait_id, erased, repeated, implied);
+        }
+        Ok(component)
+    }
+}
+
+impl<'de, M: Threading> Deserialize<'de> for Component<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = ComponentIn::deserialize(deserializer)?;
+        data.into_component().map_err(de::Error::custom)
+    }
+}
+
+// Components are sorted by id so that the output is deterministic.
+impl<M: Threading> Serialize for ComponentStore<M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ids = self.ids();
+        let mut seq = serializer.serialize_seq(Some(ids.len()))?;
+        for id in ids {
+            seq.serialize_element(self.get(id).unwrap())?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, M: Threading> Deserialize<'de> for ComponentStore<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let components: Vec<Component<M>> = Deserialize::deserialize(deserializer)?;
+        let mut store = ComponentStore::new();
+        for component in components {
+            if let Some(old) = store.insert(component) {
+                let message = format!("component {} appears twice", old.id);
+                return Err(de::Error::custom(message));
+            }
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Debug;
+    use paste::paste;
+
+    trait Named {
+        fn name(&self) -> String;
+    }
+    register_type!(Named);
+
+    trait Scored: Named {
+        fn score(&self) -> i32;
+    }
+    register_type!(Scored, supertraits = [Named]);
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Player {
+        name: String,
+        score: i32,
+    }
+    register_type!(Player, serde, traits = [Scored], repeated_traits = [Debug]);
+
+    impl Named for Player {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    impl Scored for Player {
+        fn score(&self) -> i32 {
+            self.score
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Nickname {
+        name: String,
+    }
+    register_type!(Nickname, serde, traits = [Named], repeated_traits = [Debug]);
+
+    impl Named for Nickname {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    #[derive(Debug)]
+    struct Secret {}
+    register_type!(Secret);
+
+    #[test]
+    fn components() {
+        let mut component = Component::new("player");
+        let player = Player {
+            name: "Bob".to_owned(),
+            score: 10,
+        };
+        add_object!(component, Player, player, [Scored], [Debug]);
+        let nick = Nickname {
+            name: "Bobby".to_owned(),
+        };
+        push_object!(component, Nickname, nick, [Named], [Debug]);
+        let alt = Nickname {
+            name: "Robert".to_owned(),
+        };
+        add_keyed_object!(component, Nickname, "alt", alt, [Named]);
+        mask_trait!(component, Debug);
+
+        let text = serde_json::to_string(&component).unwrap();
+        let mut copy: Component = serde_json::from_str(&text).unwrap();
+        assert_eq!(copy.id, component.id);
+        assert_eq!(find_trait!(copy, Scored).unwrap().score(), 10);
+        assert_eq!(find_trait!(copy, Named).unwrap().name(), "Bobby");
+        assert_eq!(find_trait!(copy, Named, "alt").unwrap().name(), "Robert");
+        assert_eq!(copy.exposed_traits(), component.exposed_traits());
+
+        // Removing the override restores the supertrait from Player.
+        unmask_trait!(copy, Debug);
+        assert_eq!(find_repeated_trait!(copy, Debug).count(), 2);
+        remove_object!(copy, Nickname).unwrap();
+        assert_eq!(find_trait!(copy, Named).unwrap().name(), "Bob");
+    }
+
+    #[test]
+    fn states() {
+        let mut component = SendComponent::new_sendable("player");
+        let player = Player {
+            name: "Bob".to_owned(),
+            score: 10,
+        };
+        add_state_object!(component, "awake", Player, player, [Scored], [Debug]);
+        let nick = Nickname {
+            name: "Zzz".to_owned(),
+        };
+        add_state_object!(component, "asleep", Nickname, nick, [Named], [Debug]);
+        component.set_state("asleep").unwrap();
+        mask_object!(component, Nickname).unwrap();
+
+        let text = serde_json::to_string(&component).unwrap();
+        let mut copy: SendComponent = serde_json::from_str(&text).unwrap();
+        assert_eq!(copy.state(), Some("asleep"));
+        assert!(!has_trait!(copy, Named));
+        unmask_object!(copy, Nickname).unwrap();
+        assert_eq!(find_trait!(copy, Named).unwrap().name(), "Zzz");
+        assert!(!has_trait!(copy, Scored));
+
+        copy.set_state("awake").unwrap();
+        assert_eq!(find_trait!(copy, Named).unwrap().name(), "Bob");
+        assert_eq!(find_trait!(copy, Scored).unwrap().score(), 10);
+    }
+
+    #[test]
+    fn stores() {
+        let mut store = ComponentStore::new();
+        for (name, score) in [("Ann", 3), ("Bob", 5)] {
+            let mut component = Component::new(name);
+            let player = Player {
+                name: name.to_owned(),
+                score,
+            };
+            add_object!(component, Player, player, [Scored]);
+            store.insert(component);
+        }
+
+        let text = serde_json::to_string(&store).unwrap();
+        let copy: ComponentStore = serde_json::from_str(&text).unwrap();
+        assert_eq!(copy.ids(), store.ids());
+        for id in store.ids() {
+            let lhs = find_trait!(store.get(id).unwrap(), Scored).unwrap().score();
+            let rhs = find_trait!(copy.get(id).unwrap(), Scored).unwrap().score();
+            assert_eq!(lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut component = Component::new("secret");
+        add_object!(component, Secret, Secret {}, [], [Debug]);
+        let err = serde_json::to_string(&component).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Secret wasn't registered with the serde option"
+        );
+
+        let mut component = Component::new("nick");
+        let nick = Nickname {
+            name: "Bobby".to_owned(),
+        };
+        add_object!(component, Nickname, nick, [Named, Debug]);
+        let err = serde_json::to_string(&component).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Nickname exposes Debug but it isn't listed in the traits option"
+        );
+
+        let text = r#"{"id":["x",1],"state":null,"masked_traits":[],"objects":[
+            {"type":"Bogus","version":1,"key":"","state":null,"masked":false,"traits":[],"data":{}}
+        ]}"#;
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unknown object type 'Bogus' (see preload_types)"));
+
+        let nick = r#"{"type":"Nickname","version":1,"key":"","state":null,"masked":false,
+            "traits":[{"name":"Named","repeated":false,"depth":0,"implied":false}],
+            "data":{"name":"Bobby"}}"#;
+        let text = format!(
+            r#"{{"id":["x",2],"state":null,"masked_traits":[],"objects":[{nick},{nick}]}}"#
+        );
+        let err = serde_json::from_str::<Component>(&text).unwrap_err();
+        assert!(err.to_string().starts_with("object Nickname appears twice"));
+
+        let nick = nick.replace(r#""key":"""#, r#""key":"much_too_long_to_be_a_key""#);
+        let text =
+            format!(r#"{{"id":["x",4],"state":null,"masked_traits":[],"objects":[{nick}]}}"#);
+        let err = serde_json::from_str::<Component>(&text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("'much_too_long_to_be_a_key' is too long to be a key"));
+
+        let text = r#"{"id":["x",3],"state":"much_too_long_to_be_a_state","masked_traits":[],
+            "objects":[]}"#;
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("'much_too_long_to_be_a_state' is too long to be a key"));
+
+        // Ids of deserialized components are reserved.
+        let text = r#"{"id":["x",900000],"state":null,"masked_traits":[],"objects":[]}"#;
+        let component: Component = serde_json::from_str(text).unwrap();
+        assert!(Component::new("x").id > component.id);
+
+        let text = r#"{"id":["x",4294967295],"state":null,"masked_traits":[],"objects":[]}"#;
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("component id 4294967295 is too large"));
+    }
+
+    #[test]
+    fn ambiguous_names() {
+        mod first {
+            use super::*;
+
+            #[allow(dead_code)]
+            pub struct Twin;
+            register_type!(Twin);
+        }
+
+        mod second {
+            use super::*;
+
+            #[allow(dead_code)]
+            pub struct Twin;
+            register_type!(Twin);
+        }
+
+        first::get_twin_id();
+        assert!(find_type("Twin").is_ok());
+        second::get_twin_id();
+        let err = find_type("Twin").unwrap_err();
+        assert_eq!(err, TypeNameError::Ambiguous("Twin".to_owned()));
+
+        let text = r#"{"id":["x",5],"state":null,"masked_traits":[],"objects":[
+            {"type":"Twin","version":1,"key":"","state":null,"masked":false,"traits":[],"data":{}}
+        ]}"#;
+        let err = serde_json::from_str::<Component>(text).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("more than one type is named 'Twin'"));
+    }
+}
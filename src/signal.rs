@@ -0,0 +1,397 @@
+//! Signals notify subscribers when something happens to a component. Signals are types
+//! registered with [`register_type`], emitted with [`emit`], and handled by [`subscribe`].
+//!
+//! [`register_type`]: crate::register_type
+//! [`emit`]: crate::emit
+//! [`subscribe`]: crate::subscribe
+use super::*;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// When subscribers are called.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delivery {
+    /// Called when the signal is emitted if possible. This is best-effort: if a handler
+    /// is running or the component is borrowed then delivery is deferred.
+    Immediate,
+
+    /// Called by [`Component::dispatch_signals`].
+    Queued,
+}
+
+/// Returned by [`subscribe`] and used to unsubscribe.
+///
+/// [`subscribe`]: crate::subscribe
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SignalHandle(u64);
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+// Handlers and signals are only Send or Sync if the threading marker requires it, see
+// the Send and Sync impls for Component.
+type Handler<M> = Box<dyn Fn(&Component<M>, &dyn Any)>;
+
+struct Subscriber<M: Threading> {
+    handle: SignalHandle,
+    signal_id: TypeId,
+    delivery: Delivery,
+    handler: Handler<M>,
+    subscribed: AtomicBool, // cleared on unsubscribe so pending signals aren't delivered
+}
+
+type Pending<M> = (Arc<Subscriber<M>>, Arc<dyn Any>);
+
+// The subscribers and undelivered signals for a component.
+pub(crate) struct Signals<M: Threading> {
+    subscribers: Mutex<Vec<Arc<Subscriber<M>>>>,
+    deferred: Mutex<VecDeque<Pending<M>>>, // immediate signals that couldn't be delivered yet
+    queued: Mutex<VecDeque<Pending<M>>>,
+    delivering: AtomicBool, // set while handlers are being called
+}
+
+impl<M: Threading> Signals<M> {
+    pub(crate) fn new() -> Signals<M> {
+        Signals {
+            subscribers: Mutex::new(Vec::new()),
+            deferred: Mutex::new(VecDeque::new()),
+            queued: Mutex::new(VecDeque::new()),
+            delivering: AtomicBool::new(false),
+        }
+    }
+
+    // Calls the handlers for the pending signals unless handlers are already being called.
+    fn deliver(&self, component: &Component<M>, pending: &Mutex<VecDeque<Pending<M>>>) {
+        // Clears the flag even if a handler panics.
+        struct Delivering<'a>(&'a AtomicBool);
+
+        impl Drop for Delivering<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+
+        while !pending.lock().unwrap().is_empty() && !self.delivering.swap(true, Ordering::Acquire)
+        {
+            let _delivering = Delivering(&self.delivering);
+            loop {
+                let next = pending.lock().unwrap().pop_front();
+                let Some((subscriber, signal)) = next else {
+                    break;
+                };
+                if subscriber.subscribed.load(Ordering::Relaxed) {
+                    (subscriber.handler)(component, &*signal);
+                }
+            }
+        }
+    }
+}
+
+impl<M: Threading> Component<M> {
+    // Normally the [`subscribe`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn subscribe<Signal, Handler>(
+        &self,
+        signal_id: TypeId,
+        delivery: Delivery,
+        handler: Handler,
+    ) -> SignalHandle
+    where
+        Signal: 'static,
+        Handler: Fn(&Component<M>, &Signal) + 'static,
+        M: Admits<Handler>,
+    {
+        let handle = SignalHandle(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed));
+        let subscriber = Subscriber {
+            handle,
+            signal_id,
+            delivery,
+            handler: Box::new(move |component, signal| {
+                handler(component, signal.downcast_ref::<Signal>().unwrap())
+            }),
+            subscribed: AtomicBool::new(true),
+        };
+        let signals = self.signals();
+        signals
+            .subscribers
+            .lock()
+            .unwrap()
+            .push(Arc::new(subscriber));
+        handle
+    }
+
+    /// Removes a subscriber, including any signals that haven't been delivered to it.
+    /// Returns false if the subscriber wasn't found. May be called from within a handler.
+    pub fn unsubscribe(&self, handle: SignalHandle) -> bool {
+        let mut subscribers = self.signals().subscribers.lock().unwrap();
+        match subscribers.iter().position(|s| s.handle == handle) {
+            Some(index) => {
+                let subscriber = subscribers.remove(index);
+                subscriber.subscribed.store(false, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Normally the [`emit`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn emit<Signal: 'static>(&self, signal_id: TypeId, signal: Signal)
+    where
+        M: Admits<Signal>,
+    {
+        let signals = self.signals();
+        let subscribers: Vec<_> = signals
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| s.signal_id == signal_id)
+            .cloned()
+            .collect();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let signal: Arc<dyn Any> = Arc::new(signal);
+        for subscriber in subscribers {
+            let pending = match subscriber.delivery {
+                Delivery::Immediate => &signals.deferred,
+                Delivery::Queued => &signals.queued,
+            };
+            pending
+                .lock()
+                .unwrap()
+                .push_back((subscriber, signal.clone()));
+        }
+        if !self.is_borrowed() {
+            signals.deliver(self, &signals.deferred);
+        }
+    }
+
+    /// Calls the queued handlers along with immediate handlers whose delivery was
+    /// deferred. Normally called once per tick.
+    pub fn dispatch_signals(&self) {
+        let signals = self.signals();
+        signals.deliver(self, &signals.deferred);
+        signals.deliver(self, &signals.queued);
+    }
+}
+
+/// Emits a signal to the component's subscribers.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+/// use std::sync::atomic::{AtomicI32, Ordering};
+/// use std::sync::Arc;
+///
+/// struct HungerChanged {
+///     old: i32,
+///     new: i32,
+/// }
+/// register_type!(HungerChanged);
+///
+/// let component = Component::new("rabbit");
+/// let total = Arc::new(AtomicI32::new(0));
+/// let counter = total.clone();
+/// let handle = subscribe!(component, HungerChanged, move |_, signal| {
+///     counter.fetch_add(signal.new - signal.old, Ordering::Relaxed);
+/// });
+///
+/// emit!(component, HungerChanged { old: 10, new: 15 });
+/// assert_eq!(total.load(Ordering::Relaxed), 5);
+///
+/// component.unsubscribe(handle);
+/// emit!(component, HungerChanged { old: 15, new: 20 });
+/// assert_eq!(total.load(Ordering::Relaxed), 5);
+/// ```
+#[macro_export]
+macro_rules! emit {
+    ($component:expr, $signal:ident $($body:tt)*) => {{
+        paste! {
+            $component.emit([<get_ $signal:lower _id>](), $signal $($body)*)
+        }
+    }};
+}
+
+/// Adds a handler that is called when a signal is emitted and returns a handle that can
+/// be used to unsubscribe. The handler is called with the component and the signal.
+/// Like objects, handlers and signals must be `Send + Sync` for [`Component`] and `Send`
+/// for [`SendComponent`].
+#[macro_export]
+macro_rules! subscribe {
+    ($component:expr, $signal:ty, $handler:expr) => {{
+        paste! {
+            $component.subscribe::<$signal, _>([<get_ $signal:lower _id>](), Delivery::Immediate, $handler)
+        }
+    }};
+}
+
+/// Like [`subscribe`] except that the handler is called by
+/// [`Component::dispatch_signals`].
+#[macro_export]
+macro_rules! subscribe_queued {
+    ($component:expr, $signal:ty, $handler:expr) => {{
+        paste! {
+            $component.subscribe::<$signal, _>([<get_ $signal:lower _id>](), Delivery::Queued, $handler)
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::cell::Cell;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    trait Fullness {
+        fn fullness(&self) -> i32;
+        fn eat(&mut self, component: &Component, amount: i32);
+    }
+    register_type!(Fullness);
+
+    struct Belly {
+        fullness: i32,
+    }
+    register_type!(Belly);
+
+    #[derive(Debug, PartialEq)]
+    struct Ate {
+        old: i32,
+        new: i32,
+    }
+    register_type!(Ate);
+
+    struct Burped;
+    register_type!(Burped);
+
+    impl Fullness for Belly {
+        fn fullness(&self) -> i32 {
+            self.fullness
+        }
+
+        fn eat(&mut self, component: &Component, amount: i32) {
+            let old = self.fullness;
+            self.fullness += amount;
+            emit!(
+                component,
+                Ate {
+                    old,
+                    new: self.fullness
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn delivery() {
+        let mut component = Component::new("belly");
+        add_object!(component, Belly, Belly { fullness: 0 }, [Fullness]);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let now = log.clone();
+        subscribe!(component, Ate, move |component, signal| {
+            // Handlers can look at the object that emitted the signal.
+            let fullness = find_trait!(component, Fullness).unwrap().fullness();
+            assert_eq!(fullness, signal.new);
+            now.lock()
+                .unwrap()
+                .push(format!("now {} {}", signal.old, signal.new));
+        });
+        let later = log.clone();
+        let queued = subscribe_queued!(component, Ate, move |_, signal| {
+            later
+                .lock()
+                .unwrap()
+                .push(format!("later {} {}", signal.old, signal.new));
+        });
+
+        // The emitter has Belly mutably borrowed so delivery is deferred.
+        find_trait_mut!(component, Fullness)
+            .unwrap()
+            .eat(&component, 5);
+        assert!(log.lock().unwrap().is_empty());
+
+        component.dispatch_signals();
+        assert_eq!(*log.lock().unwrap(), vec!["now 0 5", "later 0 5"]);
+
+        log.lock().unwrap().clear();
+        emit!(component, Ate { old: 5, new: 5 });
+        assert_eq!(*log.lock().unwrap(), vec!["now 5 5"]);
+
+        assert!(component.unsubscribe(queued));
+        assert!(!component.unsubscribe(queued));
+        component.dispatch_signals();
+        assert_eq!(*log.lock().unwrap(), vec!["now 5 5"]);
+    }
+
+    #[test]
+    fn reentrancy() {
+        let component = Component::new("belly");
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let inner = log.clone();
+        subscribe!(component, Burped, move |component, _| {
+            inner.lock().unwrap().push("burped".to_owned());
+            emit!(component, Ate { old: 0, new: 1 });
+            inner.lock().unwrap().push("handled".to_owned());
+        });
+        let inner = log.clone();
+        subscribe!(component, Ate, move |_, signal| {
+            inner
+                .lock()
+                .unwrap()
+                .push(format!("ate {} {}", signal.old, signal.new));
+        });
+        let handle = Arc::new(Mutex::new(None));
+        let inner = handle.clone();
+        *handle.lock().unwrap() = Some(subscribe!(component, Ate, move |component, _| {
+            // Handlers can unsubscribe themselves.
+            let handle = inner.lock().unwrap().take().unwrap();
+            assert!(component.unsubscribe(handle));
+        }));
+
+        emit!(component, Burped);
+        emit!(component, Burped);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["burped", "handled", "ate 0 1", "burped", "handled", "ate 0 1"]
+        );
+    }
+
+    #[test]
+    fn panicking_handler() {
+        let component = Component::new("belly");
+        let handle = subscribe!(component, Burped, |_, _| panic!("hiccup"));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| emit!(component, Burped)));
+        assert!(result.is_err());
+
+        // Later signals are still delivered.
+        component.unsubscribe(handle);
+        let count = Arc::new(Mutex::new(0));
+        let inner = count.clone();
+        subscribe!(component, Burped, move |_, _| *inner.lock().unwrap() += 1);
+        emit!(component, Burped);
+        assert_eq!(*count.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn local() {
+        struct Poked(Rc<i32>);
+        register_type!(Poked);
+
+        // Local components allow handlers and signals that aren't Send or Sync.
+        let component = Component::new_local("local");
+        let total = Rc::new(Cell::new(0));
+        let counter = total.clone();
+        subscribe!(component, Poked, move |_, signal| {
+            counter.set(counter.get() + *signal.0)
+        });
+        emit!(component, Poked(Rc::new(3)));
+        assert_eq!(total.get(), 3);
+    }
+}
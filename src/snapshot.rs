@@ -0,0 +1,311 @@
+//! Snapshots save an entire [`ComponentStore`] using a compact, checksummed binary format.
+//! Objects must be registered with the serde option.
+use super::*;
+use crate::binary::{BinaryReader, BinaryWriter};
+use crate::component_id::{defer_reserving_ids, peek_next_component_id, restore_next_component_id};
+use serde::{de, ser, Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Formatter};
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"GEARSNAP";
+
+/// Incremented when the snapshot format changes (this is not the same as object versions
+/// which are handled by migrations).
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+
+    /// The data doesn't start with the snapshot magic bytes.
+    BadHeader,
+
+    /// The snapshot was written with a newer format version.
+    UnsupportedVersion(u32),
+
+    /// The data was truncated or corrupted.
+    BadChecksum,
+
+    /// The data couldn't be encoded or decoded, e.g. because an object wasn't registered
+    /// with the serde option.
+    Format(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{err}"),
+            SnapshotError::BadHeader => write!(f, "not a gear snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {version} is newer than version {SNAPSHOT_VERSION}"
+            ),
+            SnapshotError::BadChecksum => write!(f, "snapshot checksum doesn't match"),
+            SnapshotError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl ser::Error for SnapshotError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SnapshotError::Format(message.to_string())
+    }
+}
+
+impl de::Error for SnapshotError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SnapshotError::Format(message.to_string())
+    }
+}
+
+// The checksum is FNV-1a over everything preceding it.
+struct ChecksumWriter<W: Write> {
+    writer: W,
+    hasher: fnv::FnvHasher,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.writer.write(buf)?;
+        self.hasher.write(&buf[..count]);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+struct ChecksumReader<R: Read> {
+    reader: R,
+    hasher: fnv::FnvHasher,
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.hasher.write(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// Writes all of the components in store to writer.
+pub fn write_snapshot<M: Threading>(
+    store: &ComponentStore<M>,
+    writer: impl Write,
+) -> Result<(), SnapshotError> {
+    let mut writer = ChecksumWriter {
+        writer,
+        hasher: fnv::FnvHasher::default(),
+    };
+    writer.write_all(MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+
+    let mut binary = BinaryWriter::new(&mut writer);
+    peek_next_component_id().serialize(&mut binary)?;
+    store.serialize(&mut binary)?;
+
+    let checksum = writer.hasher.finish();
+    writer.writer.write_all(&checksum.to_le_bytes())?;
+    writer.writer.flush()?;
+    Ok(())
+}
+
+/// Reads a store written by [`write_snapshot`]. Component ids allocated afterwards will
+/// not collide with ids that were allocated when the snapshot was written.
+pub fn read_snapshot<M: Threading>(reader: impl Read) -> Result<ComponentStore<M>, SnapshotError> {
+    let mut reader = ChecksumReader {
+        reader,
+        hasher: fnv::FnvHasher::default(),
+    };
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(SnapshotError::BadHeader);
+    }
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    // Ids are only reserved once the checksum has been verified.
+    let (result, reserved) = defer_reserving_ids(|| {
+        let mut binary = BinaryReader::new(&mut reader);
+        let next_id = u32::deserialize(&mut binary)?;
+        let store = ComponentStore::deserialize(&mut binary)?;
+
+        let expected = reader.hasher.finish();
+        let mut checksum = [0u8; 8];
+        reader.reader.read_exact(&mut checksum)?;
+        if u64::from_le_bytes(checksum) != expected {
+            return Err(SnapshotError::BadChecksum);
+        }
+        Ok((next_id, store))
+    });
+    let (next_id, store) = result?;
+    restore_next_component_id(next_id.max(reserved));
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Debug;
+    use paste::paste;
+    use std::sync::Arc;
+
+    trait Weighted {
+        fn weight(&self) -> f64;
+    }
+    register_type!(Weighted);
+
+    #[derive(Debug, Deserialize, Serialize)]
+    enum Cargo {
+        Empty,
+        Coal(u32),
+        Mixed { tons: i64, items: Vec<String> },
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Wagon {
+        label: String,
+        weight: f64,
+        cargo: Cargo,
+        owner: Option<char>,
+    }
+    register_type!(Wagon, serde, traits = [Weighted], repeated_traits = [Debug]);
+
+    impl Weighted for Wagon {
+        fn weight(&self) -> f64 {
+            self.weight
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut base = Component::new("base");
+        let spare = Wagon {
+            label: "spare".to_owned(),
+            weight: 1.0,
+            cargo: Cargo::Empty,
+            owner: None,
+        };
+        add_keyed_object!(base, Wagon, "spare", spare, [Weighted]);
+        let base = Arc::new(base);
+        register_prototype("spare wagon", base.clone());
+
+        let mut store = ComponentStore::new();
+        let mut first = Component::new("wagon");
+        let wagon = Wagon {
+            label: "a".to_owned(),
+            weight: -2.5,
+            cargo: Cargo::Coal(300),
+            owner: Some('é'),
+        };
+        add_object!(first, Wagon, wagon, [Weighted], [Debug]);
+        store.insert(first);
+        let mut second = Component::new("wagon");
+        let wagon = Wagon {
+            label: "b".to_owned(),
+            weight: 1e10,
+            cargo: Cargo::Mixed {
+                tons: -4,
+                items: vec!["tv".to_owned(), "lamp".to_owned()],
+            },
+            owner: None,
+        };
+        add_object!(second, Wagon, wagon, [Weighted], [Debug]);
+        second.set_prototype(base);
+        let id = second.id;
+        store.insert(second);
+
+        let mut bytes = Vec::new();
+        write_snapshot(&store, &mut bytes).unwrap();
+        let copy: ComponentStore = read_snapshot(bytes.as_slice()).unwrap();
+        assert_eq!(copy.ids(), store.ids());
+        for id in store.ids() {
+            let lhs = store.get(id).unwrap();
+            let rhs = copy.get(id).unwrap();
+            let lhs = format!("{:?}", &*find_repeated_trait!(lhs, Debug).next().unwrap());
+            let rhs = format!("{:?}", &*find_repeated_trait!(rhs, Debug).next().unwrap());
+            assert_eq!(lhs, rhs);
+        }
+
+        let component = copy.get(id).unwrap();
+        assert_eq!(find_trait!(component, Weighted).unwrap().weight(), 1e10);
+        assert!(component.prototype().is_some());
+
+        // Ids allocated after the snapshot was taken are still unique.
+        assert!(Component::new("wagon").id > id);
+    }
+
+    #[test]
+    fn errors() {
+        let mut store = ComponentStore::new();
+        let far = ComponentId::new("far", 1 << 30);
+        let mut component: Component = Component::with_id(far);
+        let wagon = Wagon {
+            label: "zebra".to_owned(),
+            weight: 1.0,
+            cargo: Cargo::Empty,
+            owner: None,
+        };
+        add_object!(component, Wagon, wagon, [Weighted], [Debug]);
+        store.insert(component);
+        let mut bytes = Vec::new();
+        write_snapshot(&store, &mut bytes).unwrap();
+
+        // Corrupt a label so that the snapshot still decodes. The ids of the components
+        // in the snapshot aren't reserved.
+        let mut corrupted = bytes.clone();
+        let label = corrupted.windows(5).position(|w| w == b"zebra").unwrap();
+        corrupted[label] = b'Z';
+        let err = read_snapshot::<Shared>(corrupted.as_slice()).err();
+        assert!(matches!(err, Some(SnapshotError::BadChecksum)));
+        assert!(Component::new("far").id < far);
+
+        let mut newer = bytes.clone();
+        newer[8] = 99;
+        let err = read_snapshot::<Shared>(newer.as_slice()).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "snapshot version 99 is newer than version 1"
+        );
+
+        let err = read_snapshot::<Shared>(&b"GEARSNIP"[..]).err();
+        assert!(matches!(err, Some(SnapshotError::BadHeader)));
+
+        let err = read_snapshot::<Shared>(&bytes[..bytes.len() - 1]).err();
+        assert!(matches!(err, Some(SnapshotError::Io(_))));
+
+        let mut store = ComponentStore::new();
+        let mut component = Component::new("orphan");
+        component.set_prototype(Arc::new(Component::new("base")));
+        let id = component.id;
+        store.insert(component);
+        let err = write_snapshot(&store, io::sink()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{id}'s prototype isn't registered (see register_prototype)")
+        );
+    }
+}
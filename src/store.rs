@@ -0,0 +1,95 @@
+use super::*;
+use fnv::FnvHashMap;
+
+/// A collection of components indexed by their ids. With the serde feature stores can be
+/// serialized, e.g. to save a game.
+pub struct ComponentStore<M: Threading = Shared> {
+    components: FnvHashMap<ComponentId, Component<M>>,
+}
+
+impl<M: Threading> ComponentStore<M> {
+    pub fn new() -> ComponentStore<M> {
+        ComponentStore {
+            components: FnvHashMap::default(),
+        }
+    }
+
+    /// Adds a component returning the old component if there was already one with the
+    /// same id.
+    pub fn insert(&mut self, component: Component<M>) -> Option<Component<M>> {
+        self.components.insert(component.id, component)
+    }
+
+    pub fn remove(&mut self, id: ComponentId) -> Option<Component<M>> {
+        self.components.remove(&id)
+    }
+
+    pub fn get(&self, id: ComponentId) -> Option<&Component<M>> {
+        self.components.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: ComponentId) -> Option<&mut Component<M>> {
+        self.components.get_mut(&id)
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.components.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Returns the ids of all the components in sorted order.
+    pub fn ids(&self) -> Vec<ComponentId> {
+        let mut ids: Vec<_> = self.components.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the ids of the components that changed at or after tick in sorted order,
+    /// see [`Component::changed_since`]. Removed components are not included.
+    pub fn changed_since(&self, tick: u32) -> Vec<ComponentId> {
+        let mut ids: Vec<_> = self
+            .components
+            .values()
+            .filter(|component| component.changed_since(tick))
+            .map(|component| component.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Calls [`Component::swap_buffers`] on each component, normally at the end of a tick.
+    pub fn swap_buffers(&mut self) {
+        for component in self.components.values_mut() {
+            component.swap_buffers();
+        }
+    }
+
+    /// Calls [`Component::dispatch_signals`] on each component in arbitrary order.
+    pub fn dispatch_signals(&self) {
+        for component in self.components.values() {
+            component.dispatch_signals();
+        }
+    }
+
+    /// Iterates over the components in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &Component<M>> {
+        self.components.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Component<M>> {
+        self.components.values_mut()
+    }
+}
+
+impl<M: Threading> Default for ComponentStore<M> {
+    fn default() -> Self {
+        ComponentStore::new()
+    }
+}
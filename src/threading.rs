@@ -0,0 +1,223 @@
+use super::*;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Marker types used to parameterize [`Component`] by the threading guarantees its
+/// objects provide. This controls which objects can be added to the component, how
+/// outstanding trait references are counted, and whether the component itself can be
+/// sent to or shared with other threads.
+///
+/// [`Component`]: crate::Component
+pub trait Threading: 'static {
+    #[doc(hidden)]
+    type Refs: BorrowCounts;
+}
+
+/// Used by [`Component`] which requires that objects be `Send + Sync` and is itself
+/// `Send + Sync`. Borrow counts use atomics.
+///
+/// [`Component`]: crate::Component
+pub struct Shared;
+
+/// Used by [`SendComponent`] which requires that objects be `Send` and is itself `Send`
+/// but not `Sync`. Borrow counts are not atomic.
+///
+/// [`SendComponent`]: crate::SendComponent
+pub struct Sendable;
+
+/// Used by [`LocalComponent`] which allows any object (e.g. one using `Rc` or `RefCell`)
+/// but is neither `Send` nor `Sync`. Borrow counts are not atomic.
+///
+/// [`LocalComponent`]: crate::LocalComponent
+pub struct Local;
+
+impl Threading for Shared {
+    type Refs = ObjectRefs;
+}
+
+impl Threading for Sendable {
+    type Refs = LocalRefs;
+}
+
+impl Threading for Local {
+    type Refs = LocalRefs;
+}
+
+/// Implemented by threading markers for the objects they allow to be added to a
+/// component.
+pub trait Admits<Object>: Threading {}
+
+impl<Object: Send + Sync> Admits<Object> for Shared {}
+impl<Object: Send> Admits<Object> for Sendable {}
+impl<Object> Admits<Object> for Local {}
+
+/// Implemented by threading markers whose components can share a prototype, see
+/// [`Component::set_prototype`].
+///
+/// [`Component::set_prototype`]: crate::Component::set_prototype
+pub trait SharesPrototypes: Threading {}
+
+impl SharesPrototypes for Shared {}
+impl SharesPrototypes for Local {}
+
+/// Tracks the outstanding trait references for a single object along with the change
+/// tick at which the object was last mutated.
+#[doc(hidden)]
+pub trait BorrowCounts: Default {
+    fn borrow(&self);
+    fn try_borrow(&self) -> bool; // false if there is a mutable reference
+    fn release(&self);
+    fn borrow_mut(&self);
+    fn release_mut(&self);
+    fn is_borrowed(&self) -> bool;
+    fn mark_changed(&self);
+    fn changed(&self) -> u32;
+}
+
+/// Borrow counts used by components that can be shared across threads.
+#[doc(hidden)]
+pub struct ObjectRefs {
+    immutable_refs: AtomicU32,
+    mutable_refs: AtomicU32,
+    changed: AtomicU32,
+}
+
+// New objects count as changed.
+impl Default for ObjectRefs {
+    fn default() -> Self {
+        ObjectRefs {
+            immutable_refs: AtomicU32::new(0),
+            mutable_refs: AtomicU32::new(0),
+            changed: AtomicU32::new(change_tick()),
+        }
+    }
+}
+
+impl BorrowCounts for ObjectRefs {
+    fn borrow(&self) {
+        let old = self.immutable_refs.fetch_add(1, Ordering::Relaxed);
+        assert!(old < u32::MAX, "immutable_refs wrapped around");
+        assert!(
+            self.mutable_refs.load(Ordering::Relaxed) == 0,
+            "mutable reference already exists"
+        );
+    }
+
+    fn try_borrow(&self) -> bool {
+        let old = self.immutable_refs.fetch_add(1, Ordering::Relaxed);
+        assert!(old < u32::MAX, "immutable_refs wrapped around");
+        if self.mutable_refs.load(Ordering::Relaxed) == 0 {
+            true
+        } else {
+            self.immutable_refs.fetch_sub(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    fn release(&self) {
+        let old = self.immutable_refs.fetch_sub(1, Ordering::Relaxed);
+        assert!(old < u32::MAX, "immutable_refs wrapped around");
+    }
+
+    fn borrow_mut(&self) {
+        let old = self.mutable_refs.fetch_add(1, Ordering::Relaxed);
+        assert!(old == 0, "mutable reference already exists");
+        assert!(
+            self.immutable_refs.load(Ordering::Relaxed) == 0,
+            "immutable_ref already exists"
+        );
+    }
+
+    fn release_mut(&self) {
+        let old = self.mutable_refs.fetch_sub(1, Ordering::Relaxed);
+        assert!(old < u32::MAX, "mutable_refs wrapped around");
+    }
+
+    fn is_borrowed(&self) -> bool {
+        self.immutable_refs.load(Ordering::Relaxed) > 0
+            || self.mutable_refs.load(Ordering::Relaxed) > 0
+    }
+
+    fn mark_changed(&self) {
+        self.changed.store(change_tick(), Ordering::Relaxed);
+    }
+
+    fn changed(&self) -> u32 {
+        self.changed.load(Ordering::Relaxed)
+    }
+}
+
+/// Borrow counts used by components that are never shared across threads.
+#[doc(hidden)]
+pub struct LocalRefs {
+    immutable_refs: Cell<u32>,
+    mutable_refs: Cell<u32>,
+    changed: Cell<u32>,
+}
+
+impl Default for LocalRefs {
+    fn default() -> Self {
+        LocalRefs {
+            immutable_refs: Cell::new(0),
+            mutable_refs: Cell::new(0),
+            changed: Cell::new(change_tick()),
+        }
+    }
+}
+
+impl BorrowCounts for LocalRefs {
+    fn borrow(&self) {
+        let old = self.immutable_refs.get();
+        assert!(old < u32::MAX, "immutable_refs wrapped around");
+        assert!(
+            self.mutable_refs.get() == 0,
+            "mutable reference already exists"
+        );
+        self.immutable_refs.set(old + 1);
+    }
+
+    fn try_borrow(&self) -> bool {
+        if self.mutable_refs.get() == 0 {
+            self.borrow();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn release(&self) {
+        let old = self.immutable_refs.get();
+        assert!(old > 0, "immutable_refs wrapped around");
+        self.immutable_refs.set(old - 1);
+    }
+
+    fn borrow_mut(&self) {
+        assert!(
+            self.mutable_refs.get() == 0,
+            "mutable reference already exists"
+        );
+        assert!(
+            self.immutable_refs.get() == 0,
+            "immutable_ref already exists"
+        );
+        self.mutable_refs.set(1);
+    }
+
+    fn release_mut(&self) {
+        let old = self.mutable_refs.get();
+        assert!(old > 0, "mutable_refs wrapped around");
+        self.mutable_refs.set(old - 1);
+    }
+
+    fn is_borrowed(&self) -> bool {
+        self.immutable_refs.get() > 0 || self.mutable_refs.get() > 0
+    }
+
+    fn mark_changed(&self) {
+        self.changed.set(change_tick());
+    }
+
+    fn changed(&self) -> u32 {
+        self.changed.get()
+    }
+}
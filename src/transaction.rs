@@ -0,0 +1,379 @@
+use super::*;
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// The state of an object before it was first mutably borrowed within a transaction.
+pub(crate) enum Snapshot {
+    Cloned(Box<dyn Any>, AssignFn),
+    #[cfg(feature = "serde")]
+    Encoded(Vec<u8>, SerdeFns),
+}
+
+impl Snapshot {
+    // Returns None if the object wasn't registered with the clone (or serde) option or
+    // if it couldn't be serialized.
+    pub(crate) fn new(obj_id: ObjectId, object: &dyn Any) -> Option<Snapshot> {
+        let info = type_info(obj_id.type_id)?;
+        if let Some((clone, assign)) = info.clone {
+            return Some(Snapshot::Cloned(clone(object), assign));
+        }
+        #[cfg(feature = "serde")]
+        if let Some(serde) = info.serde {
+            let bytes = encode((serde.serialize)(object)).ok()?;
+            return Some(Snapshot::Encoded(bytes, serde));
+        }
+        None
+    }
+
+    // Rough number of bytes used by the snapshot: cloned objects don't include the
+    // memory they point to.
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            Snapshot::Cloned(saved, _) => mem::size_of_val(&**saved),
+            #[cfg(feature = "serde")]
+            Snapshot::Encoded(bytes, _) => bytes.len(),
+        }
+    }
+
+    // Leaves the object alone if the snapshot couldn't be deserialized.
+    pub(crate) fn restore(self, object: &mut dyn Any) -> Result<(), String> {
+        match self {
+            Snapshot::Cloned(saved, assign) => assign(object, saved),
+            #[cfg(feature = "serde")]
+            Snapshot::Encoded(bytes, serde) => {
+                let saved = decode(&bytes, serde).map_err(|err| err.to_string())?;
+                (serde.assign)(object, saved);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn encode(object: &dyn erased_serde::Serialize) -> Result<Vec<u8>, SnapshotError> {
+    use serde::Serialize;
+
+    let mut bytes = Vec::new();
+    object.serialize(&mut crate::binary::BinaryWriter::new(&mut bytes))?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "serde")]
+fn decode(bytes: &[u8], serde: SerdeFns) -> Result<Box<dyn Any>, erased_serde::Error> {
+    let mut reader = crate::binary::BinaryReader::new(bytes);
+    let mut erased = <dyn erased_serde::Deserializer>::erase(&mut reader);
+    (serde.deserialize)(&mut erased)
+}
+
+// Records objects as they are mutably borrowed within a transaction (or an undo command).
+// Scopes may nest: each has its own frame of snapshots.
+#[derive(Default)]
+pub(crate) struct Journal {
+    active: AtomicBool,
+    frames: Mutex<Vec<Vec<(ObjectId, Snapshot)>>>,
+}
+
+impl Journal {
+    pub(crate) fn begin(&self) {
+        self.frames.lock().unwrap().push(Vec::new());
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    // Returns the snapshots taken since the matching begin. If commit is set and there is
+    // an enclosing scope then the snapshots are handed to that scope instead.
+    pub(crate) fn end(&self, commit: bool) -> Vec<(ObjectId, Snapshot)> {
+        let mut frames = self.frames.lock().unwrap();
+        let mut saved = frames.pop().expect("journal wasn't begun");
+        self.active.store(!frames.is_empty(), Ordering::Relaxed);
+        if commit {
+            if let Some(outer) = frames.last_mut() {
+                for (obj_id, snapshot) in mem::take(&mut saved) {
+                    if outer.iter().all(|(id, _)| *id != obj_id) {
+                        outer.push((obj_id, snapshot));
+                    }
+                }
+            }
+        }
+        saved
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    // Called when an object within the component has been mutably borrowed.
+    pub(crate) fn record(&self, obj_id: ObjectId, object: &dyn Any) {
+        let mut frames = self.frames.lock().unwrap();
+        let saved = frames.last_mut().unwrap();
+        if saved.iter().all(|(id, _)| *id != obj_id) {
+            if let Some(snapshot) = Snapshot::new(obj_id, object) {
+                saved.push((obj_id, snapshot));
+            }
+        }
+    }
+}
+
+impl<M: Threading> Component<M> {
+    /// Calls f so that its changes are all-or-nothing: if f returns an error or panics
+    /// then the objects it mutably borrowed are restored. Only objects registered with
+    /// the clone (or serde) option are restored and prototypes are left alone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gear_objects::*;
+    /// use paste::paste;
+    ///
+    /// trait Hunger {
+    ///     fn eat(&mut self, amount: i32) -> Result<(), String>;
+    ///     fn hunger(&self) -> i32;
+    /// }
+    /// register_type!(Hunger);
+    ///
+    /// #[derive(Clone)]
+    /// struct Stomach {
+    ///     hunger: i32,
+    /// }
+    /// register_type!(Stomach, clone);
+    ///
+    /// impl Hunger for Stomach {
+    ///     fn eat(&mut self, amount: i32) -> Result<(), String> {
+    ///         self.hunger -= amount;
+    ///         if self.hunger < 0 {
+    ///             return Err("too full".to_owned());
+    ///         }
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn hunger(&self) -> i32 {
+    ///         self.hunger
+    ///     }
+    /// }
+    ///
+    /// let mut component = Component::new("rabbit");
+    /// add_object!(component, Stomach, Stomach { hunger: 10 }, [Hunger]);
+    ///
+    /// let result = component.transaction(|c| {
+    ///     let mut hunger = find_trait_mut!(c, Hunger).unwrap();
+    ///     hunger.eat(4)?;
+    ///     hunger.eat(8)
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(find_trait!(component, Hunger).unwrap().hunger(), 10);
+    /// ```
+    pub fn transaction<T, E>(
+        &mut self,
+        f: impl FnOnce(&Component<M>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.journal().begin();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        let saved = self.journal().end(matches!(result, Ok(Ok(_))));
+
+        match result {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => {
+                self.rollback(saved);
+                Err(err)
+            }
+            Err(payload) => {
+                self.rollback(saved);
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    // Objects whose snapshots can't be restored keep their changes.
+    fn rollback(&mut self, saved: Vec<(ObjectId, Snapshot)>) {
+        for (obj_id, snapshot) in saved {
+            if let Some(object) = self.writable_any_mut(obj_id) {
+                let _ = snapshot.restore(object);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+
+    trait Stock {
+        fn count(&self) -> i32;
+        fn take(&mut self, count: i32) -> Result<(), String>;
+    }
+    register_type!(Stock);
+
+    #[derive(Clone)]
+    struct Shelf {
+        count: i32,
+    }
+    register_type!(Shelf, clone);
+
+    impl Stock for Shelf {
+        fn count(&self) -> i32 {
+            self.count
+        }
+
+        fn take(&mut self, count: i32) -> Result<(), String> {
+            if count > self.count {
+                return Err(format!("only {} left", self.count));
+            }
+            self.count -= count;
+            Ok(())
+        }
+    }
+
+    // Not clonable so changes to it aren't rolled back.
+    struct Tally {
+        count: i32,
+    }
+    register_type!(Tally);
+
+    impl Stock for Tally {
+        fn count(&self) -> i32 {
+            self.count
+        }
+
+        fn take(&mut self, count: i32) -> Result<(), String> {
+            self.count -= count;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn commits() {
+        let mut component = Component::new("shop");
+        add_object!(component, Shelf, Shelf { count: 5 }, [Stock]);
+        component
+            .transaction(|c| find_trait_mut!(c, Stock).unwrap().take(2))
+            .unwrap();
+        assert_eq!(find_trait!(component, Stock).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn rolls_back() {
+        let mut component = Component::new("shop");
+        add_keyed_object!(component, Shelf, "apples", Shelf { count: 5 }, [Stock]);
+        add_keyed_object!(component, Tally, "sales", Tally { count: 0 }, [Stock]);
+
+        let result = component.transaction(|c| {
+            find_trait_mut!(c, Stock, "apples").unwrap().take(2)?;
+            find_trait_mut!(c, Stock, "sales").unwrap().take(-2)?;
+            find_trait_mut!(c, Stock, "apples").unwrap().take(9)
+        });
+        assert_eq!(result.unwrap_err(), "only 3 left");
+        assert_eq!(find_trait!(component, Stock, "apples").unwrap().count(), 5);
+        assert_eq!(find_trait!(component, Stock, "sales").unwrap().count(), 2);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            component.transaction(|c| {
+                find_trait_mut!(c, Stock, "apples").unwrap().take(1)?;
+                panic!("oops");
+                #[allow(unreachable_code)]
+                Ok::<(), String>(())
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(find_trait!(component, Stock, "apples").unwrap().count(), 5);
+
+        // Snapshots don't outlive the transaction.
+        component
+            .transaction(|c| find_trait_mut!(c, Stock, "apples").unwrap().take(1))
+            .unwrap();
+        let _ = component.transaction(|c| find_trait_mut!(c, Stock, "apples").unwrap().take(9));
+        assert_eq!(find_trait!(component, Stock, "apples").unwrap().count(), 4);
+    }
+
+    #[test]
+    fn nested() {
+        let mut first = Component::new("shop");
+        add_object!(first, Shelf, Shelf { count: 5 }, [Stock]);
+        let mut second = Component::new("shop");
+        add_object!(second, Shelf, Shelf { count: 2 }, [Stock]);
+
+        let result = first.transaction(|a| {
+            find_trait_mut!(a, Stock).unwrap().take(1)?;
+            second.transaction(|b| find_trait_mut!(b, Stock).unwrap().take(9))
+        });
+        assert!(result.is_err());
+        assert_eq!(find_trait!(first, Stock).unwrap().count(), 5);
+        assert_eq!(find_trait!(second, Stock).unwrap().count(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn encoded() {
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct Crate {
+            count: i32,
+        }
+        register_type!(Crate, serde);
+
+        impl Stock for Crate {
+            fn count(&self) -> i32 {
+                self.count
+            }
+
+            fn take(&mut self, count: i32) -> Result<(), String> {
+                self.count -= count;
+                Err("dropped".to_owned())
+            }
+        }
+
+        let mut component = Component::new("shop");
+        add_object!(component, Crate, Crate { count: 3 }, [Stock]);
+        let result = component.transaction(|c| find_trait_mut!(c, Stock).unwrap().take(2));
+        assert!(result.is_err());
+        assert_eq!(find_trait!(component, Stock).unwrap().count(), 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unrestorable() {
+        use serde::{de, ser};
+
+        // Can't be deserialized, and can't be serialized once it's empty.
+        struct Jar {
+            count: i32,
+        }
+        register_type!(Jar, serde);
+
+        impl serde::Serialize for Jar {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if self.count < 0 {
+                    return Err(<S::Error as ser::Error>::custom("overdrawn"));
+                }
+                serializer.serialize_i32(self.count)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for Jar {
+            fn deserialize<D: serde::Deserializer<'de>>(_: D) -> Result<Self, D::Error> {
+                Err(<D::Error as de::Error>::custom("sealed"))
+            }
+        }
+
+        impl Stock for Jar {
+            fn count(&self) -> i32 {
+                self.count
+            }
+
+            fn take(&mut self, count: i32) -> Result<(), String> {
+                self.count -= count;
+                Err("cracked".to_owned())
+            }
+        }
+
+        let mut component = Component::new("shop");
+        add_object!(component, Jar, Jar { count: 3 }, [Stock]);
+        let result = component.transaction(|c| find_trait_mut!(c, Stock).unwrap().take(5));
+        assert!(result.is_err());
+        assert_eq!(find_trait!(component, Stock).unwrap().count(), -2);
+        let result = component.transaction(|c| find_trait_mut!(c, Stock).unwrap().take(1));
+        assert!(result.is_err());
+        assert_eq!(find_trait!(component, Stock).unwrap().count(), -3);
+    }
+}
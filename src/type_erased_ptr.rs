@@ -3,23 +3,24 @@ use std::marker::Unsize;
 use std::mem::transmute;
 use std::ops::{Deref, DerefMut};
 use std::ptr::{self, DynMetadata, Pointee};
-use std::sync::atomic::{AtomicU32, Ordering};
 
 // Decomposed trait pointer.
 pub struct TypeErasedPointer {
-    pub object_id: TypeId,
+    pub object_id: ObjectId,
     pointer: *mut (),
     metadata: Box<*const ()>,
 }
 
 impl TypeErasedPointer {
-    pub fn from_trait<Object, Trait>(object_id: TypeId, pointer: *mut Object) -> Self
+    pub fn from_trait<Object, Trait>(object_id: ObjectId, pointer: *mut Object) -> Self
     where
         Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
-        Object: Unsize<Trait>,
+        Object: ?Sized + Unsize<Trait>,
     {
-        let (pointer, metadata) = (pointer as *mut Trait).to_raw_parts();
-        let metadata = unsafe { transmute(Box::new(metadata)) };
+        let pointer: *mut Trait = pointer;
+        let (pointer, metadata) = pointer.to_raw_parts();
+        let metadata =
+            unsafe { transmute::<Box<DynMetadata<Trait>>, Box<*const ()>>(Box::new(metadata)) };
 
         TypeErasedPointer {
             object_id,
@@ -28,49 +29,123 @@ impl TypeErasedPointer {
         }
     }
 
-    pub unsafe fn to_trait<'a, Trait>(&self, refs: &'a ObjectRefs) -> RefTrait<'a, Trait>
+    pub unsafe fn borrow_trait<'a, Trait, Refs>(&self, refs: &'a Refs) -> RefTrait<'a, Trait, Refs>
     where
         Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Refs: BorrowCounts,
     {
-        let old = refs
-            .immutable_refs
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        assert!(old < u32::MAX, "immutable_refs wrapped around");
-        assert!(
-            refs.mutable_refs.load(Ordering::Relaxed) == 0,
-            "mutable reference already exists"
-        );
-
-        let src = self.metadata.as_ref();
-        let metadata = unsafe { *transmute::<_, *const <Trait as Pointee>::Metadata>(src) };
-        let typed_ptr = ptr::from_raw_parts_mut::<Trait>(self.pointer, metadata);
+        refs.borrow();
         RefTrait {
-            trait_ptr: typed_ptr,
+            trait_ptr: self.typed::<Trait>(),
             refs,
+            adapter: None,
         }
     }
 
-    pub unsafe fn to_trait_mut<'a, Trait>(&self, refs: &'a ObjectRefs) -> RefMutTrait<'a, Trait>
+    // Borrows the object and then uses adapt to get at a trait the object doesn't
+    // implement.
+    pub unsafe fn borrow_adapted<'a, Trait, Refs>(
+        &self,
+        refs: &'a Refs,
+        adapt: AdaptFn,
+    ) -> RefTrait<'a, Trait, Refs>
     where
         Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Refs: BorrowCounts,
     {
-        let old = refs
-            .mutable_refs
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        assert!(old == 0, "mutable reference already exists");
-        assert!(
-            refs.immutable_refs.load(Ordering::Relaxed) == 0,
-            "immutable_ref already exists"
-        );
-
-        let src = self.metadata.as_ref();
-        let metadata = unsafe { *transmute::<_, *const <Trait as Pointee>::Metadata>(src) };
-        let typed_ptr = ptr::from_raw_parts_mut::<Trait>(self.pointer, metadata);
+        refs.borrow();
+        let (adapter, erased) = Adapter::new(self, adapt, false);
+        RefTrait {
+            trait_ptr: erased.typed::<Trait>(),
+            refs,
+            adapter: Some(adapter),
+        }
+    }
+
+    pub unsafe fn borrow_trait_mut<'a, Trait, Refs>(
+        &self,
+        refs: &'a Refs,
+    ) -> RefMutTrait<'a, Trait, Refs>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Refs: BorrowCounts,
+    {
+        refs.borrow_mut();
         RefMutTrait {
-            trait_ptr: typed_ptr,
+            trait_ptr: self.typed::<Trait>(),
             refs,
+            adapter: None,
+        }
+    }
+
+    pub unsafe fn borrow_adapted_mut<'a, Trait, Refs>(
+        &self,
+        refs: &'a Refs,
+        adapt: AdaptFn,
+    ) -> RefMutTrait<'a, Trait, Refs>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+        Refs: BorrowCounts,
+    {
+        refs.borrow_mut();
+        let (adapter, erased) = Adapter::new(self, adapt, true);
+        RefMutTrait {
+            trait_ptr: erased.typed::<Trait>(),
+            refs,
+            adapter: Some(adapter),
+        }
+    }
+
+    // Returns a pointer to the same trait but for a different object of the same type.
+    pub fn rebase(&self, pointer: *mut ()) -> TypeErasedPointer {
+        TypeErasedPointer {
+            object_id: self.object_id,
+            pointer,
+            metadata: Box::new(*self.metadata),
         }
     }
+
+    pub fn typed<Trait>(&self) -> *mut Trait
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let src = self.metadata.as_ref() as *const *const () as *const DynMetadata<Trait>;
+        let metadata = unsafe { *src };
+        ptr::from_raw_parts_mut::<Trait>(self.pointer, metadata)
+    }
+}
+
+// Borrows an object (or a trait of an object) using a pointer to the object.
+pub unsafe fn borrow_pointer<'a, Target, Refs>(
+    pointer: *mut Target,
+    refs: &'a Refs,
+) -> RefTrait<'a, Target, Refs>
+where
+    Target: ?Sized,
+    Refs: BorrowCounts,
+{
+    refs.borrow();
+    RefTrait {
+        trait_ptr: pointer,
+        refs,
+        adapter: None,
+    }
+}
+
+pub unsafe fn borrow_pointer_mut<'a, Target, Refs>(
+    pointer: *mut Target,
+    refs: &'a Refs,
+) -> RefMutTrait<'a, Target, Refs>
+where
+    Target: ?Sized,
+    Refs: BorrowCounts,
+{
+    refs.borrow_mut();
+    RefMutTrait {
+        trait_ptr: pointer,
+        refs,
+        adapter: None,
+    }
 }
 
 // Code can only get at these pointers except by going through the Component interface
@@ -79,17 +154,20 @@ impl TypeErasedPointer {
 unsafe impl Send for TypeErasedPointer {}
 unsafe impl Sync for TypeErasedPointer {}
 
-pub struct RefTrait<'a, Trait>
+pub struct RefTrait<'a, Trait, Refs = ObjectRefs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     trait_ptr: *mut Trait,
-    refs: &'a ObjectRefs,
+    refs: &'a Refs,
+    adapter: Option<Adapter>, // owns the object trait_ptr points to for adapted traits
 }
 
-impl<'a, Trait> Deref for RefTrait<'a, Trait>
+impl<'a, Trait, Refs> Deref for RefTrait<'a, Trait, Refs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     type Target = Trait;
 
@@ -98,30 +176,31 @@ where
     }
 }
 
-impl<'a, Trait> Drop for RefTrait<'a, Trait>
+impl<'a, Trait, Refs> Drop for RefTrait<'a, Trait, Refs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     fn drop(&mut self) {
-        let old = self
-            .refs
-            .immutable_refs
-            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-        assert!(old < u32::MAX, "immutable_refs wrapped around");
+        self.adapter = None;
+        self.refs.release();
     }
 }
 
-pub struct RefMutTrait<'a, Trait>
+pub struct RefMutTrait<'a, Trait, Refs = ObjectRefs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     trait_ptr: *mut Trait,
-    refs: &'a ObjectRefs,
+    refs: &'a Refs,
+    adapter: Option<Adapter>, // owns the object trait_ptr points to for adapted traits
 }
 
-impl<'a, Trait> Deref for RefMutTrait<'a, Trait>
+impl<'a, Trait, Refs> Deref for RefMutTrait<'a, Trait, Refs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     type Target = Trait;
 
@@ -130,37 +209,24 @@ where
     }
 }
 
-impl<'a, Trait> DerefMut for RefMutTrait<'a, Trait>
+impl<'a, Trait, Refs> DerefMut for RefMutTrait<'a, Trait, Refs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     fn deref_mut(&mut self) -> &mut Trait {
+        self.refs.mark_changed();
         unsafe { &mut *self.trait_ptr }
     }
 }
 
-impl<'a, Trait> Drop for RefMutTrait<'a, Trait>
+impl<'a, Trait, Refs> Drop for RefMutTrait<'a, Trait, Refs>
 where
-    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    Trait: ?Sized,
+    Refs: BorrowCounts,
 {
     fn drop(&mut self) {
-        let old = self
-            .refs
-            .mutable_refs
-            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-        assert!(old < u32::MAX, "mutable_refs wrapped around");
-    }
-}
-pub struct ObjectRefs {
-    immutable_refs: AtomicU32,
-    mutable_refs: AtomicU32,
-}
-
-impl ObjectRefs {
-    pub fn new() -> ObjectRefs {
-        ObjectRefs {
-            immutable_refs: AtomicU32::new(0),
-            mutable_refs: AtomicU32::new(0),
-        }
+        self.adapter = None;
+        self.refs.release_mut();
     }
 }
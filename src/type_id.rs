@@ -1,4 +1,12 @@
+#[cfg(feature = "serde")]
+use crate::serialization::SerdeFns;
+use crate::type_erased_ptr::TypeErasedPointer;
+use crate::{ObjectId, TypeNameError};
 use core::sync::atomic::AtomicU16;
+use std::any::Any;
+use std::marker::Unsize;
+use std::ptr::{DynMetadata, Pointee};
+use std::sync::{LazyLock, RwLock};
 
 /// Used to identify trait and object types. Note that these are generally not directly
 /// used by client code.
@@ -9,12 +17,233 @@ pub struct TypeId(pub u16);
 #[doc(hidden)]
 pub static NEXT_TYPE_ID: AtomicU16 = AtomicU16::new(0);
 
+/// Information about a type registered with [`register_type`].
+///
+/// [`register_type`]: crate::register_type
+#[derive(Clone, Debug)]
+pub struct TypeInfo {
+    /// The name of the type as it appeared in register_type.
+    pub name: &'static str,
+
+    /// Defaults to 1. Set using the version option with register_type.
+    pub version: u32,
+
+    /// Traits that are also exposed when an object is added using this trait. Set using
+    /// the supertraits and repeated_supertraits options with register_type.
+    pub supertraits: Vec<Supertrait>,
+
+    /// Traits an object may expose. Set using the traits and repeated_traits options with
+    /// register_type. Used when deserializing components.
+    pub traits: Vec<ObjectTrait>,
+
+    /// Set for objects registered with the clone option. Used by [`Component::try_clone`]
+    /// and [`Component::transaction`].
+    ///
+    /// [`Component::try_clone`]: crate::Component::try_clone
+    /// [`Component::transaction`]: crate::Component::transaction
+    #[doc(hidden)]
+    pub clone: Option<(CloneFn, AssignFn)>,
+
+    /// Set for objects registered with the double_buffered option, see
+    /// [`Component::swap_buffers`].
+    ///
+    /// [`Component::swap_buffers`]: crate::Component::swap_buffers
+    #[doc(hidden)]
+    pub double_buffered: Option<SwapFn>,
+
+    /// Set for objects registered with the serde option.
+    #[cfg(feature = "serde")]
+    #[doc(hidden)]
+    pub serde: Option<SerdeFns>,
+}
+
+/// A supertrait declared with [`register_type`].
+///
+/// [`register_type`]: crate::register_type
+#[derive(Clone, Debug)]
+pub struct Supertrait {
+    pub trait_id: TypeId,
+
+    /// Set if the supertrait is exposed as a repeated trait.
+    pub repeated: bool,
+
+    #[doc(hidden)]
+    pub upcast: fn(&TypeErasedPointer) -> TypeErasedPointer,
+}
+
+/// A trait declared for an object with [`register_type`].
+///
+/// [`register_type`]: crate::register_type
+#[derive(Clone, Debug)]
+pub struct ObjectTrait {
+    pub trait_id: TypeId,
+
+    /// Set if the trait is exposed as a repeated trait.
+    pub repeated: bool,
+
+    #[doc(hidden)]
+    pub expose: fn(ObjectId, *mut ()) -> TypeErasedPointer,
+}
+
+impl TypeInfo {
+    #[doc(hidden)]
+    pub fn new(name: &'static str) -> TypeInfo {
+        TypeInfo {
+            name,
+            version: 1,
+            supertraits: Vec::new(),
+            traits: Vec::new(),
+            clone: None,
+            double_buffered: None,
+            #[cfg(feature = "serde")]
+            serde: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn with_version(self, version: u32) -> TypeInfo {
+        TypeInfo { version, ..self }
+    }
+
+    #[doc(hidden)]
+    pub fn with_clone(self, clone: CloneFn, assign: AssignFn) -> TypeInfo {
+        TypeInfo {
+            clone: Some((clone, assign)),
+            ..self
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn with_double_buffering(self, swap: SwapFn) -> TypeInfo {
+        TypeInfo {
+            double_buffered: Some(swap),
+            ..self
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn with_supertrait(
+        mut self,
+        trait_id: TypeId,
+        repeated: bool,
+        upcast: fn(&TypeErasedPointer) -> TypeErasedPointer,
+    ) -> TypeInfo {
+        self.supertraits.push(Supertrait {
+            trait_id,
+            repeated,
+            upcast,
+        });
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn with_trait(
+        mut self,
+        trait_id: TypeId,
+        repeated: bool,
+        expose: fn(ObjectId, *mut ()) -> TypeErasedPointer,
+    ) -> TypeInfo {
+        self.traits.push(ObjectTrait {
+            trait_id,
+            repeated,
+            expose,
+        });
+        self
+    }
+
+    #[cfg(feature = "serde")]
+    #[doc(hidden)]
+    pub fn with_serde(self, serde: SerdeFns) -> TypeInfo {
+        TypeInfo {
+            serde: Some(serde),
+            ..self
+        }
+    }
+}
+
+#[doc(hidden)]
+pub type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+
+#[doc(hidden)]
+pub fn clone_object<Object: Clone + 'static>(object: &dyn Any) -> Box<dyn Any> {
+    Box::new(object.downcast_ref::<Object>().unwrap().clone())
+}
+
+// Overwrites an object with another object of the same type (the object isn't moved so
+// trait pointers to it remain valid).
+#[doc(hidden)]
+pub type AssignFn = fn(&mut dyn Any, Box<dyn Any>);
+
+#[doc(hidden)]
+pub fn assign_object<Object: 'static>(dst: &mut dyn Any, src: Box<dyn Any>) {
+    *dst.downcast_mut::<Object>().unwrap() = *src.downcast::<Object>().unwrap();
+}
+
+#[doc(hidden)]
+pub type SwapFn = fn(&mut dyn Any, &mut dyn Any);
+
+#[doc(hidden)]
+pub fn swap_objects<Object: 'static>(lhs: &mut dyn Any, rhs: &mut dyn Any) {
+    std::mem::swap(
+        lhs.downcast_mut::<Object>().unwrap(),
+        rhs.downcast_mut::<Object>().unwrap(),
+    );
+}
+
+#[doc(hidden)]
+pub fn expose_trait<Object, Trait>(obj_id: ObjectId, obj_ptr: *mut ()) -> TypeErasedPointer
+where
+    Object: Unsize<Trait> + 'static,
+    Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+{
+    TypeErasedPointer::from_trait::<Object, Trait>(obj_id, obj_ptr as *mut Object)
+}
+
+// Indexed by TypeId.
+static TYPE_INFOS: LazyLock<RwLock<Vec<Option<TypeInfo>>>> =
+    LazyLock::new(|| RwLock::new(Vec::new()));
+
+#[doc(hidden)]
+pub fn register_type_info(id: TypeId, info: TypeInfo) {
+    let mut infos = TYPE_INFOS.write().unwrap();
+    let index = id.0 as usize;
+    if infos.len() <= index {
+        infos.resize(index + 1, None);
+    }
+    infos[index] = Some(info);
+}
+
+/// Returns information about a type. Note that types are registered the first time
+/// their id is used (e.g. when an object is added to a component).
+pub fn type_info(id: TypeId) -> Option<TypeInfo> {
+    let infos = TYPE_INFOS.read().unwrap();
+    infos.get(id.0 as usize).cloned().flatten()
+}
+
+/// Returns the id of a type using the name it was registered with. As with [`type_info`]
+/// this only works for types whose id has been used.
+pub fn find_type(name: &str) -> Result<TypeId, TypeNameError> {
+    let infos = TYPE_INFOS.read().unwrap();
+    let mut ids = infos
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| info.as_ref().is_some_and(|info| info.name == name))
+        .map(|(index, _)| TypeId(index as u16));
+    match (ids.next(), ids.next()) {
+        (Some(id), None) => Ok(id),
+        (None, _) => Err(TypeNameError::Unknown(name.to_owned())),
+        (Some(_), Some(_)) => Err(TypeNameError::Ambiguous(name.to_owned())),
+    }
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! unique_type_id {
-    () => {{
+    ($info:expr) => {{
         static LOCAL_ID: std::sync::LazyLock<u16> = std::sync::LazyLock::new(|| {
-            NEXT_TYPE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            let id = NEXT_TYPE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            register_type_info(TypeId(id), $info);
+            id
         });
         TypeId(*LOCAL_ID)
     }};
@@ -0,0 +1,567 @@
+//! Undo support for editors: changes made by [`UndoHistory::command`] can be undone and
+//! redone. Objects are restored using the same snapshots as [`Component::transaction`].
+use super::*;
+use crate::transaction::Snapshot;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+type OperationFn<M> = Box<dyn FnMut(&mut ComponentStore<M>)>;
+
+// A change to the store. Applying an edit returns the edit that reverses it.
+enum Edit<M: Threading> {
+    Object(ComponentId, ObjectId, Snapshot),
+    Insert(Box<Component<M>>),
+    Remove(ComponentId),
+    Operation(OperationFn<M>, OperationFn<M>), // (apply, inverse)
+}
+
+impl<M: Threading> Edit<M> {
+    fn apply(self, store: &mut ComponentStore<M>) -> Option<Edit<M>> {
+        match self {
+            Edit::Object(id, obj_id, snapshot) => {
+                let object = store.get_mut(id)?.object_any_mut(obj_id)?;
+                let current = Snapshot::new(obj_id, object)?;
+                snapshot.restore(object).ok()?;
+                Some(Edit::Object(id, obj_id, current))
+            }
+            Edit::Insert(component) => {
+                let id = component.id;
+                store.insert(*component);
+                Some(Edit::Remove(id))
+            }
+            Edit::Remove(id) => store.remove(id).map(|c| Edit::Insert(Box::new(c))),
+            Edit::Operation(mut apply, inverse) => {
+                apply(store);
+                Some(Edit::Operation(inverse, apply))
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        let data = match self {
+            Edit::Object(_, _, snapshot) => snapshot.size(),
+            Edit::Insert(component) => mem::size_of::<Component<M>>() + component.objects_size(),
+            Edit::Remove(_) => 0,
+            Edit::Operation(apply, inverse) => {
+                mem::size_of_val(&**apply) + mem::size_of_val(&**inverse)
+            }
+        };
+        data + mem::size_of::<Edit<M>>()
+    }
+}
+
+struct Command<M: Threading> {
+    name: String,
+    edits: Vec<Edit<M>>, // in the order they should be applied
+    size: usize,
+}
+
+impl<M: Threading> Command<M> {
+    fn new(name: String, edits: Vec<Edit<M>>) -> Command<M> {
+        let size = edits.iter().map(|edit| edit.size()).sum();
+        Command { name, edits, size }
+    }
+
+    // Returns the command that reverses this one.
+    fn apply(self, store: &mut ComponentStore<M>) -> Command<M> {
+        let mut edits: Vec<_> = self
+            .edits
+            .into_iter()
+            .filter_map(|edit| edit.apply(store))
+            .collect();
+        edits.reverse();
+        Command::new(self.name, edits)
+    }
+}
+
+/// Passed into the closure given to [`UndoHistory::command`] and used to make changes
+/// that can be undone.
+pub struct Edits<'a, M: Threading = Shared> {
+    store: &'a mut ComponentStore<M>,
+    recording: RefCell<Vec<ComponentId>>, // components whose objects are being snapshotted
+    edits: Vec<Edit<M>>,
+}
+
+impl<'a, M: Threading> Edits<'a, M> {
+    /// Returns a component within the store. Objects within the component that are
+    /// mutably borrowed will be restored when the command is undone.
+    pub fn get(&self, id: ComponentId) -> Option<&Component<M>> {
+        let component = self.store.get(id)?;
+        let mut recording = self.recording.borrow_mut();
+        if !recording.contains(&id) {
+            component.journal().begin();
+            recording.push(id);
+        }
+        Some(component)
+    }
+
+    /// Adds a component to the store. Undo will remove it.
+    pub fn spawn(&mut self, component: Component<M>) {
+        self.stop_recording(true);
+        self.edits.push(Edit::Remove(component.id));
+        self.store.insert(component);
+    }
+
+    /// Removes a component from the store. Undo will add it back.
+    pub fn despawn(&mut self, id: ComponentId) -> bool {
+        self.stop_recording(true);
+        match self.store.remove(id) {
+            Some(component) => {
+                self.edits.push(Edit::Insert(Box::new(component)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls apply now and inverse when the command is undone. Use this for changes that
+    /// snapshots don't cover, e.g. adding an object to a component.
+    pub fn operation(
+        &mut self,
+        mut apply: impl FnMut(&mut ComponentStore<M>) + 'static,
+        inverse: impl FnMut(&mut ComponentStore<M>) + 'static,
+    ) {
+        self.stop_recording(true);
+        apply(self.store);
+        self.edits
+            .push(Edit::Operation(Box::new(inverse), Box::new(apply)));
+    }
+
+    // Records the snapshots taken so far so that edits stay in program order. Objects
+    // borrowed after this are snapshotted again by the next call to get.
+    fn stop_recording(&mut self, commit: bool) {
+        for id in mem::take(self.recording.get_mut()) {
+            if let Some(component) = self.store.get(id) {
+                let saved = component.journal().end(commit);
+                self.edits.extend(
+                    saved
+                        .into_iter()
+                        .map(|(obj_id, snapshot)| Edit::Object(id, obj_id, snapshot)),
+                );
+            }
+        }
+    }
+}
+
+/// Undo and redo stacks of commands applied to a [`ComponentStore`].
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+///
+/// trait Label {
+///     fn label(&self) -> String;
+///     fn set_label(&mut self, label: &str);
+/// }
+/// register_type!(Label);
+///
+/// #[derive(Clone)]
+/// struct Text {
+///     text: String,
+/// }
+/// register_type!(Text, clone);
+///
+/// impl Label for Text {
+///     fn label(&self) -> String {
+///         self.text.clone()
+///     }
+///
+///     fn set_label(&mut self, label: &str) {
+///         self.text = label.to_owned();
+///     }
+/// }
+///
+/// let mut store = ComponentStore::new();
+/// let mut component = Component::new("button");
+/// add_object!(component, Text, Text { text: "OK".to_owned() }, [Label]);
+/// let id = component.id;
+/// store.insert(component);
+///
+/// let mut history = UndoHistory::new();
+/// history.command(&mut store, "Rename", |edits| {
+///     let component = edits.get(id).unwrap();
+///     find_trait_mut!(component, Label).unwrap().set_label("Cancel");
+/// });
+/// assert_eq!(history.undo_name(), Some("Rename"));
+///
+/// history.undo(&mut store);
+/// assert_eq!(find_trait!(store.get(id).unwrap(), Label).unwrap().label(), "OK");
+///
+/// history.redo(&mut store);
+/// assert_eq!(find_trait!(store.get(id).unwrap(), Label).unwrap().label(), "Cancel");
+/// ```
+pub struct UndoHistory<M: Threading = Shared> {
+    undo: VecDeque<Command<M>>,
+    redo: Vec<Command<M>>,
+    limit: usize, // max bytes used by the commands
+    size: usize,
+}
+
+impl<M: Threading> UndoHistory<M> {
+    pub fn new() -> UndoHistory<M> {
+        UndoHistory::with_limit(usize::MAX)
+    }
+
+    /// Once the commands use more than limit bytes the oldest commands are discarded.
+    /// Sizes are estimates that don't include memory owned by cloned objects.
+    pub fn with_limit(limit: usize) -> UndoHistory<M> {
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+            size: 0,
+        }
+    }
+
+    /// Calls f to make changes to store that will be undone as a group. If f panics
+    /// then its changes are reverted and nothing is recorded. Commands that don't change
+    /// anything are not recorded. Clears the redo stack.
+    pub fn command<T>(
+        &mut self,
+        store: &mut ComponentStore<M>,
+        name: &str,
+        f: impl FnOnce(&mut Edits<'_, M>) -> T,
+    ) -> T {
+        let mut edits = Edits {
+            store,
+            recording: RefCell::new(Vec::new()),
+            edits: Vec::new(),
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut edits)));
+        edits.stop_recording(result.is_ok());
+        let value = match result {
+            Ok(value) => value,
+            Err(payload) => {
+                for edit in mem::take(&mut edits.edits).into_iter().rev() {
+                    edit.apply(edits.store);
+                }
+                panic::resume_unwind(payload)
+            }
+        };
+
+        if !edits.edits.is_empty() {
+            // Undo has to apply the edits in the reverse order.
+            let mut changes = edits.edits;
+            changes.reverse();
+            self.clear_redo();
+            self.push_undo(Command::new(name.to_owned(), changes));
+        }
+        value
+    }
+
+    /// Reverts the most recent command and returns its name.
+    pub fn undo(&mut self, store: &mut ComponentStore<M>) -> Option<String> {
+        let command = self.undo.pop_back()?;
+        self.size -= command.size;
+        let command = command.apply(store);
+        let name = command.name.clone();
+        self.size += command.size;
+        self.redo.push(command);
+        Some(name)
+    }
+
+    /// Re-applies the most recently undone command and returns its name.
+    pub fn redo(&mut self, store: &mut ComponentStore<M>) -> Option<String> {
+        let command = self.redo.pop()?;
+        self.size -= command.size;
+        let command = command.apply(store);
+        let name = command.name.clone();
+        self.push_undo(command);
+        Some(name)
+    }
+
+    /// The name of the command that undo would revert, e.g. for an "Undo Rename" menu item.
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo.back().map(|command| command.name.as_str())
+    }
+
+    pub fn redo_name(&self) -> Option<&str> {
+        self.redo.last().map(|command| command.name.as_str())
+    }
+
+    /// Estimated number of bytes used by the undo and redo stacks.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.size = 0;
+    }
+
+    fn clear_redo(&mut self) {
+        for command in self.redo.drain(..) {
+            self.size -= command.size;
+        }
+    }
+
+    fn push_undo(&mut self, command: Command<M>) {
+        self.size += command.size;
+        self.undo.push_back(command);
+        while self.size > self.limit {
+            match self.undo.pop_front() {
+                Some(command) => self.size -= command.size,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<M: Threading> Default for UndoHistory<M> {
+    fn default() -> Self {
+        UndoHistory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::rc::Rc;
+
+    trait Pixel {
+        fn color(&self) -> u32;
+        fn paint(&mut self, color: u32);
+    }
+    register_type!(Pixel);
+
+    #[derive(Clone)]
+    struct Dot {
+        color: u32,
+    }
+    register_type!(Dot, clone);
+
+    impl Pixel for Dot {
+        fn color(&self) -> u32 {
+            self.color
+        }
+
+        fn paint(&mut self, color: u32) {
+            self.color = color;
+        }
+    }
+
+    #[test]
+    fn objects() {
+        let mut component = Component::new("dot");
+        add_object!(component, Dot, Dot { color: 1 }, [Pixel]);
+        let id = component.id;
+        let mut store = ComponentStore::new();
+        store.insert(component);
+        let mut history = UndoHistory::new();
+        assert_eq!(history.undo(&mut store), None);
+
+        for color in [10, 20] {
+            history.command(&mut store, "Paint", |edits| {
+                let mut pixel = find_trait_mut!(edits.get(id).unwrap(), Pixel).unwrap();
+                pixel.paint(color);
+                pixel.paint(color + 1);
+            });
+        }
+        let color =
+            |store: &ComponentStore| find_trait!(store.get(id).unwrap(), Pixel).unwrap().color();
+        assert_eq!(color(&store), 21);
+
+        assert_eq!(history.undo(&mut store), Some("Paint".to_owned()));
+        assert_eq!(color(&store), 11);
+        history.undo(&mut store);
+        assert_eq!(color(&store), 1);
+        assert_eq!(history.undo_name(), None);
+        assert_eq!(history.redo_name(), Some("Paint"));
+        history.redo(&mut store);
+        assert_eq!(color(&store), 11);
+
+        // Commands that don't change anything aren't recorded.
+        history.command(&mut store, "Look", |edits| {
+            find_trait!(edits.get(id).unwrap(), Pixel).unwrap().color()
+        });
+        assert_eq!(history.undo_name(), Some("Paint"));
+        assert_eq!(history.redo_name(), Some("Paint"));
+
+        // New commands clear the redo stack.
+        history.command(&mut store, "Erase", |edits| {
+            find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                .unwrap()
+                .paint(0)
+        });
+        assert_eq!(history.redo_name(), None);
+        history.undo(&mut store);
+        assert_eq!(color(&store), 11);
+    }
+
+    #[test]
+    fn spawns() {
+        let mut first = Component::new("dot");
+        add_object!(first, Dot, Dot { color: 1 }, [Pixel]);
+        let first_id = first.id;
+        let mut second = Component::new("dot");
+        add_object!(second, Dot, Dot { color: 2 }, [Pixel]);
+        let second_id = second.id;
+        let mut store = ComponentStore::new();
+        store.insert(first);
+        let mut history = UndoHistory::new();
+
+        history.command(&mut store, "Replace", |edits| {
+            find_trait_mut!(edits.get(first_id).unwrap(), Pixel)
+                .unwrap()
+                .paint(5);
+            assert!(edits.despawn(first_id));
+            edits.spawn(second);
+        });
+        assert_eq!(store.ids(), vec![second_id]);
+
+        history.undo(&mut store);
+        assert_eq!(store.ids(), vec![first_id]);
+        let first = store.get(first_id).unwrap();
+        assert_eq!(find_trait!(first, Pixel).unwrap().color(), 1);
+
+        history.redo(&mut store);
+        assert_eq!(store.ids(), vec![second_id]);
+    }
+
+    #[test]
+    fn operations() {
+        let mut component = Component::new("dot");
+        add_object!(component, Dot, Dot { color: 1 }, [Pixel]);
+        let id = component.id;
+        let mut store = ComponentStore::new();
+        store.insert(component);
+        let mut history = UndoHistory::new();
+
+        history.command(&mut store, "Add Dot", |edits| {
+            edits.operation(
+                move |store| {
+                    let component = store.get_mut(id).unwrap();
+                    add_keyed_object!(component, Dot, "extra", Dot { color: 7 }, [Pixel]);
+                },
+                move |store| {
+                    let component = store.get_mut(id).unwrap();
+                    component
+                        .remove_object(ObjectId::new(get_dot_id(), "extra"))
+                        .unwrap();
+                },
+            );
+        });
+        assert!(has_trait!(store.get(id).unwrap(), Pixel, "extra"));
+        history.undo(&mut store);
+        assert!(!has_trait!(store.get(id).unwrap(), Pixel, "extra"));
+        history.redo(&mut store);
+        assert!(has_trait!(store.get(id).unwrap(), Pixel, "extra"));
+
+        // Edits are undone in the reverse of the order they were made.
+        let repaint = move |color| {
+            move |store: &mut ComponentStore| {
+                find_trait_mut!(store.get(id).unwrap(), Pixel)
+                    .unwrap()
+                    .paint(color)
+            }
+        };
+        history.command(&mut store, "Paint", |edits| {
+            find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                .unwrap()
+                .paint(5);
+            edits.operation(repaint(9), repaint(5));
+            find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                .unwrap()
+                .paint(12);
+        });
+        history.undo(&mut store);
+        assert_eq!(
+            find_trait!(store.get(id).unwrap(), Pixel).unwrap().color(),
+            1
+        );
+        history.redo(&mut store);
+        assert_eq!(
+            find_trait!(store.get(id).unwrap(), Pixel).unwrap().color(),
+            12
+        );
+
+        // Operations may remove components that the command has borrowed.
+        let removed = Rc::new(RefCell::new(None));
+        let saved = removed.clone();
+        history.command(&mut store, "Remove", |edits| {
+            find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                .unwrap()
+                .paint(20);
+            edits.operation(
+                move |store| *saved.borrow_mut() = store.remove(id),
+                move |store| {
+                    store.insert(removed.borrow_mut().take().unwrap());
+                },
+            );
+        });
+        assert!(store.is_empty());
+        history.undo(&mut store);
+        assert_eq!(
+            find_trait!(store.get(id).unwrap(), Pixel).unwrap().color(),
+            12
+        );
+    }
+
+    #[test]
+    fn panics() {
+        let mut component = Component::new("dot");
+        add_object!(component, Dot, Dot { color: 1 }, [Pixel]);
+        let id = component.id;
+        let mut store = ComponentStore::new();
+        store.insert(component);
+        let mut history = UndoHistory::new();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            history.command(&mut store, "Panic", |edits| {
+                find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                    .unwrap()
+                    .paint(5);
+                edits.spawn(Component::new("dot"));
+                panic!("oops");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(store.ids(), vec![id]);
+        assert_eq!(
+            find_trait!(store.get(id).unwrap(), Pixel).unwrap().color(),
+            1
+        );
+        assert_eq!(history.undo_name(), None);
+    }
+
+    #[test]
+    fn limit() {
+        let mut component = Component::new("dot");
+        add_object!(component, Dot, Dot { color: 1 }, [Pixel]);
+        let id = component.id;
+        let mut store = ComponentStore::new();
+        store.insert(component);
+
+        let mut history = UndoHistory::new();
+        history.command(&mut store, "Paint", |edits| {
+            find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                .unwrap()
+                .paint(0)
+        });
+        let size = history.size();
+        assert!(size > 0);
+
+        let mut history = UndoHistory::with_limit(2 * size);
+        for color in 1..5 {
+            history.command(&mut store, "Paint", |edits| {
+                find_trait_mut!(edits.get(id).unwrap(), Pixel)
+                    .unwrap()
+                    .paint(color)
+            });
+        }
+        assert_eq!(history.size(), 2 * size);
+        assert!(history.undo(&mut store).is_some());
+        assert!(history.undo(&mut store).is_some());
+        assert!(history.undo(&mut store).is_none());
+        assert_eq!(
+            find_trait!(store.get(id).unwrap(), Pixel).unwrap().color(),
+            2
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Views are immutable copy-on-write copies of components that other threads can read
+//! while the originals keep changing. Objects must be registered with the clone option.
+use super::*;
+use fnv::FnvHashMap;
+use std::any::Any;
+use std::iter;
+use std::ptr::{DynMetadata, Pointee};
+use std::sync::Arc;
+use type_erased_ptr::*;
+
+// A copy of an object, shared between views.
+struct ViewObject(Box<dyn Any>);
+
+// Views are only published for Shared components so the objects are Send + Sync.
+unsafe impl Send for ViewObject {}
+unsafe impl Sync for ViewObject {}
+
+/// An immutable copy of a [`Component`]. The [`find_trait`] and [`find_repeated_trait`]
+/// macros return plain references for views. Adapted traits are not available.
+///
+/// [`find_trait`]: crate::find_trait
+/// [`find_repeated_trait`]: crate::find_repeated_trait
+pub struct ComponentView {
+    pub id: ComponentId,
+    traits: FnvHashMap<(TypeId, InstanceKey), TypeErasedPointer>, // pointers into objects
+    repeated: FnvHashMap<TypeId, Vec<TypeErasedPointer>>,
+    objects: Vec<((ComponentId, ObjectId), Arc<ViewObject>)>, // what the pointers point to
+}
+
+impl ComponentView {
+    // Normally the [`has_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn has<Trait>(&self, trait_id: TypeId, key: &str) -> bool
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        InstanceKey::try_from_str(key).is_ok_and(|key| self.traits.contains_key(&(trait_id, key)))
+    }
+
+    // Normally the [`find_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find<Trait>(&self, trait_id: TypeId, key: &str) -> Option<&Trait>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        let key = InstanceKey::try_from_str(key).ok()?;
+        self.traits
+            .get(&(trait_id, key))
+            .map(|erased| unsafe { &*erased.typed::<Trait>() })
+    }
+
+    // Normally the [`find_repeated_trait`]` macro would be used instead of calling this directly.
+    #[doc(hidden)]
+    pub fn find_repeated<Trait>(&self, trait_id: TypeId) -> impl Iterator<Item = &Trait>
+    where
+        Trait: ?Sized + Pointee<Metadata = DynMetadata<Trait>> + 'static,
+    {
+        self.repeated
+            .get(&trait_id)
+            .into_iter()
+            .flatten()
+            .map(|erased| unsafe { &*erased.typed::<Trait>() })
+    }
+}
+
+/// An immutable copy of a [`ComponentStore`].
+pub struct StoreView {
+    components: FnvHashMap<ComponentId, Arc<ComponentView>>,
+}
+
+impl StoreView {
+    pub fn get(&self, id: ComponentId) -> Option<&ComponentView> {
+        self.components.get(&id).map(|view| &**view)
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.components.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Returns the ids of all the components in sorted order.
+    pub fn ids(&self) -> Vec<ComponentId> {
+        let mut ids: Vec<_> = self.components.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Iterates over the components in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentView> {
+        self.components.values().map(|view| &**view)
+    }
+}
+
+/// Publishes views of components and stores. Objects that haven't changed since the
+/// last publish (see [`change_tick`]) are shared with the earlier views.
+///
+/// # Examples
+///
+/// ```
+/// use gear_objects::*;
+/// use paste::paste;
+/// use std::thread;
+///
+/// trait Position {
+///     fn position(&self) -> i32;
+///     fn advance(&mut self);
+/// }
+/// register_type!(Position);
+///
+/// #[derive(Clone)]
+/// struct Ship {
+///     x: i32,
+/// }
+/// register_type!(Ship, clone);
+///
+/// impl Position for Ship {
+///     fn position(&self) -> i32 {
+///         self.x
+///     }
+///
+///     fn advance(&mut self) {
+///         self.x += 1;
+///     }
+/// }
+///
+/// let mut component = Component::new("ship");
+/// add_object!(component, Ship, Ship { x: 0 }, [Position]);
+///
+/// let mut publisher = ViewPublisher::new();
+/// let view = publisher.publish_component(&component).unwrap();
+/// let reader = thread::spawn(move || find_trait!(view, Position).unwrap().position());
+///
+/// // The writer can keep going, even while the reader is looking at the view.
+/// let mut position = find_trait_mut!(component, Position).unwrap();
+/// position.advance();
+/// assert_eq!(position.position(), 1);
+/// assert_eq!(reader.join().unwrap(), 0);
+/// ```
+pub struct ViewPublisher {
+    objects: FnvHashMap<(ComponentId, ObjectId), Arc<ViewObject>>, // copies in the last views
+    views: FnvHashMap<ComponentId, Arc<ComponentView>>,            // the last views
+    published: Option<u32>, // change tick of the last publish
+}
+
+impl ViewPublisher {
+    pub fn new() -> ViewPublisher {
+        ViewPublisher {
+            objects: FnvHashMap::default(),
+            views: FnvHashMap::default(),
+            published: None,
+        }
+    }
+
+    /// Returns a view of every component within store. Fails if an object wasn't
+    /// registered with the clone option or is mutably borrowed.
+    pub fn publish(&mut self, store: &ComponentStore) -> Result<Arc<StoreView>, ComponentError> {
+        let tick = change_tick();
+        let mut objects = FnvHashMap::default();
+        let mut views = FnvHashMap::default();
+        for component in store.iter() {
+            let view = self.view(component, &mut objects)?;
+            views.insert(component.id, view);
+        }
+        self.published = Some(tick);
+        self.objects = objects;
+        self.views = views.clone();
+        Ok(Arc::new(StoreView { components: views }))
+    }
+
+    /// Returns a view of a single component. Fails if an object wasn't registered with
+    /// the clone option or is mutably borrowed.
+    pub fn publish_component(
+        &mut self,
+        component: &Component,
+    ) -> Result<Arc<ComponentView>, ComponentError> {
+        let tick = change_tick();
+        let mut objects = FnvHashMap::default();
+        let view = self.view(component, &mut objects)?;
+        self.published = Some(tick);
+        self.objects = objects;
+        self.views = iter::once((component.id, view.clone())).collect();
+        Ok(view)
+    }
+
+    fn view(
+        &self,
+        component: &Component,
+        objects: &mut FnvHashMap<(ComponentId, ObjectId), Arc<ViewObject>>,
+    ) -> Result<Arc<ComponentView>, ComponentError> {
+        let published = self.published;
+        let unchanged = |tick: u32| published.is_some_and(|published| tick < published);
+        let chain = || iter::successors(Some(component), |c| c.prototype().map(|p| &**p));
+
+        // If nothing changed the old view can be used as is.
+        if let Some(view) = self.views.get(&component.id) {
+            if chain().all(|c| published.is_some_and(|tick| !c.changed_since(tick))) {
+                objects.extend(view.objects.iter().cloned());
+                return Ok(view.clone());
+            }
+        }
+
+        let mut kept = Vec::new();
+        let (traits, repeated) = component.copy_traits(|id, obj_id, changed, object| {
+            let key = (id, obj_id);
+            let copy = match self.objects.get(&key) {
+                Some(copy) if unchanged(changed) => copy.clone(),
+                _ => {
+                    let clone = type_info(obj_id.type_id)
+                        .and_then(|info| info.clone)
+                        .ok_or(ComponentError::NotClonable(obj_id))?
+                        .0;
+                    Arc::new(ViewObject(clone(object)))
+                }
+            };
+            let pointer = &*copy.0 as *const dyn Any as *mut ();
+            objects.insert(key, copy.clone());
+            kept.push((key, copy));
+            Ok(pointer)
+        })?;
+        Ok(Arc::new(ComponentView {
+            id: component.id,
+            traits,
+            repeated,
+            objects: kept,
+        }))
+    }
+}
+
+impl Default for ViewPublisher {
+    fn default() -> Self {
+        ViewPublisher::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paste::paste;
+    use std::thread;
+
+    trait Glyph {
+        fn glyph(&self) -> char;
+        fn set_glyph(&mut self, glyph: char);
+    }
+    register_type!(Glyph);
+
+    #[derive(Clone)]
+    struct Tile {
+        glyph: char,
+    }
+    register_type!(Tile, clone);
+
+    impl Glyph for Tile {
+        fn glyph(&self) -> char {
+            self.glyph
+        }
+
+        fn set_glyph(&mut self, glyph: char) {
+            self.glyph = glyph;
+        }
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let mut first = Component::new("tile");
+        add_object!(first, Tile, Tile { glyph: 'a' }, [Glyph]);
+        let mut second = Component::new("tile");
+        add_object!(second, Tile, Tile { glyph: 'b' }, [Glyph]);
+        let (first_id, second_id) = (first.id, second.id);
+        let mut store = ComponentStore::new();
+        store.insert(first);
+        store.insert(second);
+
+        let mut publisher = ViewPublisher::new();
+        advance_change_tick();
+        let old = publisher.publish(&store).unwrap();
+
+        find_trait_mut!(store.get(first_id).unwrap(), Glyph)
+            .unwrap()
+            .set_glyph('x');
+        advance_change_tick();
+        let new = publisher.publish(&store).unwrap();
+        assert_eq!(
+            find_trait!(old.get(first_id).unwrap(), Glyph)
+                .unwrap()
+                .glyph(),
+            'a'
+        );
+        assert_eq!(
+            find_trait!(new.get(first_id).unwrap(), Glyph)
+                .unwrap()
+                .glyph(),
+            'x'
+        );
+
+        // Only the changed component was copied.
+        assert!(!Arc::ptr_eq(
+            &old.components[&first_id],
+            &new.components[&first_id]
+        ));
+        assert!(Arc::ptr_eq(
+            &old.components[&second_id],
+            &new.components[&second_id]
+        ));
+
+        // Readers on other threads see a consistent view while the writer mutates.
+        let reader = thread::spawn(move || {
+            find_trait!(new.get(first_id).unwrap(), Glyph)
+                .unwrap()
+                .glyph()
+        });
+        find_trait_mut!(store.get(first_id).unwrap(), Glyph)
+            .unwrap()
+            .set_glyph('z');
+        assert_eq!(reader.join().unwrap(), 'x');
+    }
+
+    #[test]
+    fn borrowed() {
+        let mut component = Component::new("tile");
+        add_object!(component, Tile, Tile { glyph: 'a' }, [Glyph]);
+        let mut publisher = ViewPublisher::new();
+        let glyph = find_trait_mut!(component, Glyph).unwrap();
+        let err = publisher.publish_component(&component).err().unwrap();
+        assert!(matches!(err, ComponentError::Borrowed(_)));
+
+        drop(glyph);
+        let view = publisher.publish_component(&component).unwrap();
+        assert_eq!(find_trait!(view, Glyph).unwrap().glyph(), 'a');
+    }
+
+    #[test]
+    fn prototypes() {
+        let mut base = Component::new("base");
+        add_keyed_object!(base, Tile, "floor", Tile { glyph: '.' }, [Glyph]);
+        add_keyed_object!(base, Tile, "wall", Tile { glyph: '#' }, [Glyph]);
+        let mut component = Component::new("tile");
+        add_object!(component, Tile, Tile { glyph: '@' }, [Glyph]);
+        component.set_prototype(Arc::new(base));
+
+        let mut publisher = ViewPublisher::new();
+        let view = publisher.publish_component(&component).unwrap();
+        assert_eq!(find_trait!(view, Glyph).unwrap().glyph(), '@');
+        assert_eq!(find_trait!(view, Glyph, "floor").unwrap().glyph(), '.');
+        assert!(has_trait!(view, Glyph, "wall"));
+
+        component.mask_trait(get_glyph_id());
+        let view = publisher.publish_component(&component).unwrap();
+        assert!(find_trait!(view, Glyph).is_none());
+
+        struct Opaque;
+        register_type!(Opaque);
+        component.add_object(ObjectId::new(get_opaque_id(), ""), Opaque);
+        let err = publisher.publish_component(&component).err().unwrap();
+        assert!(matches!(err, ComponentError::NotClonable(_)));
+    }
+}